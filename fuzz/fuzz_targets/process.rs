@@ -0,0 +1,16 @@
+//! Run `cargo fuzz run process` from the top of the repository. The options
+//! and the input are both read from the fuzzer's bytes.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tabulate::generate::{Gen, Generate};
+use tabulate::Options;
+
+fuzz_target!(|data: &[u8]| {
+    let mut g = Gen::from_bytes(data);
+    let opts = Options::generate(&mut g);
+    let input = g.input(5);
+    let _ = tabulate::process(&input[..], std::io::sink(), &opts);
+    let _ = tabulate::process_threaded(&input[..], std::io::sink(), &opts);
+});
@@ -0,0 +1,109 @@
+//! Replacing files atomically, for `--output` and `--in-place`.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// A file that is written next to its final path and only renamed into place
+/// by `commit`, so readers never see a partly written file and a failed run
+/// leaves the old file alone. The temporary file is removed if the
+/// `AtomicFile` is dropped without being committed.
+pub struct AtomicFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: Option<BufWriter<File>>,
+}
+
+impl AtomicFile {
+    /// Starts writing a file that will replace `path`. The temporary file is
+    /// created in the same directory, so the rename cannot cross file systems.
+    /// If `path` exists, the new file gets its permissions.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<AtomicFile> {
+        let path = path.as_ref().to_path_buf();
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("not a file: {}", path.display())))?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".tabulate-{}", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+
+        let file = File::create(&temp_path)?;
+        let mut atomic = AtomicFile {
+            path,
+            temp_path,
+            file: None,
+        };
+        if let Ok(metadata) = fs::metadata(&atomic.path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        atomic.file = Some(BufWriter::new(file));
+        Ok(atomic)
+    }
+
+    /// The path the file will be renamed to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flushes everything written to disk and renames the file into place.
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            let file = file.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+        }
+        fs::rename(&self.temp_path, &self.path)
+    }
+
+    fn file(&mut self) -> &mut BufWriter<File> {
+        self.file.as_mut().expect("the file is only taken by commit")
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Close the file first; some systems cannot remove open files. After
+        // a commit there is nothing left to remove, and nothing can be done
+        // about a temporary file that cannot be removed.
+        self.file.take();
+        let _ = fs::remove_file(&self.temp_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_only_on_commit() {
+        let dir = std::env::temp_dir().join(format!("tabulate-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("table.txt");
+        fs::write(&path, "old\n").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+        drop(file);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"new\n").unwrap();
+        file.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
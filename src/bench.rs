@@ -0,0 +1,146 @@
+//! Generated input of a given shape, for measuring the cost of options with
+//! `--bench` and for `--generate`.
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::errors::*;
+
+/// The shape of generated input: the number of rows and columns, and the
+/// range of the widths of the values, which are spread evenly over it. The
+/// values are random, but the same for the same seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shape {
+    pub rows: usize,
+    pub columns: usize,
+    pub min_width: usize,
+    pub max_width: usize,
+    pub seed: u64,
+}
+
+/// Parses `ROWS,COLS` or `ROWS,COLS,MIN-MAX`. The widths default to 1-12.
+impl FromStr for Shape {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Shape> {
+        let err = || Error::ShapeParseError { s: s.to_string() };
+        let parts: Vec<&str> = s.split(',').collect();
+        let (rows, columns, widths) = match parts[..] {
+            [rows, columns] => (rows, columns, "1-12"),
+            [rows, columns, widths] => (rows, columns, widths),
+            _ => return Err(err()),
+        };
+        let (min_width, max_width) = widths.split_once('-').ok_or_else(err)?;
+        let shape = Shape {
+            rows: rows.parse().map_err(|_| err())?,
+            columns: columns.parse().map_err(|_| err())?,
+            min_width: min_width.parse().map_err(|_| err())?,
+            max_width: max_width.parse().map_err(|_| err())?,
+            seed: 0,
+        };
+        if shape.columns == 0 || shape.min_width == 0 || shape.min_width > shape.max_width {
+            return Err(err());
+        }
+        Ok(shape)
+    }
+}
+
+/// Parses `ROWS,COLS` or `ROWS,COLS,SEED`, the shape of `--generate`. The
+/// widths are 1-12 and the seed defaults to 0.
+pub fn parse_table_shape(s: &str) -> Result<Shape> {
+    let err = || Error::ShapeParseError { s: s.to_string() };
+    let (dims, seed) = match s.matches(',').count() {
+        1 => (s, 0),
+        2 => {
+            let (dims, seed) = s.rsplit_once(',').ok_or_else(err)?;
+            (dims, seed.parse().map_err(|_| err())?)
+        }
+        _ => return Err(err()),
+    };
+    let shape: Shape = dims.parse().map_err(|_| err())?;
+    Ok(Shape { seed, ..shape })
+}
+
+impl Shape {
+    /// Generates the lines of the input. Every other column holds numbers,
+    /// the rest words.
+    pub fn generate(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out).expect("writing to a Vec cannot fail");
+        out
+    }
+
+    /// Writes the lines that `generate` returns to `out`, one at a time.
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut rng = Rng::new(self.seed);
+        let mut line = Vec::new();
+        for _ in 0..self.rows {
+            line.clear();
+            for col in 0..self.columns {
+                if col > 0 {
+                    line.push(b' ');
+                }
+                let width = self.min_width + rng.below(self.max_width - self.min_width + 1);
+                let (first, len) = if col % 2 == 1 { (b'0', 10) } else { (b'a', 26) };
+                line.extend((0..width).map(|_| first + rng.below(len) as u8));
+            }
+            line.push(b'\n');
+            out.write_all(&line)?;
+        }
+        out.flush()
+    }
+}
+
+/// A xorshift generator, which is plenty for made up values.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // Xorshift gets stuck at 0, and similar seeds should not give
+        // similar values.
+        Rng((seed ^ 0x2545_f491_4f6c_dd1d).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_shape() {
+        let shape: Shape = "3,4,2-5".parse().unwrap();
+        let input = shape.generate();
+        let lines: Vec<&[u8]> = input.split(|&b| b == b'\n').filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.len(), 3);
+        for line in lines {
+            let cells: Vec<&[u8]> = line.split(|&b| b == b' ').collect();
+            assert_eq!(cells.len(), 4);
+            assert!(cells.iter().all(|c| (2..=5).contains(&c.len())));
+            assert!(cells[1].iter().all(u8::is_ascii_digit));
+        }
+        assert_eq!(shape.generate(), input);
+
+        assert_eq!("10,2".parse::<Shape>().unwrap().max_width, 12);
+        assert!("10".parse::<Shape>().is_err());
+        assert!("10,0".parse::<Shape>().is_err());
+        assert!("10,2,5-3".parse::<Shape>().is_err());
+    }
+
+    #[test]
+    fn table_shape_seed() {
+        let shape = parse_table_shape("5,3,42").unwrap();
+        assert_eq!((shape.rows, shape.columns, shape.seed), (5, 3, 42));
+        assert_eq!(parse_table_shape("5,3").unwrap().seed, 0);
+        assert_eq!(shape.generate(), parse_table_shape("5,3,42").unwrap().generate());
+        assert_ne!(shape.generate(), parse_table_shape("5,3,43").unwrap().generate());
+        assert!(parse_table_shape("5,3,x").is_err());
+        assert!(parse_table_shape("5,3,1-4").is_err());
+    }
+}
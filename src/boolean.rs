@@ -0,0 +1,89 @@
+//! Rewriting truthy and falsy values to one short form, for `--booleans`.
+
+use std::str::FromStr;
+
+use crate::errors::*;
+use crate::parser::Row;
+use crate::range::RangeMap;
+
+const TRUTHY: &[&str] = &["true", "t", "yes", "y", "on", "1"];
+const FALSY: &[&str] = &["false", "f", "no", "n", "off", "0"];
+
+/// The values that truthy and falsy cells of a column are rewritten to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Booleans {
+    truthy: Vec<u8>,
+    falsy: Vec<u8>,
+}
+
+impl Booleans {
+    pub fn new<T: Into<Vec<u8>>, F: Into<Vec<u8>>>(truthy: T, falsy: F) -> Booleans {
+        Booleans {
+            truthy: truthy.into(),
+            falsy: falsy.into(),
+        }
+    }
+
+    /// The value `cell` is rewritten to, if it is a truthy or falsy value such
+    /// as `true`, `Yes`, `1`, `f` or `off`.
+    pub fn rewrite(&self, cell: &[u8]) -> Option<&[u8]> {
+        let s = std::str::from_utf8(cell).ok()?.trim();
+        if TRUTHY.iter().any(|t| t.eq_ignore_ascii_case(s)) {
+            Some(&self.truthy)
+        } else if FALSY.iter().any(|f| f.eq_ignore_ascii_case(s)) {
+            Some(&self.falsy)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses `TRUE/FALSE`, such as `yes/no` or `✓/✗`.
+impl FromStr for Booleans {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Booleans> {
+        match s.split_once('/') {
+            Some((t, f)) if !t.is_empty() && !f.is_empty() && !f.contains('/') => Ok(Booleans::new(t, f)),
+            _ => Err(Error::BooleansParseError { s: s.to_string() }),
+        }
+    }
+}
+
+/// Rewrites the truthy and falsy cells of the columns in `booleans`.
+pub(crate) fn apply_booleans(row: &mut Row, booleans: &RangeMap<Booleans>) {
+    if booleans.0.is_empty() {
+        return;
+    }
+    let mut line = Vec::with_capacity(row.as_line().len());
+    let mut spans = Vec::with_capacity(row.len());
+    for (i, cell) in row.get_parts().enumerate() {
+        let start = line.len();
+        match booleans.get(i as u32 + 1).and_then(|b| b.rewrite(cell)) {
+            Some(value) => line.extend_from_slice(value),
+            None => line.extend_from_slice(cell),
+        }
+        spans.push((start, line.len()));
+    }
+    *row = Row::from_spans(line, spans);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::RowParser;
+    use crate::range::Ranges;
+
+    #[test]
+    fn rewrite_booleans() {
+        let booleans = RangeMap(vec![(Ranges(vec!["2-".parse().unwrap()]), "✓/✗".parse().unwrap())]);
+        let mut row = Row::new();
+        RowParser::new(" ", false).parse_into(&mut row, "true True no 1 F maybe");
+        apply_booleans(&mut row, &booleans);
+        let cells: Vec<&str> = row.get_parts().map(|c| std::str::from_utf8(c).unwrap()).collect();
+        assert_eq!(cells, ["true", "✓", "✗", "✓", "✗", "maybe"]);
+
+        assert!("yes".parse::<Booleans>().is_err());
+        assert!("yes/".parse::<Booleans>().is_err());
+        assert!("a/b/c".parse::<Booleans>().is_err());
+    }
+}
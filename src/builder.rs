@@ -0,0 +1,174 @@
+//! A builder for `Options`, so library users do not have to spell out every
+//! field.
+
+
+use crate::boolean::Booleans;
+use crate::color::{Style, Threshold};
+use crate::compute::Computation;
+use crate::layout::Layout;
+use crate::parser::ColumnDelimiter;
+use crate::pattern::{ColumnPattern, Extraction, Regex, Validation};
+use crate::project::Projection;
+use crate::range::{RangeMap, Ranges};
+use crate::rename::Rename;
+use crate::sort::SortKey;
+use crate::spark::Spark;
+use crate::{Alignment, Options, SizingStrategy};
+
+/// Builder for `Options`, starting from the defaults.
+///
+/// ```
+/// use tabulate::prelude::*;
+///
+/// let opts = Options::builder().lines(10).delimiters(",").strict(true).build();
+/// assert_eq!(opts.lines, 10);
+/// ```
+#[derive(Debug, Default)]
+pub struct OptionsBuilder {
+    opts: Options,
+}
+
+macro_rules! setters {
+    ($($name:ident: $ty:ty,)*) => {
+        $(
+            #[doc = concat!("Sets `Options::", stringify!($name), "`.")]
+            pub fn $name(mut self, value: $ty) -> OptionsBuilder {
+                self.opts.$name = value;
+                self
+            }
+        )*
+    };
+}
+
+impl OptionsBuilder {
+    pub fn new() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
+    /// The characters that separate the columns of the input.
+    pub fn delimiters<S: Into<Vec<u8>>>(mut self, delim: S) -> OptionsBuilder {
+        self.opts.delim = delim.into();
+        self
+    }
+
+    /// Parse columns as strictly being delimited by a single delimiter.
+    pub fn strict(mut self, strict_delim: bool) -> OptionsBuilder {
+        self.opts.strict_delim = strict_delim;
+        self
+    }
+
+    /// The delimiters that always separate two columns, while the others
+    /// collapse.
+    pub fn strict_delimiters<S: Into<Vec<u8>>>(mut self, delim: S) -> OptionsBuilder {
+        self.opts.strict_delims = delim.into();
+        self
+    }
+
+    /// The string printed between columns.
+    pub fn output_delimiter<S: Into<Vec<u8>>>(mut self, delim: S) -> OptionsBuilder {
+        self.opts.output_delim = delim.into();
+        self
+    }
+
+    /// The mark printed instead of a value that repeats the one above it.
+    pub fn ditto_mark<S: Into<Vec<u8>>>(mut self, mark: S) -> OptionsBuilder {
+        self.opts.ditto_mark = mark.into();
+        self
+    }
+
+    /// The mark printed after values that fail validation when colors are off.
+    pub fn invalid_mark<S: Into<Vec<u8>>>(mut self, mark: S) -> OptionsBuilder {
+        self.opts.invalid_mark = mark.into();
+        self
+    }
+
+    setters! {
+        truncate: Option<Ranges>,
+        ratio: f64,
+        truncate_ratio: Option<f64>,
+        sizing_strategy: SizingStrategy,
+        bucket_widths: bool,
+        ignore_outliers: f64,
+        lines: usize,
+        estimate_mem: Option<usize>,
+        include_cols: Option<Ranges>,
+        exclude_cols: Ranges,
+        include_names: Vec<Vec<u8>>,
+        exclude_names: Vec<Vec<u8>>,
+        print_info: bool,
+        explain_sizing: bool,
+        info_cols: Option<Ranges>,
+        info_names: Vec<Vec<u8>>,
+        online: bool,
+        find: Option<ColumnPattern>,
+        validate: Vec<Validation>,
+        checksum: bool,
+        footer_info: bool,
+        page_length: Option<usize>,
+        page_numbers: bool,
+        quiet: bool,
+        color: bool,
+        fit: bool,
+        auto_hide: bool,
+        width: Option<usize>,
+        pin: Option<Ranges>,
+        right_edge: Option<Ranges>,
+        weights: RangeMap<f64>,
+        collapse_repeats: Option<Ranges>,
+        group_sep: Option<u32>,
+        group_rule: Option<char>,
+        follow: bool,
+        tail: Option<usize>,
+        window: Option<usize>,
+        header: bool,
+        header_floor: bool,
+        underline_header: Option<char>,
+        header_style: Option<Style>,
+        header_align: Option<Alignment>,
+        rename: Vec<Rename>,
+        hide_header: bool,
+        events: bool,
+        soft_wrap: Option<Vec<u8>>,
+        no_trailing_space: bool,
+        border: bool,
+        check_columns: bool,
+        warn_estimate: bool,
+        project: Option<Projection>,
+        compute: Vec<Computation>,
+        extract: Vec<Extraction>,
+        percent: Option<u32>,
+        spark: Option<Spark>,
+        thresholds: Vec<Threshold>,
+        precision: RangeMap<usize>,
+        null_values: Vec<Vec<u8>>,
+        empty_value: Option<Vec<u8>>,
+        booleans: RangeMap<Booleans>,
+        align: RangeMap<Alignment>,
+        left_align_numbers: bool,
+        align_numbers: bool,
+        protect_numbers: bool,
+        byte_widths: bool,
+        ignore_ansi: bool,
+        pass_ansi_lines: bool,
+        line_ranges: Option<Ranges>,
+        auto_detect_table: bool,
+        drop_other_lines: bool,
+        sort: Vec<SortKey>,
+        layout: Option<Layout>,
+        measure_only: bool,
+        every: usize,
+        max_columns: Option<usize>,
+        preview: Option<usize>,
+        drop_constant: bool,
+        show_dropped: bool,
+        drop_empty: bool,
+        exclude_matching: Option<Regex>,
+        continuation: Option<Regex>,
+        join_continuations: bool,
+        column_delims: Vec<ColumnDelimiter>,
+    }
+
+    pub fn build(self) -> Options {
+        self.opts
+    }
+}
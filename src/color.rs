@@ -0,0 +1,218 @@
+//! ANSI terminal styling of output cells.
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::errors::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl Color {
+    fn code(self) -> u8 {
+        30 + self as u8
+    }
+}
+
+impl FromStr for Color {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Color> {
+        use self::Color::*;
+        Ok(match s {
+            "black" => Black,
+            "red" => Red,
+            "green" => Green,
+            "yellow" => Yellow,
+            "blue" => Blue,
+            "magenta" => Magenta,
+            "cyan" => Cyan,
+            "white" => White,
+            _ => return Err(Error::InvalidStyle { s: s.to_string() }),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bold: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    pub fn fg(color: Color) -> Style {
+        Style {
+            fg: Some(color),
+            ..Style::default()
+        }
+    }
+
+    pub fn bold(self) -> Style {
+        Style { bold: true, ..self }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    pub fn write_prefix<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.is_plain() {
+            return Ok(());
+        }
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push(1);
+        }
+        if self.underline {
+            codes.push(4);
+        }
+        if self.reverse {
+            codes.push(7);
+        }
+        if let Some(fg) = self.fg {
+            codes.push(fg.code());
+        }
+        let codes: Vec<String> = codes.iter().map(|c| c.to_string()).collect();
+        write!(out, "\x1b[{}m", codes.join(";"))
+    }
+
+    pub fn write_suffix<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.is_plain() {
+            return Ok(());
+        }
+        out.write_all(b"\x1b[0m")
+    }
+}
+
+/// Parses styles such as `red`, `bold` or `bold+yellow`.
+impl FromStr for Style {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Style> {
+        let mut style = Style::default();
+        for part in s.split('+') {
+            match part {
+                "bold" => style.bold = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                _ => style.fg = Some(part.parse()?),
+            }
+        }
+        Ok(style)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn matches(self, a: f64, b: f64) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Equal => a == b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Greater => a > b,
+        }
+    }
+}
+
+/// Styles for the numeric cells of a column depending on their value, such as
+/// `3:>100=red,>50=yellow`. The first rule that matches a value is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    pub column: u32,
+    rules: Vec<(Comparison, f64, Style)>,
+}
+
+impl Threshold {
+    /// The style of `cell`, if it is a number that matches one of the rules.
+    pub fn style(&self, cell: &[u8]) -> Option<&Style> {
+        let value: f64 = std::str::from_utf8(cell).ok()?.trim().parse().ok()?;
+        self.rules
+            .iter()
+            .find(|&&(cmp, limit, _)| cmp.matches(value, limit))
+            .map(|(_, _, style)| style)
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Threshold> {
+        let err = || Error::ThresholdParseError { s: s.to_string() };
+        let (column, rules) = s.split_once(':').ok_or_else(err)?;
+        let column: u32 = column.trim().parse().map_err(|_| err())?;
+        if column == 0 {
+            return Err(Error::ColumnsStartAtOne);
+        }
+        let rules = rules
+            .split(',')
+            .map(|rule| {
+                let rule = rule.trim();
+                let (cmp, rest) = [
+                    (">=", Comparison::GreaterOrEqual),
+                    ("<=", Comparison::LessOrEqual),
+                    (">", Comparison::Greater),
+                    ("<", Comparison::Less),
+                    ("=", Comparison::Equal),
+                ]
+                .iter()
+                .find_map(|&(op, cmp)| rule.strip_prefix(op).map(|rest| (cmp, rest)))
+                .ok_or_else(err)?;
+                let (limit, style) = rest.split_once('=').ok_or_else(err)?;
+                let limit: f64 = limit.trim().parse().map_err(|_| err())?;
+                Ok((cmp, limit, style.trim().parse()?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Threshold { column, rules })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_write_style() {
+        let style: Style = "bold+red".parse().unwrap();
+        assert_eq!(style, Style::fg(Color::Red).bold());
+
+        let mut out = Vec::new();
+        style.write_prefix(&mut out).unwrap();
+        out.extend_from_slice(b"x");
+        style.write_suffix(&mut out).unwrap();
+        assert_eq!(&out[..], b"\x1b[1;31mx\x1b[0m");
+
+        assert!("purple".parse::<Style>().is_err());
+    }
+
+    #[test]
+    fn threshold() {
+        let t: Threshold = "2:>100=red,>=50=bold+yellow".parse().unwrap();
+        assert_eq!(t.column, 2);
+        assert_eq!(t.style(b"150"), Some(&Style::fg(Color::Red)));
+        assert_eq!(t.style(b" 50"), Some(&Style::fg(Color::Yellow).bold()));
+        assert_eq!(t.style(b"10"), None);
+        assert_eq!(t.style(b"n/a"), None);
+
+        assert!("2".parse::<Threshold>().is_err());
+        assert!("2:100=red".parse::<Threshold>().is_err());
+        assert!("2:>x=red".parse::<Threshold>().is_err());
+        assert!("2:>1=purple".parse::<Threshold>().is_err());
+    }
+}
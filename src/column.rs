@@ -1,27 +1,196 @@
 use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::color::Style;
+use crate::errors::*;
+use crate::numeric::{self, NumberWidth};
+use crate::pattern::Regex;
+use crate::utils::trim_end;
+use crate::width::Widths;
 
 #[derive(Debug, Clone)]
 struct Options {
     excluded: bool,
     truncated: bool,
+    pinned: bool,
+    /// Kept at full size against the right edge when fitting to a width.
+    right_edge: bool,
+    /// Relative importance of the column when it has to be shrunk.
+    weight: f64,
+    /// Never print more than `size` characters, not even in the last column.
+    clipped: bool,
+    /// Right-align numeric columns on the decimal point.
+    align_numbers: bool,
+    /// Never truncate the column if it holds numbers, not even to fit it.
+    protect_numbers: bool,
+    /// Excluded because it did not fit in the output width.
+    hidden: bool,
+    /// Kept at its size, as set by `lock`.
+    locked: bool,
+    /// How values are measured.
+    widths: Widths,
+    /// Where values are placed in the column, or `None` to right-align
+    /// numbers and left-align everything else.
+    alignment: Option<Alignment>,
+}
+
+impl Options {
+    fn width(&self, s: &[u8]) -> usize {
+        self.widths.width(s)
+    }
 }
 
 #[derive(Debug, Clone)]
 struct ExtraInfo {
-    min_value: Option<String>,
-    max_value: Option<String>,
+    min_value: Option<Vec<u8>>,
+    max_value: Option<Vec<u8>>,
+}
+
+/// With `SizingStrategy::Auto`, columns whose values differ in width by less
+/// than this are sized by evaluating every size.
+const EXHAUSTIVE_SIZES: usize = 256;
+
+/// With bucketing, widths below this are still counted exactly.
+const EXACT_WIDTHS: usize = 64;
+
+/// With bucketing, every doubling of the width is split into this many
+/// buckets, so a bucket is at most about 6% of the widths in it.
+const BUCKETS_PER_DOUBLING: u32 = 16;
+
+/// The largest width in the bucket of `size`.
+fn bucket(size: usize) -> usize {
+    if size < EXACT_WIDTHS {
+        return size;
+    }
+    let log2 = usize::BITS - 1 - size.leading_zeros();
+    let step = 1 << (log2 - BUCKETS_PER_DOUBLING.trailing_zeros());
+    (size / step + 1) * step - 1
+}
+
+/// Adds `n` to the count of `key` in `counts`, which is sorted by key.
+fn add_count(counts: &mut Vec<(usize, usize)>, key: usize, n: usize) {
+    match counts.binary_search_by_key(&key, |t| t.0) {
+        Ok(i) => counts[i].1 += n,
+        Err(i) => counts.insert(i, (key, n)),
+    }
+}
+
+/// Subtracts one from the count of `key` in `counts`, which is sorted by key.
+fn remove_count(counts: &mut Vec<(usize, usize)>, key: usize) {
+    if let Ok(i) = counts.binary_search_by_key(&key, |t| t.0) {
+        counts[i].1 -= 1;
+        if counts[i].1 == 0 {
+            counts.remove(i);
+        }
+    }
+}
+
+/// How the sizes of a column are searched for the one with the lowest score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizingStrategy {
+    /// `Exhaustive` if there are few sizes to evaluate, else `FirstMinimum`.
+    Auto,
+    /// Evaluate sizes from the narrowest value up and stop at the first size
+    /// that scores no better than the one before it. Fast, but only finds the
+    /// best size if the score has a single minimum, which holds for the
+    /// current cost model but not for every cost model.
+    FirstMinimum,
+    /// Evaluate every size from the narrowest to the widest value.
+    Exhaustive,
+}
+
+/// Parses `auto`, `first-minimum` or `exhaustive`.
+impl FromStr for SizingStrategy {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<SizingStrategy> {
+        match s {
+            "auto" => Ok(SizingStrategy::Auto),
+            "first-minimum" => Ok(SizingStrategy::FirstMinimum),
+            "exhaustive" => Ok(SizingStrategy::Exhaustive),
+            _ => Err(Error::SizingStrategyParseError { s: s.to_string() }),
+        }
+    }
+}
+
+/// Where the values of a column are placed when they are narrower than it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+/// Parses `left`, `right` or `center`, or `l`, `r` or `c`.
+impl FromStr for Alignment {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Alignment> {
+        match s {
+            "left" | "l" => Ok(Alignment::Left),
+            "right" | "r" => Ok(Alignment::Right),
+            "center" | "c" => Ok(Alignment::Center),
+            _ => Err(Error::AlignmentParseError { s: s.to_string() }),
+        }
+    }
+}
+
+/// A size considered for a column, with the costs the size is chosen by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeCandidate {
+    pub size: usize,
+    /// The expected number of unused characters in a cell.
+    pub waste: f64,
+    /// The expected number of characters a cell is too wide by.
+    pub overflow: f64,
+    pub score: f64,
 }
 
 #[derive(Debug, Clone)]
 pub struct MeasureColumn {
     samples: Vec<(usize, usize)>,
+    /// Count the widths of the samples in logarithmic buckets instead of
+    /// exactly, which bounds the number of distinct widths kept.
+    bucketed: bool,
+    /// The width of the widest sample, which is not kept when bucketing.
+    widest: usize,
+    /// If set, empty samples are missing values that are printed this wide
+    /// and are left out of the column information.
+    missing_width: Option<usize>,
+    /// Whether no sample had a value.
+    all_empty: bool,
+    /// Samples can be removed again, for measuring a sliding window of rows.
+    /// This needs the counts below, which are only kept when it is set.
+    windowed: bool,
+    /// The number of non-empty samples that were not numbers, and the
+    /// number of samples by the widths of their integer and fraction parts.
+    non_numbers: usize,
+    integers: Vec<(usize, usize)>,
+    fractions: Vec<(usize, usize)>,
+    min_size: usize,
+    /// The widest parts of the numbers seen, or `None` if some non-empty
+    /// sample was not a number.
+    numbers: Option<NumberWidth>,
+    /// The first sample, and whether any later sample was different.
+    first_value: Option<Vec<u8>>,
+    varies: bool,
+    /// A pattern to match every sample against, and whether all of them
+    /// matched so far.
+    pattern: Option<Regex>,
+    all_match: bool,
+    /// The header of the column, if there is one.
+    name: Option<Vec<u8>>,
+    /// The percentage of the widest samples to ignore when sizing.
+    outliers: f64,
+    strategy: SizingStrategy,
     opts: Options,
     extra_info: Option<ExtraInfo>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Column {
     size: usize,
+    max_size: usize,
+    numbers: Option<NumberWidth>,
+    name: Option<Vec<u8>>,
     opts: Options,
     extra_info: Option<ExtraInfo>,
 }
@@ -39,9 +208,39 @@ impl MeasureColumn {
 
         MeasureColumn {
             samples: vec![],
+            bucketed: false,
+            widest: 0,
+            missing_width: None,
+            all_empty: true,
+            windowed: false,
+            non_numbers: 0,
+            integers: vec![],
+            fractions: vec![],
+            min_size: 0,
+            numbers: Some(NumberWidth {
+                integer: 0,
+                fraction: 0,
+            }),
+            first_value: None,
+            varies: false,
+            pattern: None,
+            all_match: true,
+            name: None,
+            outliers: 0.0,
+            strategy: SizingStrategy::Auto,
             opts: Options {
                 excluded: false,
                 truncated: false,
+                pinned: false,
+                right_edge: false,
+                weight: 1.0,
+                clipped: false,
+                align_numbers: false,
+                protect_numbers: false,
+                hidden: false,
+                locked: false,
+                widths: Widths::default(),
+                alignment: None,
             },
             extra_info: extra,
         }
@@ -55,82 +254,385 @@ impl MeasureColumn {
         self.opts.truncated = is_truncated;
     }
 
-    pub fn add_sample(&mut self, sample: &str) {
-        let size = sample.len();
-        match self.samples.binary_search_by_key(&size, |t| t.0) {
-            Ok(i) => self.samples[i].1 += 1,
-            Err(i) => self.samples.insert(i, (size, 1)),
+    pub fn set_pinned(&mut self, is_pinned: bool) {
+        self.opts.pinned = is_pinned;
+    }
+
+    pub fn set_right_edge(&mut self, is_right_edge: bool) {
+        self.opts.right_edge = is_right_edge;
+    }
+
+    pub fn set_weight(&mut self, weight: f64) {
+        self.opts.weight = weight;
+    }
+
+    pub fn set_align_numbers(&mut self, align_numbers: bool) {
+        self.opts.align_numbers = align_numbers;
+    }
+
+    pub fn set_protect_numbers(&mut self, protect_numbers: bool) {
+        self.opts.protect_numbers = protect_numbers;
+    }
+
+    pub fn set_widths(&mut self, widths: Widths) {
+        self.opts.widths = widths;
+    }
+
+    pub fn set_alignment(&mut self, alignment: Option<Alignment>) {
+        self.opts.alignment = alignment;
+    }
+
+    pub fn set_name(&mut self, name: &[u8]) {
+        self.name = Some(name.to_vec());
+    }
+
+    /// Ignore the widest `percent` percent of the samples when sizing the
+    /// column.
+    pub fn set_ignore_outliers(&mut self, percent: f64) {
+        self.outliers = percent;
+    }
+
+    /// Count the widths of the samples in buckets that are wider the wider
+    /// the values are. Must be set before any sample is added.
+    pub fn set_bucketed(&mut self, bucketed: bool) {
+        debug_assert!(self.samples.is_empty());
+        self.bucketed = bucketed;
+    }
+
+    /// Treat empty samples as missing values that are printed `width`
+    /// characters wide, e.g. by `--empty-value`, and leave them out of the
+    /// smallest and largest values.
+    pub fn set_missing_width(&mut self, width: usize) {
+        self.missing_width = Some(width);
+    }
+
+    /// Make it possible to remove samples with `remove_sample`. Must be set
+    /// before any sample is added.
+    pub fn set_windowed(&mut self, windowed: bool) {
+        debug_assert!(self.samples.is_empty());
+        self.windowed = windowed;
+    }
+
+    pub fn set_sizing_strategy(&mut self, strategy: SizingStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// The column is never made narrower than `size`.
+    pub fn set_min_size(&mut self, size: usize) {
+        self.min_size = size;
+    }
+
+    /// Like `set_min_size`, but never makes the column narrower than it
+    /// already has to be.
+    pub fn raise_min_size(&mut self, size: usize) {
+        self.min_size = self.min_size.max(size);
+    }
+
+    pub fn is_excluded(&self) -> bool {
+        self.opts.excluded
+    }
+
+    /// Whether values that do not fit are truncated, which numbers never are
+    /// if they are protected.
+    pub fn is_truncated(&self) -> bool {
+        self.opts.truncated && !(self.opts.protect_numbers && self.numbers.is_some_and(|w| w.integer > 0))
+    }
+
+    /// The value of every sample, if there were at least two samples and all
+    /// of them were the same.
+    pub fn constant_value(&self) -> Option<&[u8]> {
+        let n: usize = self.samples.iter().map(|p| p.1).sum();
+        match self.first_value {
+            Some(ref v) if n >= 2 && !self.varies => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Match every sample against `pattern`, for `all_match()`.
+    pub fn set_pattern(&mut self, pattern: Regex) {
+        self.pattern = Some(pattern);
+    }
+
+    /// Whether there is a pattern and it matched every sample, of which
+    /// there was at least one.
+    pub fn all_match(&self) -> bool {
+        self.pattern.is_some() && self.all_match && !self.samples.is_empty()
+    }
+
+    /// Whether every sample was empty.
+    pub fn is_empty(&self) -> bool {
+        self.all_empty
+    }
+
+    /// Adds the samples of `other`, e.g. measured from another part of the
+    /// input or from another input, as if they had been added to this column.
+    /// The options of this column are kept, and its name if it has one.
+    pub fn merge(&mut self, other: &MeasureColumn) {
+        match (&self.first_value, &other.first_value) {
+            (None, _) => {
+                self.first_value = other.first_value.clone();
+                self.varies = other.varies;
+            }
+            (Some(a), Some(b)) => self.varies |= other.varies || a != b,
+            (Some(_), None) => {}
+        }
+        self.widest = self.widest.max(other.widest);
+        self.all_empty &= other.all_empty;
+        self.all_match &= other.all_match;
+        self.min_size = self.min_size.max(other.min_size);
+        for &(size, count) in &other.samples {
+            let key = if self.bucketed { bucket(size) } else { size };
+            add_count(&mut self.samples, key, count);
+        }
+        if self.windowed && other.windowed {
+            self.non_numbers += other.non_numbers;
+            for &(width, count) in &other.integers {
+                add_count(&mut self.integers, width, count);
+            }
+            for &(width, count) in &other.fractions {
+                add_count(&mut self.fractions, width, count);
+            }
+        }
+        self.numbers = match (self.numbers, other.numbers) {
+            (Some(a), Some(b)) => Some(NumberWidth {
+                integer: a.integer.max(b.integer),
+                fraction: a.fraction.max(b.fraction),
+            }),
+            _ => None,
+        };
+        if self.name.is_none() {
+            self.name = other.name.clone();
+        }
+        let opts = &self.opts;
+        if let (Some(extra), Some(other)) = (self.extra_info.as_mut(), other.extra_info.as_ref()) {
+            if let Some(ref min) = other.min_value {
+                if extra.min_value.as_ref().is_none_or(|s| opts.width(min) < opts.width(s)) {
+                    extra.min_value = Some(min.clone());
+                }
+            }
+            if let Some(ref max) = other.max_value {
+                if extra.max_value.as_ref().is_none_or(|s| opts.width(max) > opts.width(s)) {
+                    extra.max_value = Some(max.clone());
+                }
+            }
         }
-        if let Some(ref mut extra) = self.extra_info {
+    }
+
+    pub fn add_sample(&mut self, sample: &[u8]) {
+        match self.first_value {
+            None => self.first_value = Some(sample.to_vec()),
+            Some(ref v) if !self.varies && v[..] != *sample => self.varies = true,
+            _ => {}
+        }
+        self.all_empty &= sample.is_empty();
+        if let (Some(pattern), true) = (self.pattern.as_ref(), self.all_match) {
+            self.all_match = pattern.is_match(sample);
+        }
+        let missing = sample.is_empty() && self.missing_width.is_some();
+        let size = self.sample_size(sample);
+        self.widest = self.widest.max(size);
+        add_count(&mut self.samples, if self.bucketed { bucket(size) } else { size }, 1);
+        if !sample.is_empty() && (self.numbers.is_some() || self.windowed) {
+            match numeric::number_width(sample) {
+                Some(w) => {
+                    if let Some(widest) = self.numbers.as_mut() {
+                        widest.integer = widest.integer.max(w.integer);
+                        widest.fraction = widest.fraction.max(w.fraction);
+                    }
+                    if self.windowed {
+                        add_count(&mut self.integers, w.integer, 1);
+                        add_count(&mut self.fractions, w.fraction, 1);
+                    }
+                }
+                None => {
+                    self.numbers = None;
+                    self.non_numbers += 1;
+                }
+            }
+        }
+        let opts = &self.opts;
+        if let (Some(ref mut extra), false) = (self.extra_info.as_mut(), missing) {
             if extra
                 .min_value
                 .as_ref()
-                .map(|s| size < s.len())
+                .map(|s| size < opts.width(s))
                 .unwrap_or(true)
             {
-                extra.min_value = Some(sample.to_string());
+                extra.min_value = Some(sample.to_vec());
             }
             if extra
                 .max_value
                 .as_ref()
-                .map(|s| size > s.len())
+                .map(|s| size > opts.width(s))
                 .unwrap_or(true)
             {
-                extra.max_value = Some(sample.to_string());
+                extra.max_value = Some(sample.to_vec());
             }
         }
     }
 
-    pub fn calculate_size(&self, ratio: f64) -> Column {
-        assert!(!self.samples.is_empty());
-
-        let best_size = if ratio == 0. {
-            // Optimization
-            self.samples.iter().map(|p| p.0).max().unwrap_or(0)
-        } else {
-            let n: usize = self.samples.iter().map(|p| p.1).sum();
-            let min = self.samples.iter().map(|p| p.0).min().unwrap();
-            let max = self.samples.iter().map(|p| p.0).max().unwrap();
-            let spread = (0.7 + 20.0 / (1 + max - min) as f64).powi(2);
-            let prob = self
-                .samples
-                .iter()
-                .map(|&(s, x)| (s, x as f64 / n as f64))
-                .collect::<Vec<_>>();
-
-            let mut best_score = ::std::f64::INFINITY;
-            let mut best_size = max;
-            for l in min..=max {
-                let waste: f64 = prob
-                    .iter()
-                    .take_while(|&&(s, _)| s < l)
-                    .map(|&(s, p)| p * l.saturating_sub(s) as f64)
-                    .sum();
-                let overflow: f64 = prob
-                    .iter()
-                    .skip_while(|&&(s, _)| s <= l)
-                    .map(|&(s, p)| p * s.saturating_sub(l) as f64)
-                    .sum();
-
-                let score = ratio * (1.0 + waste) + (1.0 + overflow).powi(2) * spread;
-
-                if score < best_score {
-                    best_score = score;
-                    best_size = l;
-                } else {
-                    break;
+    /// Removes a sample that was added before, for measuring a sliding window
+    /// of rows. The first value and the column information are not updated.
+    pub fn remove_sample(&mut self, sample: &[u8]) {
+        debug_assert!(self.windowed);
+        let size = self.sample_size(sample);
+        remove_count(&mut self.samples, if self.bucketed { bucket(size) } else { size });
+        self.widest = self.samples.last().map_or(0, |p| p.0);
+        if !sample.is_empty() {
+            match numeric::number_width(sample) {
+                Some(w) => {
+                    remove_count(&mut self.integers, w.integer);
+                    remove_count(&mut self.fractions, w.fraction);
                 }
+                None => self.non_numbers = self.non_numbers.saturating_sub(1),
             }
+            self.numbers = match self.non_numbers {
+                0 => Some(NumberWidth {
+                    integer: self.integers.last().map_or(0, |p| p.0),
+                    fraction: self.fractions.last().map_or(0, |p| p.0),
+                }),
+                _ => None,
+            };
+        }
+    }
 
-            best_size
+    /// The width `sample` is measured as.
+    fn sample_size(&self, sample: &[u8]) -> usize {
+        match self.missing_width {
+            Some(width) if sample.is_empty() => width,
+            _ => self.opts.width(sample),
+        }
+    }
+
+    /// The samples without the widest `outliers` percent of them.
+    fn sizing_samples(&self) -> Vec<(usize, usize)> {
+        let n: usize = self.samples.iter().map(|p| p.1).sum();
+        let mut drop = (n as f64 * self.outliers / 100.0).floor() as usize;
+        let mut samples = self.samples.clone();
+        while let Some(last) = samples.last_mut() {
+            if drop == 0 {
+                break;
+            }
+            if last.1 > drop {
+                last.1 -= drop;
+                break;
+            }
+            drop -= last.1;
+            samples.pop();
+        }
+        // The bucket of the widest sample may end past it.
+        if let Some(last) = samples.last_mut() {
+            last.0 = last.0.min(self.widest);
+        }
+        samples
+    }
+
+    /// The sizes evaluated when sizing the column with `ratio`, in the order
+    /// they were evaluated. Empty if the column is simply as wide as its widest
+    /// value, which is the case when `ratio` is 0.
+    pub fn size_candidates(&self, ratio: f64) -> Vec<SizeCandidate> {
+        let samples = self.sizing_samples();
+        let mut candidates = Vec::new();
+        // The samples are sorted by size.
+        let (min, max) = match (samples.first(), samples.last()) {
+            (Some(&(min, _)), Some(&(max, _))) if ratio != 0. => (min, max),
+            _ => return candidates,
         };
 
+        let n: usize = samples.iter().map(|p| p.1).sum();
+        let spread = (0.7 + 20.0 / (1 + max - min) as f64).powi(2);
+        let prob = samples
+            .iter()
+            .map(|&(s, x)| (s, x as f64 / n as f64))
+            .collect::<Vec<_>>();
+
+        let exhaustive = match self.strategy {
+            SizingStrategy::Auto => max - min < EXHAUSTIVE_SIZES,
+            SizingStrategy::FirstMinimum => false,
+            SizingStrategy::Exhaustive => true,
+        };
+        let mut best_score = f64::INFINITY;
+        for l in min..=max {
+            let waste: f64 = prob
+                .iter()
+                .take_while(|&&(s, _)| s < l)
+                .map(|&(s, p)| p * l.saturating_sub(s) as f64)
+                .sum();
+            let overflow: f64 = prob
+                .iter()
+                .skip_while(|&&(s, _)| s <= l)
+                .map(|&(s, p)| p * s.saturating_sub(l) as f64)
+                .sum();
+
+            let score = ratio * (1.0 + waste) + (1.0 + overflow).powi(2) * spread;
+            candidates.push(SizeCandidate {
+                size: l,
+                waste,
+                overflow,
+                score,
+            });
+
+            if score < best_score {
+                best_score = score;
+            } else if !exhaustive {
+                break;
+            }
+        }
+        candidates
+    }
+
+    pub fn calculate_size(&self, ratio: f64) -> Column {
+        let (size, max_size, numbers) = self.sizes(ratio);
         Column {
-            size: best_size,
-            opts: self.opts.clone(),
+            size,
+            max_size,
+            numbers,
+            name: self.name.clone(),
+            opts: Options {
+                truncated: self.is_truncated(),
+                ..self.opts.clone()
+            },
             extra_info: self.extra_info.clone(),
         }
     }
+
+    /// Updates `col`, which was calculated from this column before, to the
+    /// current samples. Returns whether anything changed.
+    pub fn update_size(&self, col: &mut Column, ratio: f64) -> bool {
+        let sizes = self.sizes(ratio);
+        let truncated = self.is_truncated();
+        if sizes == (col.size, col.max_size, col.numbers) && truncated == col.opts.truncated {
+            return false;
+        }
+        (col.size, col.max_size, col.numbers) = sizes;
+        col.opts.truncated = truncated;
+        true
+    }
+
+    /// The size of the column, the size of its widest value and the widths of
+    /// its numbers.
+    fn sizes(&self, ratio: f64) -> (usize, usize, Option<NumberWidth>) {
+        let max_size = self.sizing_samples().last().map_or(0, |p| p.0);
+        // The first of the candidates with the lowest score.
+        let best_size = self
+            .size_candidates(ratio)
+            .iter()
+            .min_by(|a, b| a.score.total_cmp(&b.score))
+            .map_or(max_size, |c| c.size);
+
+        let numbers = self.numbers.filter(|w| w.integer > 0);
+        // Aligned numbers need room for the widest integer and fraction parts.
+        let aligned_size = match numbers {
+            Some(w) if self.opts.align_numbers => w.integer + w.fraction,
+            _ => 0,
+        };
+
+        (
+            best_size.max(aligned_size).max(self.min_size),
+            max_size.max(aligned_size),
+            numbers,
+        )
+    }
 }
 
 impl Column {
@@ -138,58 +640,249 @@ impl Column {
         self.opts.excluded
     }
 
+    pub fn set_excluded(&mut self, is_excluded: bool) {
+        self.opts.excluded = is_excluded;
+    }
+
+    /// Excludes the column because it does not fit in the output width.
+    pub fn hide(&mut self) {
+        self.opts.excluded = true;
+        self.opts.hidden = true;
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.opts.hidden
+    }
+
+    /// Makes the column exactly `size` wide, and keeps it that size when
+    /// fitting to a width.
+    pub fn lock(&mut self, size: usize) {
+        self.size = size;
+        self.max_size = size;
+        self.opts.locked = true;
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.opts.locked
+    }
+
+    /// The header of the column, if there is one.
+    pub fn name(&self) -> Option<&[u8]> {
+        self.name.as_deref()
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.opts.pinned
+    }
+
+    pub fn is_right_edge(&self) -> bool {
+        self.opts.right_edge
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.opts.weight
+    }
+
+    /// Whether every value in the column is a number.
+    pub fn is_numeric(&self) -> bool {
+        self.numbers.is_some()
+    }
+
+    /// Whether the column holds numbers that must never be truncated.
+    pub fn protects_numbers(&self) -> bool {
+        self.opts.protect_numbers && self.is_numeric()
+    }
+
+    /// The widest integer and fraction parts of the numbers in the column, if
+    /// every value is a number.
+    pub fn number_width(&self) -> Option<NumberWidth> {
+        self.numbers
+    }
+
+    /// Makes room for numbers with integer and fraction parts as wide as `w`,
+    /// if the column is numeric.
+    pub fn widen_numbers(&mut self, w: NumberWidth) {
+        if let Some(ref mut widest) = self.numbers {
+            widest.integer = widest.integer.max(w.integer);
+            widest.fraction = widest.fraction.max(w.fraction);
+            if self.opts.align_numbers {
+                self.size = self.size.max(widest.integer + widest.fraction);
+            }
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The size of the largest value seen while measuring the column.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn set_size(&mut self, size: usize) {
+        self.size = size;
+    }
+
+    pub fn set_alignment(&mut self, alignment: Alignment) {
+        self.opts.alignment = Some(alignment);
+    }
+
+    /// A copy of the column that never truncates values.
+    pub fn untruncated(&self) -> Column {
+        let mut col = self.clone();
+        col.opts.truncated = false;
+        col.opts.clipped = false;
+        col
+    }
+
+    /// Resize the column and truncate every value that does not fit.
+    pub fn clip(&mut self, size: usize) {
+        self.size = size;
+        self.opts.truncated = true;
+        self.opts.clipped = true;
+    }
+
+    /// Whether `print_cell` would cut `cell` off.
+    pub fn truncates(&self, cell: &[u8], overflow: usize, last: bool) -> bool {
+        if !self.opts.truncated || (last && !self.opts.clipped) {
+            return false;
+        }
+        let len = match self.align_number(cell) {
+            Some(aligned) if last => self.opts.width(trim_end(&aligned)),
+            Some(aligned) => self.opts.width(&aligned),
+            None => self.opts.width(cell),
+        };
+        len > self.size.saturating_sub(overflow)
+    }
+
     pub fn print_cell<W: Write>(
         &self,
         out: &mut W,
-        cell: &str,
+        cell: &[u8],
         overflow: usize,
         last: bool,
+        style: Option<&Style>,
     ) -> io::Result<usize> {
-        if last {
-            write!(out, "{}", cell)?;
-            Ok(0)
+        let aligned = self.align_number(cell);
+        let cell = match aligned {
+            Some(ref a) if last => trim_end(a),
+            Some(ref a) => &a[..],
+            None => cell,
+        };
+
+        let style = style.copied().unwrap_or_default();
+        let out_width = self.size.saturating_sub(overflow);
+        let cell_width = self.opts.width(cell);
+        // Numbers aligned on the decimal point are already padded.
+        let split_padding = |padding| match aligned {
+            Some(_) => (0, padding),
+            None => self.split_padding(padding),
+        };
+        if last && !self.opts.clipped {
+            if !cell.is_empty() {
+                write_padding(out, split_padding(out_width.saturating_sub(cell_width)).0)?;
+            }
+            style.write_prefix(out)?;
+            out.write_all(cell)?;
+            style.write_suffix(out)?;
+            return Ok(0);
+        }
+
+        let (content, ellipsis, padding, overflow) = if self.opts.truncated && cell_width > out_width {
+            if out_width > 0 {
+                let (content, content_width) = self.opts.widths.truncate(cell, out_width - 1);
+                // Pad to make up for a character that did not fit whole.
+                (content, true, out_width - 1 - content_width, 0)
+            } else {
+                (&cell[0..0], true, 0, 1)
+            }
         } else {
-            let out_width = self.size.saturating_sub(overflow);
-            if self.opts.truncated && cell.len() > out_width {
-                if out_width > 0 {
-                    write!(out, "{}…", &cell[0..out_width - 1])?;
-                    Ok(0)
-                } else {
-                    write!(out, "…")?;
-                    Ok(1)
-                }
+            let overflow = if cell_width < self.size {
+                overflow.saturating_sub(self.size.saturating_sub(cell_width))
             } else {
-                write!(out, "{:1$}", cell, out_width)?;
-                if cell.len() < self.size {
-                    Ok(overflow.saturating_sub(self.size.saturating_sub(cell.len())))
-                } else {
-                    Ok(overflow + cell.len().saturating_sub(self.size))
-                }
+                overflow + cell_width.saturating_sub(self.size)
+            };
+            (cell, false, out_width.saturating_sub(cell_width), overflow)
+        };
+        let (lead, trail) = split_padding(padding);
+        write_padding(out, lead)?;
+        style.write_prefix(out)?;
+        out.write_all(content)?;
+        if ellipsis {
+            write!(out, "…")?;
+            // The sequence that ends a color can be among what was cut off.
+            if self.opts.widths.ansi && content.contains(&0x1b) {
+                out.write_all(b"\x1b[0m")?;
             }
         }
+        style.write_suffix(out)?;
+        if !last {
+            write_padding(out, trail)?;
+        }
+        Ok(overflow)
+    }
+
+    /// Splits the padding of a value into the spaces before and after it.
+    fn split_padding(&self, padding: usize) -> (usize, usize) {
+        let alignment = self.opts.alignment.unwrap_or(if self.is_numeric() {
+            Alignment::Right
+        } else {
+            Alignment::Left
+        });
+        match alignment {
+            Alignment::Left => (0, padding),
+            Alignment::Right => (padding, 0),
+            Alignment::Center => (padding / 2, padding - padding / 2),
+        }
     }
 
-    pub fn print_info<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
-        let extra = self.extra_info.take().unwrap();
+    /// The cell padded so that it is right-aligned and its decimal point lines
+    /// up with the other numbers in the column, if it should be.
+    fn align_number(&self, cell: &[u8]) -> Option<Vec<u8>> {
+        let widest = self.numbers.filter(|_| self.opts.align_numbers)?;
+        let w = numeric::number_width(cell)?;
+        let width = widest.integer + widest.fraction;
+        // Values read after the column was measured can have wider parts.
+        let lead = self.size.saturating_sub(width) + widest.integer.checked_sub(w.integer)?;
+        let trail = widest.fraction.checked_sub(w.fraction)?;
+        if lead + cell.len() + trail > self.size {
+            return None;
+        }
+        let mut aligned = vec![b' '; lead];
+        aligned.extend_from_slice(cell);
+        aligned.resize(lead + cell.len() + trail, b' ');
+        Some(aligned)
+    }
+
+    pub fn print_info<W: Write + ?Sized>(&mut self, out: &mut W) -> io::Result<()> {
         writeln!(out, "  Computed column size:  {}", self.size)?;
         writeln!(out, "  Excluded:              {}", self.opts.excluded)?;
         writeln!(out, "  Truncated:             {}", self.opts.truncated)?;
+        let extra = match self.extra_info.take() {
+            Some(extra) => extra,
+            None => return Ok(()),
+        };
         if let Some(ref min) = extra.min_value {
             writeln!(
                 out,
                 "  Min-length value:      [length {}] {:?}",
-                min.len(),
-                min
+                self.opts.width(min),
+                String::from_utf8_lossy(min)
             )?;
         }
         if let Some(ref max) = extra.max_value {
             writeln!(
                 out,
                 "  Max-length value:      [length {}] {:?}",
-                max.len(),
-                max
+                self.opts.width(max),
+                String::from_utf8_lossy(max)
             )?;
         }
         Ok(())
     }
 }
+
+fn write_padding<W: Write>(out: &mut W, width: usize) -> io::Result<()> {
+    write!(out, "{:1$}", "", width)
+}
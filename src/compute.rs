@@ -0,0 +1,282 @@
+//! Computed columns from arithmetic on other columns, for `--compute`.
+//!
+//! A computation is written `NAME=EXPR` where EXPR uses `+`, `-`, `*`, `/`,
+//! parentheses, numbers and column references `colN` (starting at 1), e.g.
+//! `ratio=col3/col4`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::*;
+use crate::parser::Row;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Column(usize),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// The value of the expression, or `None` if a referenced cell is missing
+    /// or not a number, or the result is not finite.
+    fn eval(&self, row: &Row) -> Option<f64> {
+        let value = match *self {
+            Expr::Number(n) => n,
+            Expr::Column(i) => {
+                if i >= row.len() {
+                    return None;
+                }
+                std::str::from_utf8(&row[i]).ok()?.trim().parse().ok()?
+            }
+            Expr::Neg(ref e) => -e.eval(row)?,
+            Expr::Binary(op, ref a, ref b) => {
+                let (a, b) = (a.eval(row)?, b.eval(row)?);
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                }
+            }
+        };
+        Some(value).filter(|v| v.is_finite())
+    }
+}
+
+#[derive(Clone)]
+pub struct Computation {
+    source: String,
+    name: String,
+    expr: Expr,
+}
+
+impl Computation {
+    pub fn new(s: &str) -> Result<Computation> {
+        let (name, _) = s.split_once('=').ok_or_else(|| Error::ComputeParseError {
+            s: s.to_string(),
+            msg: "expected NAME=EXPR".to_string(),
+            pos: 0,
+        })?;
+        let mut parser = ExprParser {
+            source: s,
+            chars: s.chars().collect(),
+            pos: name.chars().count() + 1,
+        };
+        let expr = parser.parse()?;
+        Ok(Computation {
+            source: s.to_string(),
+            name: name.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// The name of the column, used as its header.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Appends the computed value to `row`, or an empty cell if it cannot be
+    /// computed.
+    pub fn apply(&self, row: &mut Row) {
+        let value = self.expr.eval(row).map(format_number).unwrap_or_default();
+        row.push(value.as_bytes());
+    }
+}
+
+impl FromStr for Computation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Computation> {
+        Computation::new(s)
+    }
+}
+
+impl fmt::Debug for Computation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Computation({:?})", self.source)
+    }
+}
+
+/// Appends to every row the value of column `col` as a percentage of the sum
+/// of the column. Rows where the column is not a number get an empty cell.
+pub(crate) fn append_percentages(rows: &mut [Row], col: usize) {
+    let value = |row: &Row| Expr::Column(col).eval(row);
+    let total: f64 = rows.iter().filter_map(value).sum();
+    for row in rows {
+        let percent = value(row)
+            .map(|v| v / total * 100.0)
+            .filter(|p| p.is_finite())
+            .map(|p| format!("{:.1}%", p))
+            .unwrap_or_default();
+        row.push(percent.as_bytes());
+    }
+}
+
+/// Formats `n` with at most six decimals and without trailing zeros.
+fn format_number(n: f64) -> String {
+    let s = format!("{:.6}", n);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
+
+struct ExprParser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn error(&self, msg: &str) -> Error {
+        Error::ComputeParseError {
+            s: self.source.to_string(),
+            msg: msg.to_string(),
+            pos: self.pos,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse(&mut self) -> Result<Expr> {
+        let expr = self.parse_sum()?;
+        match self.peek() {
+            None => Ok(expr),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_product()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_product()?));
+        }
+    }
+
+    fn parse_product(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+        if self.eat('(') {
+            let expr = self.parse_sum()?;
+            if !self.eat(')') {
+                return Err(self.error("expected ')'"));
+            }
+            return Ok(expr);
+        }
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let n = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                n.parse()
+                    .map(Expr::Number)
+                    .map_err(|_| self.error(&format!("invalid number '{}'", n)))
+            }
+            Some('c') => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric());
+                match name.strip_prefix("col").map(str::parse::<usize>) {
+                    Some(Ok(0)) => Err(Error::ColumnsStartAtOne),
+                    Some(Ok(n)) => Ok(Expr::Column(n - 1)),
+                    _ => Err(self.error(&format!("expected a column like 'col1', found '{}'", name))),
+                }
+            }
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> String {
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|&c| f(c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::RowParser;
+
+    fn compute(computation: &str, line: &str) -> String {
+        let mut row = Row::new();
+        RowParser::new(" ", false).parse_into(&mut row, line);
+        computation.parse::<Computation>().unwrap().apply(&mut row);
+        String::from_utf8(row[row.len() - 1].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn computations() {
+        assert_eq!(compute("r=col1/col2", "3 4"), "0.75");
+        assert_eq!(compute("r = (col1 + 1) * -col2", "3 4"), "-16");
+        assert_eq!(compute("r=col1-2*col2", "1 0.5"), "0");
+        assert_eq!(compute("r=col1/col2", "1 0"), "");
+        assert_eq!(compute("r=col1+col3", "1 2"), "");
+        assert_eq!(compute("r=col1*2", "x"), "");
+    }
+
+    #[test]
+    fn invalid_computations() {
+        assert!("col1+1".parse::<Computation>().is_err());
+        assert!("r=".parse::<Computation>().is_err());
+        assert!("r=col0".parse::<Computation>().is_err());
+        assert!("r=(col1".parse::<Computation>().is_err());
+        assert!("r=col1 col2".parse::<Computation>().is_err());
+        assert!("r=foo".parse::<Computation>().is_err());
+    }
+
+    #[test]
+    fn error_position() {
+        let err = "ratio=col1 col2".parse::<Computation>().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Option);
+        assert_eq!(err.fragment(), Some("ratio=col1 col2"));
+        assert_eq!(err.column(), Some(12));
+        assert_eq!(err.line(), None);
+    }
+}
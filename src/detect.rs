@@ -0,0 +1,47 @@
+//! Finding a table among other lines, for `--auto-detect-table`.
+
+use crate::parser::{Row, RowParser};
+
+/// The first and last line, counted from 1, of the longest run of at least
+/// two lines that all split into the same number of fields, at least two.
+/// Of runs that are equally long, the first is taken.
+pub fn find_table(lines: &[Vec<u8>], parser: &RowParser) -> Option<(usize, usize)> {
+    let mut row = Row::new();
+    let mut best: Option<(usize, usize)> = None;
+    // The first line and the number of fields of the current run.
+    let mut run: Option<(usize, usize)> = None;
+    for (i, line) in lines.iter().enumerate() {
+        parser.parse_into(&mut row, line.clone());
+        let fields = row.len();
+        run = match run {
+            Some((start, n)) if n == fields => Some((start, n)),
+            _ if fields >= 2 => Some((i, fields)),
+            _ => None,
+        };
+        if let Some((start, _)) = run {
+            let longer = best.is_none_or(|(first, last)| i - start > last - first);
+            if i > start && longer {
+                best = Some((start, i));
+            }
+        }
+    }
+    best.map(|(first, last)| (first + 1, last + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<Vec<u8>> {
+        text.lines().map(|l| l.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn longest_run() {
+        let parser = RowParser::new(" \t", false);
+        let text = "Some text here\n\nname size kind\na 1 file\nb 22 dir\n\nTotal 2\nx y\n";
+        assert_eq!(find_table(&lines(text), &parser), Some((3, 5)));
+        assert_eq!(find_table(&lines("just\none\n\nword\n"), &parser), None);
+        assert_eq!(find_table(&lines("a b\nc d e\n"), &parser), None);
+    }
+}
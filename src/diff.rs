@@ -0,0 +1,194 @@
+//! Unified diffs of the lines of two texts, for `--diff`.
+
+use std::io::{self, Write};
+
+/// The number of unchanged lines shown around every change.
+const CONTEXT: usize = 3;
+
+/// The largest number of pairs of lines compared to find the lines that did
+/// not change. Beyond it, all lines between the first and the last changed
+/// line are shown as changed, which is still a correct diff.
+const MAX_COMPARISONS: usize = 1 << 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    /// Line `.0` of the old text is line `.1` of the new text.
+    Keep(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Writes a unified diff, like `diff -u`, of the changes from `old` to `new`.
+/// Nothing is written if they are the same.
+pub fn write_unified_diff<W: Write>(out: &mut W, old_name: &str, new_name: &str, old: &[u8], new: &[u8]) -> io::Result<()> {
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+    let edits = diff_lines(&old_lines, &new_lines);
+    if edits.iter().all(|e| matches!(e, Edit::Keep(..))) {
+        return Ok(());
+    }
+    writeln!(out, "--- {}", old_name)?;
+    writeln!(out, "+++ {}", new_name)?;
+    for hunk in hunks(&edits) {
+        write_hunk(out, hunk, &old_lines, &new_lines)?;
+    }
+    Ok(())
+}
+
+/// The lines of `text`, each with its line feed if it has one.
+fn lines(text: &[u8]) -> Vec<&[u8]> {
+    text.split_inclusive(|&b| b == b'\n').collect()
+}
+
+fn diff_lines(old: &[&[u8]], new: &[&[u8]]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Keep(i, i)).collect();
+    edits.extend(diff_middle(old, new, prefix, old_end, new_end));
+    edits.extend((0..suffix).map(|i| Edit::Keep(old_end + i, new_end + i)));
+    edits
+}
+
+/// The edits between lines `start..old_end` of `old` and `start..new_end` of
+/// `new`, from the longest common subsequence of lines.
+fn diff_middle(old: &[&[u8]], new: &[&[u8]], start: usize, old_end: usize, new_end: usize) -> Vec<Edit> {
+    let (n, m) = (old_end - start, new_end - start);
+    if n.saturating_mul(m) > MAX_COMPARISONS {
+        let deleted = (start..old_end).map(Edit::Delete);
+        return deleted.chain((start..new_end).map(Edit::Insert)).collect();
+    }
+    // common[i][j] is the length of the longest common subsequence of the old
+    // lines from i and the new lines from j, both counted from `start`.
+    let mut common = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[at(i, j)] = if old[start + i] == new[start + j] {
+                common[at(i + 1, j + 1)] + 1
+            } else {
+                common[at(i + 1, j)].max(common[at(i, j + 1)])
+            };
+        }
+    }
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[start + i] == new[start + j] {
+            edits.push(Edit::Keep(start + i, start + j));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || common[at(i + 1, j)] >= common[at(i, j + 1)]) {
+            edits.push(Edit::Delete(start + i));
+            i += 1;
+        } else {
+            edits.push(Edit::Insert(start + j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+/// Splits `edits` into runs of changes with up to `CONTEXT` unchanged lines
+/// around them. Changes that are close enough share their context.
+fn hunks(edits: &[Edit]) -> Vec<&[Edit]> {
+    let changed: Vec<usize> = (0..edits.len()).filter(|&i| !matches!(edits[i], Edit::Keep(..))).collect();
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changed.len() {
+        let start = changed[i].saturating_sub(CONTEXT);
+        let mut end = changed[i];
+        while i < changed.len() && changed[i] <= end + 2 * CONTEXT + 1 {
+            end = changed[i];
+            i += 1;
+        }
+        hunks.push(&edits[start..(end + CONTEXT + 1).min(edits.len())]);
+    }
+    hunks
+}
+
+fn write_hunk<W: Write>(out: &mut W, hunk: &[Edit], old: &[&[u8]], new: &[&[u8]]) -> io::Result<()> {
+    // Where the hunk starts in either text: the first line it has of it.
+    let old_start = hunk.iter().find_map(|e| match *e {
+        Edit::Keep(i, _) | Edit::Delete(i) => Some(i),
+        Edit::Insert(_) => None,
+    });
+    let new_start = hunk.iter().find_map(|e| match *e {
+        Edit::Keep(_, j) | Edit::Insert(j) => Some(j),
+        Edit::Delete(_) => None,
+    });
+    let old_count = hunk.iter().filter(|e| !matches!(e, Edit::Insert(_))).count();
+    let new_count = hunk.iter().filter(|e| !matches!(e, Edit::Delete(_))).count();
+    writeln!(
+        out,
+        "@@ -{} +{} @@",
+        range(old_start, old_count),
+        range(new_start, new_count)
+    )?;
+    for edit in hunk {
+        let (marker, line) = match *edit {
+            Edit::Keep(i, _) => (b' ', old[i]),
+            Edit::Delete(i) => (b'-', old[i]),
+            Edit::Insert(j) => (b'+', new[j]),
+        };
+        out.write_all(&[marker])?;
+        out.write_all(line)?;
+        if !line.ends_with(b"\n") {
+            out.write_all(b"\n\\ No newline at end of file\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// A range of lines as `diff -u` writes it: the first line and the count,
+/// left out if it is 1. A hunk only has no lines of a text if the text is
+/// empty, since it has context lines otherwise.
+fn range(start: Option<usize>, count: usize) -> String {
+    match (start, count) {
+        (Some(start), 1) => format!("{}", start + 1),
+        (Some(start), _) => format!("{},{}", start + 1, count),
+        (None, _) => "0,0".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> String {
+        let mut out = Vec::new();
+        write_unified_diff(&mut out, "a", "b", old.as_bytes(), new.as_bytes()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn unified_diff() {
+        assert_eq!(diff("x\ny\n", "x\ny\n"), "");
+        assert_eq!(
+            diff("1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n", "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10\n"),
+            "--- a\n+++ b\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+        assert_eq!(diff("a\n", "a\nb"), "--- a\n+++ b\n@@ -1 +1,2 @@\n a\n+b\n\\ No newline at end of file\n");
+        assert_eq!(diff("", "new\n"), "--- a\n+++ b\n@@ -0,0 +1 @@\n+new\n");
+    }
+
+    #[test]
+    fn separate_hunks() {
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new = old.replace("\n2\n", "\ntwo\n").replace("19\n", "nineteen\n");
+        assert_eq!(
+            diff(&old, &new),
+            concat!(
+                "--- a\n+++ b\n",
+                "@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n",
+                "@@ -16,5 +16,5 @@\n 16\n 17\n 18\n-19\n+nineteen\n 20\n",
+            )
+        );
+    }
+}
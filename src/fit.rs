@@ -0,0 +1,171 @@
+//! Fitting columns into a fixed output width.
+
+use crate::column::Column;
+
+/// Shrinks the visible columns so that lines are at most `width` characters
+/// wide. Pinned, right edge and locked columns, and columns of numbers that
+/// must not be truncated, are given their full width first, and
+/// the remaining space is shared among the other columns in proportion to
+/// their size times their weight. Every other column is clipped to its final
+/// size.
+///
+/// Space that is left over goes to the last column, so it can show longer
+/// values. If there are right edge columns, it goes to the last flexible
+/// column before them instead, which pushes them against the right edge.
+pub fn fit_columns(columns: &mut [Column], width: usize, delim_width: usize) {
+    let visible: Vec<usize> = (0..columns.len())
+        .filter(|&i| !columns[i].is_excluded())
+        .collect();
+    if visible.is_empty() {
+        return;
+    }
+    let mut budget = width.saturating_sub(delim_width * (visible.len() - 1));
+
+    let (pinned, flexible): (Vec<usize>, Vec<usize>) =
+        visible.iter().partition(|&&i| {
+            let col = &columns[i];
+            col.is_pinned() || col.is_right_edge() || col.protects_numbers() || col.is_locked()
+        });
+    for &i in &pinned {
+        let size = columns[i].max_size();
+        columns[i].set_size(size);
+        budget = budget.saturating_sub(size);
+    }
+
+    let wanted: Vec<usize> = flexible.iter().map(|&i| columns[i].size().max(1)).collect();
+    let weights: Vec<f64> = flexible.iter().map(|&i| columns[i].weight()).collect();
+    let sizes = distribute(budget, &wanted, &weights);
+    for (&i, size) in flexible.iter().zip(sizes) {
+        columns[i].clip(size);
+    }
+
+    let slack_column = match visible.iter().position(|&i| columns[i].is_right_edge()) {
+        Some(first_edge) => flexible.iter().rev().find(|&&i| i < visible[first_edge]),
+        None => flexible.last().filter(|&last| Some(last) == visible.last()),
+    };
+    if let Some(&i) = slack_column {
+        let used: usize = flexible.iter().map(|&i| columns[i].size()).sum();
+        if used < budget {
+            let size = columns[i].size() + budget - used;
+            columns[i].clip(size);
+        }
+    }
+}
+
+/// The narrowest a flexible column can be made before so much of its values
+/// is cut off that it is not worth showing.
+const MIN_USEFUL_SIZE: usize = 4;
+
+/// Hides the least important columns until the rest fit in `width` without
+/// shrinking any flexible column below `MIN_USEFUL_SIZE`, or its size if that
+/// is smaller. The flexible columns with the lowest weight go first, the
+/// rightmost of them before the others. Pinned, right edge, locked and
+/// protected columns are never hidden, and neither is the last visible column.
+pub fn hide_columns(columns: &mut [Column], width: usize, delim_width: usize) {
+    let is_fixed =
+        |col: &Column| col.is_pinned() || col.is_right_edge() || col.protects_numbers() || col.is_locked();
+    loop {
+        let visible: Vec<usize> = (0..columns.len())
+            .filter(|&i| !columns[i].is_excluded())
+            .collect();
+        if visible.len() <= 1 {
+            return;
+        }
+        let needed: usize = visible
+            .iter()
+            .map(|&i| match &columns[i] {
+                col if is_fixed(col) => col.max_size(),
+                col => col.size().clamp(1, MIN_USEFUL_SIZE),
+            })
+            .sum::<usize>()
+            + delim_width * (visible.len() - 1);
+        if needed <= width {
+            return;
+        }
+        let least_important = visible
+            .iter()
+            .copied()
+            .filter(|&i| !is_fixed(&columns[i]))
+            .min_by(|&a, &b| columns[a].weight().total_cmp(&columns[b].weight()).then(b.cmp(&a)));
+        match least_important {
+            Some(i) => columns[i].hide(),
+            None => return,
+        }
+    }
+}
+
+/// Divides `budget` among the `wanted` sizes. Nothing is shrunk if everything
+/// fits, otherwise each size gets a share proportional to its weighted size,
+/// but never more than it wants and never less than 1.
+fn distribute(budget: usize, wanted: &[usize], weights: &[f64]) -> Vec<usize> {
+    debug_assert_eq!(wanted.len(), weights.len());
+    let total: usize = wanted.iter().sum();
+    if total <= budget {
+        return wanted.to_vec();
+    }
+
+    let mut shares = vec![0.0; wanted.len()];
+    let mut capped = vec![false; wanted.len()];
+    let mut remaining = budget as f64;
+    loop {
+        let open: Vec<usize> = (0..wanted.len()).filter(|&i| !capped[i]).collect();
+        let weighted: f64 = open.iter().map(|&i| wanted[i] as f64 * weights[i]).sum();
+        let unweighted: f64 = open.iter().map(|&i| wanted[i] as f64).sum();
+        let mut changed = false;
+        for i in open {
+            // Columns that all have zero weight share in proportion to size.
+            let part = if weighted > 0.0 {
+                wanted[i] as f64 * weights[i] / weighted
+            } else {
+                wanted[i] as f64 / unweighted
+            };
+            shares[i] = remaining * part;
+            if shares[i] >= wanted[i] as f64 {
+                shares[i] = wanted[i] as f64;
+                capped[i] = true;
+                remaining -= shares[i];
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut sizes: Vec<usize> = shares.iter().map(|&s| (s.floor() as usize).max(1)).collect();
+    // Hand out what is left because of rounding down, largest remainders first.
+    let mut used: usize = sizes.iter().sum();
+    let mut order: Vec<usize> = (0..wanted.len()).collect();
+    order.sort_by(|&a, &b| {
+        let ra = shares[a] - sizes[a] as f64;
+        let rb = shares[b] - sizes[b] as f64;
+        rb.total_cmp(&ra)
+    });
+    for i in order {
+        if used >= budget {
+            break;
+        }
+        if sizes[i] < wanted[i] {
+            sizes[i] += 1;
+            used += 1;
+        }
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distribute_width() {
+        assert_eq!(distribute(10, &[2, 3], &[1.0, 1.0]), vec![2, 3]);
+        assert_eq!(distribute(10, &[10, 10], &[1.0, 1.0]), vec![5, 5]);
+        assert_eq!(distribute(10, &[15, 5], &[1.0, 1.0]), vec![8, 2]);
+        assert_eq!(distribute(2, &[15, 5, 5], &[1.0, 1.0, 1.0]), vec![1, 1, 1]);
+        assert_eq!(distribute(10, &[10, 10], &[3.0, 1.0]), vec![8, 2]);
+        assert_eq!(distribute(15, &[10, 10], &[4.0, 1.0]), vec![10, 5]);
+        assert_eq!(distribute(10, &[10, 10], &[0.0, 0.0]), vec![5, 5]);
+        assert_eq!(distribute(10, &[2, 10], &[1.0, 0.0]), vec![2, 8]);
+    }
+}
@@ -0,0 +1,233 @@
+//! Random options, column ranges and parser settings, to test the library
+//! with combinations of options that no test spells out. The choices are
+//! drawn from a seed, or straight from the input of a fuzz target with
+//! `Gen::from_bytes`, so that a fuzzer which changes a byte of its input
+//! changes the choice made from it.
+
+use crate::bench::Rng;
+use crate::parser::RowParser;
+use crate::range::{Range, Ranges};
+use crate::{Alignment, Options, SizingStrategy};
+
+/// The source of the choices. The same seed or bytes always give the same
+/// values.
+pub struct Gen<'a>(Source<'a>);
+
+enum Source<'a> {
+    Rng(Rng),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> Gen<'a> {
+    pub fn new(seed: u64) -> Gen<'a> {
+        Gen(Source::Rng(Rng::new(seed)))
+    }
+
+    /// A generator that reads its choices from `data`, such as the input of
+    /// a fuzz target, a byte or a few at a time. Once `data` is used up,
+    /// every choice is the first one.
+    pub fn from_bytes(data: &'a [u8]) -> Gen<'a> {
+        Gen(Source::Bytes(data))
+    }
+
+    /// A number below `n`, which must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0);
+        match self.0 {
+            Source::Rng(ref mut rng) => rng.below(n),
+            Source::Bytes(ref mut data) => {
+                let mut value = 0usize;
+                let mut range = 1usize;
+                while range < n {
+                    match data.split_first() {
+                        Some((&byte, rest)) => {
+                            value = value.wrapping_shl(8) | byte as usize;
+                            range = range.saturating_mul(256);
+                            *data = rest;
+                        }
+                        None => break,
+                    }
+                }
+                value % n
+            }
+        }
+    }
+
+    /// True one time in `n`.
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    pub fn choose<T: Clone>(&mut self, values: &[T]) -> T {
+        values[self.below(values.len())].clone()
+    }
+
+    /// Lines of up to `columns` cells of letters, digits and a few other
+    /// characters, some of them wide, separated by spaces or commas.
+    pub fn input(&mut self, columns: usize) -> Vec<u8> {
+        const CHARS: &[&str] = &["a", "b", "x", "1", "2", "9", ".", "-", "é", "名", "\t", "\x1b[1m"];
+        let mut input = Vec::new();
+        for _ in 0..self.below(10) {
+            for cell in 0..self.below(columns + 1) {
+                if cell > 0 {
+                    input.push(self.choose(b"  ,"));
+                }
+                for _ in 0..self.below(8) {
+                    input.extend_from_slice(self.choose(CHARS).as_bytes());
+                }
+            }
+            input.push(b'\n');
+        }
+        input
+    }
+}
+
+/// Types whose values can be made up by a `Gen`.
+pub trait Generate: Sized {
+    fn generate(g: &mut Gen) -> Self;
+}
+
+/// Values are mostly `None`, so the options they stand for are only used
+/// now and then.
+impl<T: Generate> Generate for Option<T> {
+    fn generate(g: &mut Gen) -> Self {
+        if g.one_in(3) {
+            Some(T::generate(g))
+        } else {
+            None
+        }
+    }
+}
+
+/// Ranges of the first few columns, including 0, which is not a column.
+impl Generate for Range {
+    fn generate(g: &mut Gen) -> Self {
+        let a = g.below(6) as u32;
+        match g.below(3) {
+            0 => Range::From(a),
+            1 => Range::To(a),
+            _ => Range::Between(a, a + g.below(3) as u32),
+        }
+    }
+}
+
+impl Generate for Ranges {
+    fn generate(g: &mut Gen) -> Self {
+        (0..g.below(3)).map(|_| Range::generate(g)).collect()
+    }
+}
+
+impl Generate for RowParser {
+    fn generate(g: &mut Gen) -> Self {
+        RowParser::builder()
+            .delimiters(g.choose(&[" ", ",", " \t", ",;", ""]))
+            .strict(g.one_in(3))
+            .strict_delimiters(g.choose(&["", ",", ";"]))
+            .build()
+    }
+}
+
+/// Options that only change how the input is read and printed. Options that
+/// wait for more input, such as `follow`, or that write to files or stderr
+/// are left alone.
+impl Generate for Options {
+    fn generate(g: &mut Gen) -> Self {
+        Options {
+            truncate: Generate::generate(g),
+            ratio: g.choose(&[0.0, 0.5, 1.0, 3.0, -1.0, f64::NAN]),
+            truncate_ratio: g.one_in(4).then(|| g.choose(&[0.0, 2.0, f64::INFINITY])),
+            bucket_widths: g.one_in(4),
+            sizing_strategy: g.choose(&[SizingStrategy::Auto, SizingStrategy::FirstMinimum, SizingStrategy::Exhaustive]),
+            ignore_outliers: g.choose(&[0.0, 0.0, 25.0, 100.0]),
+            lines: g.below(5),
+            estimate_mem: g.one_in(5).then(|| g.below(200)),
+            include_cols: Generate::generate(g),
+            exclude_cols: Generate::generate(g),
+            delim: g.choose(&[" ", " ", ",", " ,"]).into(),
+            output_delim: g.choose(&["  ", " | ", ""]).into(),
+            strict_delim: g.one_in(4),
+            print_info: g.one_in(10),
+            online: g.one_in(4),
+            checksum: g.one_in(4),
+            footer_info: g.one_in(4),
+            page_length: g.one_in(5).then(|| g.below(4)),
+            page_numbers: g.one_in(3),
+            color: g.one_in(4),
+            fit: g.one_in(3),
+            auto_hide: g.one_in(2),
+            width: Some(g.below(40)),
+            pin: Generate::generate(g),
+            right_edge: Generate::generate(g),
+            collapse_repeats: Generate::generate(g),
+            group_sep: g.one_in(5).then(|| g.below(4) as u32),
+            tail: g.one_in(6).then(|| g.below(4)),
+            window: g.one_in(5).then(|| g.below(4)),
+            header: g.one_in(2),
+            header_floor: g.one_in(3),
+            underline_header: g.one_in(5).then_some('-'),
+            header_align: g.one_in(4).then(|| g.choose(&[Alignment::Left, Alignment::Right, Alignment::Center])),
+            hide_header: g.one_in(6),
+            events: g.one_in(8),
+            soft_wrap: g.one_in(6).then(|| b"> ".to_vec()),
+            no_trailing_space: g.one_in(3),
+            border: g.one_in(5),
+            check_columns: g.one_in(6),
+            warn_estimate: g.one_in(4),
+            percent: g.one_in(8).then(|| g.below(4) as u32),
+            left_align_numbers: g.one_in(3),
+            align_numbers: g.one_in(3),
+            protect_numbers: g.one_in(3),
+            byte_widths: g.one_in(4),
+            ignore_ansi: g.one_in(3),
+            pass_ansi_lines: g.one_in(5),
+            line_ranges: Generate::generate(g),
+            auto_detect_table: g.one_in(6),
+            drop_other_lines: g.one_in(3),
+            sort: (0..g.below(2))
+                .filter_map(|_| g.choose(&["1", "2:numeric", "3:version", "1:natural"]).parse().ok())
+                .collect(),
+            every: g.below(3),
+            max_columns: g.one_in(5).then(|| g.below(4)),
+            preview: g.one_in(6).then(|| g.below(4)),
+            drop_constant: g.one_in(6),
+            drop_empty: g.one_in(6),
+            join_continuations: g.one_in(6),
+            ..Options::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_values() {
+        let options = |seed| format!("{:?}", Options::generate(&mut Gen::new(seed)));
+        assert_eq!(options(7), options(7));
+        assert_ne!(options(7), options(8));
+        assert_eq!(Gen::from_bytes(b"abc").input(3), Gen::from_bytes(b"abc").input(3));
+    }
+
+    #[test]
+    fn choices_come_from_bytes() {
+        let mut g = Gen::from_bytes(&[7, 1, 2, 200]);
+        assert_eq!(g.below(5), 2);
+        assert_eq!(g.below(1000), 258);
+        assert!(g.one_in(2));
+        // The bytes are used up.
+        assert_eq!(g.below(9), 0);
+        assert_eq!(g.choose(&["a", "b"]), "a");
+    }
+
+    #[test]
+    fn any_options_never_panic() {
+        for seed in 0..1000 {
+            let mut g = Gen::new(seed);
+            let opts = Options::generate(&mut g);
+            let input = g.input(5);
+            let _ = crate::process(&input[..], std::io::sink(), &opts);
+            let _ = crate::process_threaded(&input[..], std::io::sink(), &opts);
+        }
+    }
+}
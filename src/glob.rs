@@ -0,0 +1,150 @@
+//! Finding the files that match a pattern such as `logs/*.txt`, for `--batch`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The files matching `pattern`, sorted by path. Every part of the path may
+/// contain `*`, which matches any run of characters, `?`, which matches one
+/// character, and `[abc]`, `[a-z]` or `[!abc]`, which match one character of
+/// (or not of) a set. Names starting with `.` are only matched by parts that
+/// start with `.` too.
+pub fn expand(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![PathBuf::new()];
+    for part in Path::new(pattern).components() {
+        let part = part.as_os_str();
+        let glob = match part.to_str() {
+            Some(glob) if glob.contains(['*', '?', '[']) => glob,
+            _ => {
+                for path in &mut paths {
+                    path.push(part);
+                }
+                continue;
+            }
+        };
+        let mut matched = Vec::new();
+        for dir in &paths {
+            let entries = match fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) {
+                Ok(entries) => entries,
+                // Paths that are not readable directories have nothing in them to match.
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let name = entry?.file_name();
+                if let Some(s) = name.to_str() {
+                    if matches(glob, s) && (!s.starts_with('.') || glob.starts_with('.')) {
+                        matched.push(dir.join(s));
+                    }
+                }
+            }
+        }
+        paths = matched;
+    }
+    paths.retain(|path| path.is_file());
+    paths.sort();
+    Ok(paths)
+}
+
+/// The leading directories of `pattern` that contain no `*`, `?` or `[`, which
+/// every path found by `expand` starts with.
+pub fn root(pattern: &str) -> PathBuf {
+    let mut root = PathBuf::new();
+    for part in Path::new(pattern).components() {
+        if part.as_os_str().to_str().is_some_and(|s| s.contains(['*', '?', '['])) {
+            return root;
+        }
+        root.push(part);
+    }
+    // Without wildcards the pattern is the one file it matches.
+    root.pop();
+    root
+}
+
+/// Whether `name` matches the pattern `glob`, as described for `expand`.
+pub fn matches(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_chars(&glob, &name)
+}
+
+fn matches_chars(glob: &[char], name: &[char]) -> bool {
+    match glob.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|i| matches_chars(rest, &name[i..])),
+        Some(('?', rest)) => !name.is_empty() && matches_chars(rest, &name[1..]),
+        Some(('[', rest)) => match (class_end(rest), name.split_first()) {
+            (Some(end), Some((&c, name))) => in_class(&rest[..end], c) && matches_chars(&rest[end + 1..], name),
+            (Some(_), None) => false,
+            // A `[` that starts no set matches itself.
+            (None, _) => name.first() == Some(&'[') && matches_chars(rest, &name[1..]),
+        },
+        Some((&c, rest)) => name.first() == Some(&c) && matches_chars(rest, &name[1..]),
+    }
+}
+
+/// The position of the `]` that ends the set starting at `glob`. A `]` right
+/// at the start is part of the set.
+fn class_end(glob: &[char]) -> Option<usize> {
+    let start = if glob.first() == Some(&'!') { 1 } else { 0 };
+    glob.iter()
+        .skip(start + 1)
+        .position(|&c| c == ']')
+        .map(|i| i + start + 1)
+}
+
+fn in_class(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.split_first() {
+        Some(('!', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_names() {
+        assert!(matches("*.txt", "a.txt"));
+        assert!(matches("*.txt", ".txt"));
+        assert!(!matches("*.txt", "a.txt.gz"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(matches("log[0-9].[!c]*", "log3.txt"));
+        assert!(!matches("log[0-9].[!c]*", "log3.csv"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches("a[b", "a[b"));
+        assert!(matches("*é*", "café"));
+    }
+
+    #[test]
+    fn expand_paths() {
+        let dir = std::env::temp_dir().join(format!("tabulate-glob-{}", std::process::id()));
+        for name in &["a/1.txt", "a/2.txt", "a/.3.txt", "b/4.txt", "b/5.csv"] {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let pattern = format!("{}/*/[0-9].txt", dir.display());
+        let found: Vec<PathBuf> = expand(&pattern).unwrap();
+        let expected: Vec<PathBuf> = ["a/1.txt", "a/2.txt", "b/4.txt"].iter().map(|n| dir.join(n)).collect();
+        assert_eq!(found, expected);
+        assert!(expand(&format!("{}/*/*.json", dir.display())).unwrap().is_empty());
+        assert_eq!(root(&pattern), dir);
+        assert_eq!(root(&format!("{}/a/1.txt", dir.display())), dir.join("a"));
+        assert_eq!(root("*.txt"), PathBuf::new());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
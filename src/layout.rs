@@ -0,0 +1,563 @@
+//! Column layouts: saving and loading them, reading tables back from aligned
+//! output and checking that the output keeps to a layout.
+
+use std::io::{self, BufRead, Write};
+
+use crate::column::Column;
+use crate::errors::*;
+use crate::numeric::NumberWidth;
+use crate::parser::Row;
+use crate::utils::trim_end;
+use crate::width::Widths;
+
+/// How printed lines are measured to find the columns on them: by display
+/// width, with control sequences such as colors taking up no space.
+const WIDTHS: Widths = Widths { bytes: false, ansi: true };
+
+/// The size of a column, whether it is printed at all and, if it only has
+/// numbers, the widths used to align them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutColumn {
+    pub size: usize,
+    pub excluded: bool,
+    /// The column is always exactly `size` wide, whatever is measured and
+    /// even when fitting to the output width.
+    pub locked: bool,
+    pub numbers: Option<NumberWidth>,
+}
+
+/// The columns of a table and the delimiter printed between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub columns: Vec<LayoutColumn>,
+    pub delim: Vec<u8>,
+}
+
+impl Layout {
+    /// A layout of printed columns of the given sizes.
+    pub fn new<S: Into<Vec<u8>>>(sizes: Vec<usize>, delim: S) -> Layout {
+        let columns = sizes
+            .into_iter()
+            .map(|size| LayoutColumn {
+                size,
+                excluded: false,
+                locked: false,
+                numbers: None,
+            })
+            .collect();
+        Layout {
+            columns,
+            delim: delim.into(),
+        }
+    }
+
+    pub(crate) fn from_columns<S: Into<Vec<u8>>>(columns: &[Column], delim: S) -> Layout {
+        let columns = columns
+            .iter()
+            .map(|c| LayoutColumn {
+                size: c.size(),
+                excluded: c.is_excluded(),
+                locked: c.is_locked(),
+                numbers: c.number_width(),
+            })
+            .collect();
+        Layout {
+            columns,
+            delim: delim.into(),
+        }
+    }
+
+    /// The sizes of the columns that are printed.
+    pub fn visible_sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.columns.iter().filter(|c| !c.excluded).map(|c| c.size)
+    }
+
+    /// Makes every column that is not locked at least as wide as in `other`,
+    /// locks the columns that are locked there, and adds the columns of
+    /// `other` that this layout does not have.
+    pub fn widen(&mut self, other: &Layout) {
+        for (col, other) in self.columns.iter_mut().zip(&other.columns) {
+            if other.locked {
+                col.size = other.size;
+                col.locked = true;
+            } else if !col.locked {
+                col.size = col.size.max(other.size);
+            }
+            col.numbers = match (col.numbers, other.numbers) {
+                (Some(a), Some(b)) => Some(NumberWidth {
+                    integer: a.integer.max(b.integer),
+                    fraction: a.fraction.max(b.fraction),
+                }),
+                _ => None,
+            };
+        }
+        if other.columns.len() > self.columns.len() {
+            let n = self.columns.len();
+            self.columns.extend_from_slice(&other.columns[n..]);
+        }
+    }
+
+    /// Writes a `cut -b` range and an `awk` expression for every printed
+    /// column that slice its values out of the output. `cut` counts bytes and
+    /// `awk` counts characters, so the delimiter is counted in each unit, but
+    /// the values are assumed to be ASCII: a value with other characters moves
+    /// the `cut` positions of the columns after it. The positions also assume
+    /// that no value overflowed its column.
+    pub fn write_cut_commands<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let delim_bytes = self.delim.len();
+        let delim_chars = String::from_utf8_lossy(&self.delim).chars().count();
+        let visible: Vec<(usize, usize)> = (1..)
+            .zip(&self.columns)
+            .filter(|(_, c)| !c.excluded)
+            .map(|(i, c)| (i, c.size))
+            .collect();
+        let (mut byte, mut char) = (1, 1);
+        let mut ranges = Vec::new();
+        for (j, &(i, size)) in visible.iter().enumerate() {
+            if j + 1 == visible.len() {
+                ranges.push(format!("{}-", byte));
+                writeln!(out, "column {}: cut -b {}-  awk '{{ print substr($0, {}) }}'", i, byte, char)?;
+            } else if size > 0 {
+                ranges.push(format!("{}-{}", byte, byte + size - 1));
+                writeln!(
+                    out,
+                    "column {}: cut -b {}-{}  awk '{{ print substr($0, {}, {}) }}'",
+                    i,
+                    byte,
+                    byte + size - 1,
+                    char,
+                    size
+                )?;
+            }
+            byte += size + delim_bytes;
+            char += size + delim_chars;
+        }
+        if visible.len() > 1 {
+            writeln!(out, "all columns: cut -b {}", ranges.join(","))?;
+        }
+        Ok(())
+    }
+
+    /// Reads a layout written by `write`. The delimiter is not part of the
+    /// file and is set to `delim`.
+    pub fn read<R: BufRead, S: Into<Vec<u8>>>(input: R, delim: S) -> Result<Layout> {
+        let mut columns = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: &str| Error::LayoutParseError {
+                line: i + 1,
+                msg: msg.to_string(),
+            };
+            let mut words = line.split_whitespace();
+            let size = words
+                .next()
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(|| err("expected a column size"))?;
+            let mut col = LayoutColumn {
+                size,
+                excluded: false,
+                locked: false,
+                numbers: None,
+            };
+            for word in words {
+                match word.split_once(':') {
+                    None if word == "excluded" => col.excluded = true,
+                    None if word == "locked" => col.locked = true,
+                    Some(("numeric", widths)) => {
+                        let (integer, fraction) = widths
+                            .split_once('.')
+                            .and_then(|(i, f)| Some((i.parse().ok()?, f.parse().ok()?)))
+                            .ok_or_else(|| err("expected numeric:INTEGER.FRACTION"))?;
+                        col.numbers = Some(NumberWidth { integer, fraction });
+                    }
+                    _ => return Err(err(&format!("unknown column flag '{}'", word))),
+                }
+            }
+            columns.push(col);
+        }
+        Ok(Layout {
+            columns,
+            delim: delim.into(),
+        })
+    }
+
+    /// Writes the layout with a line for each column: its size followed by
+    /// its flags.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "# tabulate layout: one line per column with its size and flags")?;
+        for col in &self.columns {
+            write!(out, "{}", col.size)?;
+            if col.excluded {
+                write!(out, " excluded")?;
+            }
+            if col.locked {
+                write!(out, " locked")?;
+            }
+            if let Some(w) = col.numbers {
+                write!(out, " numeric:{}.{}", w.integer, w.fraction)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a cell is printed on a line, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellPlacement {
+    pub start: usize,
+    pub end: usize,
+    /// The cell does not fit in what is left of its column and pushes the
+    /// following cells to the right.
+    pub overflows: bool,
+}
+
+/// Finds the cells of `row` on `line`, which was printed with `layout`, and
+/// checks them with `check_placements`. The row has a cell for every column,
+/// including the excluded ones, which are skipped. Cells must be printed
+/// whole, so a line with truncated cells does not verify. Like the column
+/// sizes, positions on the line are measured in display width.
+pub fn verify_layout(layout: &Layout, row: &Row, line: &[u8]) -> Result<Vec<CellPlacement>> {
+    if row.len() > layout.columns.len() {
+        return Err(Error::LayoutViolation {
+            column: layout.columns.len() + 1,
+            msg: format!("the row has {} cells but the layout only {} columns", row.len(), layout.columns.len()),
+        });
+    }
+    let visible: Vec<(usize, &[u8], usize)> = row
+        .get_parts()
+        .zip(&layout.columns)
+        .enumerate()
+        .filter(|(_, (_, col))| !col.excluded)
+        .map(|(i, (cell, col))| (i, cell, col.size))
+        .collect();
+
+    let mut placements = Vec::with_capacity(visible.len());
+    let mut pos = 0;
+    let mut column_start = 0;
+    for (n, &(i, cell, size)) in visible.iter().enumerate() {
+        // A cell starts at its column, unless the cells before it pushed it
+        // further to the right.
+        let from = pos.max(WIDTHS.truncate(line, column_start).0.len()).min(line.len());
+        let start = find(&line[from..], cell).map(|j| from + j).ok_or_else(|| Error::LayoutViolation {
+            column: i + 1,
+            msg: "the cell is not on the line".to_string(),
+        })?;
+        let end = start + cell.len();
+        placements.push(CellPlacement {
+            start,
+            end,
+            overflows: n + 1 < visible.len() && WIDTHS.width(&line[..end]) > column_start + size,
+        });
+        pos = end + layout.delim.len();
+        column_start += size + WIDTHS.width(&layout.delim);
+    }
+    check_placements(layout, line, &placements)?;
+    Ok(placements)
+}
+
+/// Checks where the visible cells of a row were printed on `line` against the
+/// columns of `layout`: cells must be on the line and come in order with a
+/// delimiter between them, never start before their column, and either end
+/// within their column or be flagged as overflowing. The last cell may end
+/// anywhere. The placements are in bytes, but they are compared with the
+/// columns by display width.
+pub fn check_placements(layout: &Layout, line: &[u8], placements: &[CellPlacement]) -> Result<()> {
+    let visible: Vec<(usize, usize)> = (0..)
+        .zip(&layout.columns)
+        .filter(|(_, col)| !col.excluded)
+        .map(|(i, col)| (i, col.size))
+        .collect();
+    if placements.len() > visible.len() {
+        return Err(Error::LayoutViolation {
+            column: layout.columns.len() + 1,
+            msg: format!("there are {} cells but only {} visible columns", placements.len(), visible.len()),
+        });
+    }
+
+    let delim = WIDTHS.width(&layout.delim);
+    let mut column_start = 0;
+    let mut prev_end: Option<usize> = None;
+    for (n, (placement, &(i, size))) in placements.iter().zip(&visible).enumerate() {
+        let violation = |msg: &str| Error::LayoutViolation {
+            column: i + 1,
+            msg: msg.to_string(),
+        };
+        let last = n + 1 == placements.len();
+        if placement.start > placement.end || placement.end > line.len() {
+            return Err(violation("the cell is not on the line"));
+        }
+        let start = WIDTHS.width(&line[..placement.start]);
+        let end = WIDTHS.width(&line[..placement.end]);
+        if prev_end.is_some_and(|prev_end| prev_end + delim > start) {
+            return Err(violation("the cell overlaps the previous one"));
+        }
+        if start < column_start {
+            return Err(violation("the cell starts before its column"));
+        }
+        if !last && !placement.overflows && end > column_start + size {
+            return Err(violation("the cell ends after its column but is not flagged"));
+        }
+        prev_end = Some(end);
+        column_start += size + delim;
+    }
+    Ok(())
+}
+
+/// Splits a line of aligned output back into the cells it was printed from.
+/// Excluded columns of the layout are skipped, as they were never printed.
+///
+/// This follows the same rules as the printer: a cell is padded to the size of
+/// its column, or pushes the following columns to the right when it is larger.
+/// Sizes are display widths, as for the printer. Trailing spaces of a cell are
+/// lost, as are the parts of truncated cells.
+pub fn parse_aligned<S: Into<Vec<u8>>>(line: S, layout: &Layout) -> Row {
+    let line = line.into();
+    let sizes: Vec<usize> = layout.visible_sizes().collect();
+    let mut spans = Vec::with_capacity(sizes.len());
+    let mut pos = 0;
+    let mut overflow = 0;
+    for (i, &size) in sizes.iter().enumerate() {
+        if i + 1 == sizes.len() {
+            spans.push((pos, line.len()));
+            break;
+        }
+        let out_width = size.saturating_sub(overflow);
+        let padded_end = pos + WIDTHS.truncate(&line[pos..], out_width).0.len();
+        let end = if line[padded_end..].starts_with(&layout.delim) || padded_end == line.len() {
+            pos + trim_end(&line[pos..padded_end]).len()
+        } else {
+            // The cell overflowed its column, so it ends at the next delimiter.
+            find(&line[padded_end..], &layout.delim)
+                .map(|j| padded_end + j)
+                .unwrap_or(line.len())
+        };
+        let cell_width = WIDTHS.width(&line[pos..end]);
+        overflow = if cell_width < size {
+            overflow.saturating_sub(size - cell_width)
+        } else {
+            overflow + (cell_width - size)
+        };
+        spans.push((pos, end));
+        pos = (end.max(padded_end) + layout.delim.len()).min(line.len());
+    }
+    Row::from_spans(line, spans)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(row: &Row) -> Vec<&[u8]> {
+        row.get_parts().collect()
+    }
+
+    #[test]
+    fn parse_padded_and_overflowing_cells() {
+        let layout = Layout::new(vec![3, 2, 4], "  ");
+
+        let row = parse_aligned("a    b   c", &layout);
+        assert_eq!(cells(&row), [&b"a"[..], b"b", b"c"]);
+
+        let row = parse_aligned("aaaaa  b  cc", &layout);
+        assert_eq!(cells(&row), [&b"aaaaa"[..], b"b", b"cc"]);
+
+        let row = parse_aligned("a", &layout);
+        assert_eq!(cells(&row), [&b"a"[..], b"", b""]);
+    }
+
+    #[test]
+    fn cut_commands() {
+        let mut layout = Layout::new(vec![3, 0, 5, 4], " │ ");
+        layout.columns[2].excluded = true;
+        let mut out = Vec::new();
+        layout.write_cut_commands(&mut out).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&out).unwrap(),
+            "column 1: cut -b 1-3  awk '{ print substr($0, 1, 3) }'\n\
+             column 4: cut -b 14-  awk '{ print substr($0, 10) }'\n\
+             all columns: cut -b 1-3,14-\n"
+        );
+        let line = "abc │  │ wxyz";
+        assert_eq!(&line.as_bytes()[13..], b"wxyz");
+        assert_eq!(line.chars().skip(9).collect::<String>(), "wxyz");
+
+        // Values with characters that take more than a byte move the byte
+        // positions, which `cut` counts, but not the character positions.
+        let line = "abé │  │ wxyz";
+        assert_ne!(&line.as_bytes()[13..], b"wxyz");
+        assert_eq!(line.chars().skip(9).collect::<String>(), "wxyz");
+    }
+
+    #[test]
+    fn round_trip() {
+        use crate::parser::RowParser;
+
+        let input = "aaa bbb ccc\na b c\naaaaaaaaaaaa bb cc\naaaaa b ccccc\n";
+        let opts = crate::Options {
+            lines: 2,
+            ..crate::Options::default()
+        };
+        let mut output = Vec::new();
+        crate::process(input.as_bytes(), &mut output, &opts).unwrap();
+
+        let layout = Layout::new(vec![3, 3, 3], "  ");
+        let parser = RowParser::new(" ", false);
+        for (printed, original) in output.split(|&b| b == b'\n').zip(input.lines()) {
+            let mut expected = Row::new();
+            parser.parse_into(&mut expected, original);
+            assert_eq!(cells(&parse_aligned(printed, &layout)), cells(&expected));
+        }
+    }
+
+    #[test]
+    fn printed_cells_match_verified_placement() {
+        use crate::generate::{Gen, Generate};
+        let mut rng = Gen::new(0x2545_f491_4f6c_dd1d);
+        for _ in 0..200 {
+            let ncols = 1 + rng.below(5);
+            let lines: Vec<String> = (0..1 + rng.below(8))
+                .map(|_| {
+                    let cells: Vec<String> = (0..1 + rng.below(ncols))
+                        .map(|_| "xyz1.".chars().cycle().skip(rng.below(5)).take(1 + rng.below(12)).collect())
+                        .collect();
+                    cells.join(" ")
+                })
+                .collect();
+            let opts = crate::Options {
+                lines: 1 + rng.below(4),
+                ratio: rng.below(3) as f64,
+                exclude_cols: Generate::generate(&mut rng),
+                left_align_numbers: true,
+                ..crate::Options::default()
+            };
+            let input = lines.join("\n");
+            let mut output = Vec::new();
+            let (_, layout) = crate::process_with_layout(input.as_bytes(), &mut output, &opts).unwrap();
+
+            let parser = crate::parser::RowParser::new(" ", false);
+            for (printed, line) in output.split(|&b| b == b'\n').zip(&lines) {
+                // Cells beyond the measured columns are not printed.
+                let cells: Vec<&str> = line.split(' ').take(layout.columns.len()).collect();
+                let mut row = Row::new();
+                parser.parse_into(&mut row, cells.join(" "));
+                let placements = verify_layout(&layout, &row, printed).unwrap();
+                let visible = row
+                    .get_parts()
+                    .zip(&layout.columns)
+                    .filter(|(_, col)| !col.excluded)
+                    .map(|(cell, _)| cell);
+                for (p, cell) in placements.iter().zip(visible) {
+                    assert_eq!(&printed[p.start..p.end], cell, "{:?} in {:?}", line, input);
+                }
+                assert_eq!(placements.last().map_or(0, |p| p.end), printed.len());
+            }
+        }
+    }
+
+    #[test]
+    fn verify_finds_cells_on_line() {
+        let mut row = Row::new();
+        crate::parser::RowParser::new(" ", false).parse_into(&mut row, "a b c");
+        assert!(verify_layout(&Layout::new(vec![1, 1], " "), &row, b"a b c").is_err());
+        let layout = Layout::new(vec![1, 0, 2], "  ");
+        let placements = verify_layout(&layout, &row, b"a  b  c").unwrap();
+        let spans: Vec<_> = placements.iter().map(|p| (p.start, p.end, p.overflows)).collect();
+        assert_eq!(spans, [(0, 1, false), (3, 4, true), (6, 7, false)]);
+        assert!(verify_layout(&layout, &row, b"a  x  c").is_err());
+        // The second cell runs into the delimiter before the third.
+        assert!(verify_layout(&layout, &row, b"a  bc").is_err());
+    }
+
+    #[test]
+    fn non_ascii_cells() {
+        let input = "名前 é x\nab cdé y\n名 c 名名\n";
+        let opts = crate::Options {
+            left_align_numbers: true,
+            ..crate::Options::default()
+        };
+        let mut output = Vec::new();
+        let (_, layout) = crate::process_with_layout(input.as_bytes(), &mut output, &opts).unwrap();
+        assert_eq!(layout.visible_sizes().collect::<Vec<_>>(), [4, 3, 4]);
+
+        let parser = crate::parser::RowParser::new(" ", false);
+        for (printed, line) in output.split(|&b| b == b'\n').zip(input.lines()) {
+            let mut row = Row::new();
+            parser.parse_into(&mut row, line);
+            assert_eq!(cells(&parse_aligned(printed, &layout)), cells(&row));
+            let placements = verify_layout(&layout, &row, printed).unwrap();
+            assert!(placements.iter().all(|p| !p.overflows));
+        }
+
+        // A wide cell that overflows its column is found and flagged.
+        let layout = Layout::new(vec![2, 1], " ");
+        let mut row = Row::new();
+        parser.parse_into(&mut row, "名名 x");
+        let printed = "名名 x".as_bytes();
+        assert_eq!(cells(&parse_aligned(printed, &layout)), cells(&row));
+        let placements = verify_layout(&layout, &row, printed).unwrap();
+        assert!(placements[0].overflows);
+    }
+
+    #[test]
+    fn check_placements_violations() {
+        let layout = Layout::new(vec![2, 2, 2], " ");
+        let line = b"aa bb cc";
+        let place = |spans: &[(usize, usize, bool)]| -> Vec<CellPlacement> {
+            spans.iter().map(|&(start, end, overflows)| CellPlacement { start, end, overflows }).collect()
+        };
+        let message = |spans: &[(usize, usize, bool)]| match check_placements(&layout, line, &place(spans)) {
+            Err(Error::LayoutViolation { column, msg }) => format!("{}: {}", column, msg),
+            other => panic!("{:?}", other),
+        };
+
+        assert!(check_placements(&layout, line, &place(&[(0, 2, false), (3, 5, false), (6, 8, false)])).is_ok());
+        assert!(check_placements(&layout, line, &place(&[(0, 3, true), (4, 5, false)])).is_ok());
+        assert_eq!(
+            message(&[(0, 2, false), (3, 5, false), (6, 8, false), (8, 8, false)]),
+            "4: there are 4 cells but only 3 visible columns"
+        );
+        assert_eq!(message(&[(0, 2, false), (3, 9, false)]), "2: the cell is not on the line");
+        assert_eq!(message(&[(0, 2, false), (2, 4, false)]), "2: the cell overlaps the previous one");
+        assert_eq!(message(&[(0, 1, false), (2, 4, false)]), "2: the cell starts before its column");
+        assert_eq!(message(&[(0, 3, false), (4, 5, false)]), "1: the cell ends after its column but is not flagged");
+    }
+
+    #[test]
+    fn read_write_and_widen() {
+        let text = "# comment\n12 numeric:3.2\n\n5 excluded\n6 locked\n";
+        let mut layout = Layout::read(text.as_bytes(), "  ").unwrap();
+        assert_eq!(layout.visible_sizes().collect::<Vec<_>>(), [12, 6]);
+        assert_eq!(
+            layout.columns[0].numbers,
+            Some(NumberWidth {
+                integer: 3,
+                fraction: 2
+            })
+        );
+
+        layout.widen(&Layout::new(vec![3, 8, 9, 4], "  "));
+        let mut out = Vec::new();
+        layout.write(&mut out).unwrap();
+        let written = String::from_utf8(out).unwrap();
+        assert_eq!(
+            written.lines().skip(1).collect::<Vec<_>>(),
+            ["12", "8 excluded", "6 locked", "4"]
+        );
+        assert_eq!(Layout::read(written.as_bytes(), "  ").unwrap(), layout);
+
+        assert!(Layout::read("x\n".as_bytes(), "  ").is_err());
+        assert!(Layout::read("1 wide\n".as_bytes(), "  ").is_err());
+    }
+}
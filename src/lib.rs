@@ -1,15 +1,73 @@
-use std::cmp::min;
+//! Aligning data in columns.
+//!
+//! The entry points, such as `process`, never panic on any input or options:
+//! bad values are either reported as an `Error` or handled as gracefully as
+//! possible. Internal invariants are only checked with `debug_assert!`.
+
+use std::collections::VecDeque;
+use std::iter::Peekable;
 use std::io::{self, BufRead, Write};
+use std::sync::mpsc;
 
-use crate::column::{Column, MeasureColumn};
+use crate::boolean::Booleans;
+use crate::color::{Style, Threshold};
+use crate::column::Column;
+use crate::compute::Computation;
+use crate::layout::Layout;
 use crate::errors::*;
-use crate::parser::{Row, RowParser};
-use crate::range::{Range, Ranges};
+use crate::parser::{ColumnDelimiter, Row, RowParser};
+use crate::pattern::{ColumnPattern, Extraction, Regex, Validation};
+use crate::printer::RowPrinter;
+use crate::project::Projection;
+use crate::range::{Range, RangeMap, Ranges};
+use crate::rename::Rename;
+use crate::sort::SortKey;
+use crate::spark::{Spark, SparkHistory};
+use crate::width::Widths;
+
+// The command line tool's helpers, which are not part of the library's API.
+#[doc(hidden)]
+pub use crate::atomic::AtomicFile;
+pub use crate::builder::OptionsBuilder;
+pub use crate::column::{Alignment, MeasureColumn, SizingStrategy};
 
-pub mod column;
+#[doc(hidden)]
+pub mod bench;
+pub mod boolean;
+pub mod color;
+pub mod compute;
+#[doc(hidden)]
+pub mod diff;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod generate;
+#[doc(hidden)]
+pub mod glob;
+pub mod layout;
+pub mod numeric;
 pub mod parser;
+pub mod pattern;
+pub mod project;
 pub mod range;
+pub mod rename;
+pub mod sort;
+pub mod spark;
+mod atomic;
+mod builder;
+mod column;
+mod detect;
+mod fit;
+mod printer;
 mod utils;
+mod width;
+
+/// The types most programs using the library need.
+pub mod prelude {
+    pub use crate::errors::{Error, ErrorKind, Result};
+    pub use crate::layout::Layout;
+    pub use crate::parser::{Row, RowParser};
+    pub use crate::range::{Range, Ranges};
+    pub use crate::{process, process_with_layout, Options, OptionsBuilder, Overflow, Summary};
+}
 
 pub mod errors {
     pub type Result<T> = std::result::Result<T, Error>;
@@ -24,6 +82,11 @@ pub mod errors {
             s: String
         },
 
+        #[error("could not parse '{}' as a list of ranges and a value", .s)]
+        RangeValueParseError {
+            s: String
+        },
+
         #[error("invalid decreasing range: {}", .s)]
         InvalidDecreasingRange {
             s: String
@@ -31,6 +94,209 @@ pub mod errors {
 
         #[error("columns are numbered starting from 1")]
         ColumnsStartAtOne,
+
+        #[error("could not parse '{}' as a column and a regular expression", .s)]
+        ColumnPatternParseError {
+            s: String
+        },
+
+        #[error("unknown style '{}'", .s)]
+        InvalidStyle {
+            s: String
+        },
+
+        #[error("no rows matched")]
+        NoMatch,
+
+        #[error("invalid regular expression '{}': {} at position {}", .pattern, .msg, .pos + 1)]
+        PatternError {
+            pattern: String,
+            msg: String,
+            pos: usize,
+        },
+
+        #[error("found {} rows with an unexpected number of columns, the first on line {}", .rows, .line)]
+        ColumnCheckFailed {
+            rows: usize,
+            line: usize,
+            /// The number of columns of the first row.
+            expected: usize,
+            /// The line and the number of columns of every row that failed.
+            mismatches: Vec<(usize, usize)>,
+        },
+
+        #[error("found {} rows with values that fail validation", .rows)]
+        ValidationFailed {
+            rows: usize,
+        },
+
+        #[error("invalid computation '{}': {} at position {}", .s, .msg, .pos + 1)]
+        ComputeParseError {
+            s: String,
+            msg: String,
+            pos: usize,
+        },
+
+        #[error("could not parse '{}' as a truthy and a falsy value separated by '/'", .s)]
+        BooleansParseError {
+            s: String
+        },
+
+        #[error("could not parse '{}' as the number of rows and columns of a table", .s)]
+        ShapeParseError {
+            s: String
+        },
+
+        #[error("could not parse '{}' as a column and an optional window size", .s)]
+        SparkParseError {
+            s: String
+        },
+
+        #[error("could not parse '{}' as a column and a list of thresholds", .s)]
+        ThresholdParseError {
+            s: String
+        },
+
+        #[error("could not parse '{}' as a column and a regular expression", .s)]
+        ValidationParseError {
+            s: String
+        },
+
+        #[error("could not parse '{}' as NAME=REGEX", .s)]
+        ExtractionParseError {
+            s: String
+        },
+
+        #[error("could not parse '{}' as a column and delimiters", .s)]
+        ColumnDelimiterParseError {
+            s: String
+        },
+
+        #[error("could not parse '{}' as a column and a sort order", .s)]
+        SortKeyParseError {
+            s: String
+        },
+
+        #[error("unknown sizing strategy '{}', expected auto, first-minimum or exhaustive", .s)]
+        SizingStrategyParseError {
+            s: String
+        },
+
+        #[error("unknown alignment '{}', expected left, right or center (or l, r or c)", .s)]
+        AlignmentParseError {
+            s: String
+        },
+
+        #[error("invalid layout on line {}: {}", .line, .msg)]
+        LayoutParseError {
+            line: usize,
+            msg: String,
+        },
+
+        #[error("layout does not hold for column {}: {}", .column, .msg)]
+        LayoutViolation {
+            column: usize,
+            msg: String,
+        },
+
+        #[error("could not parse '{}' as OLD=NEW", .s)]
+        RenameParseError {
+            s: String
+        },
+
+        #[error("{}", .problems.join("\n"))]
+        InvalidOptions {
+            problems: Vec<String>,
+        },
+
+        #[error("invalid projection '{}': {} at position {}", .s, .msg, .pos + 1)]
+        ProjectionParseError {
+            s: String,
+            msg: String,
+            pos: usize,
+        },
+
+        #[error("no files match '{}'", .s)]
+        NoFilesMatch {
+            s: String
+        },
+
+        #[error("no column is named '{}'", .s)]
+        UnknownColumn {
+            s: String
+        },
+    }
+
+    /// What an error is about, for deciding how to report it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ErrorKind {
+        /// Reading the input or writing the output failed.
+        Io,
+        /// An option has an invalid value.
+        Option,
+        /// The input does not have the expected shape.
+        Input,
+        /// A saved layout could not be read or does not fit a row.
+        Layout,
+    }
+
+    impl Error {
+        pub fn kind(&self) -> ErrorKind {
+            match self {
+                Error::Io(_) => ErrorKind::Io,
+                Error::NoMatch | Error::ColumnCheckFailed { .. } | Error::ValidationFailed { .. } => ErrorKind::Input,
+                Error::LayoutParseError { .. } | Error::LayoutViolation { .. } => ErrorKind::Layout,
+                _ => ErrorKind::Option,
+            }
+        }
+
+        /// The line number, starting at 1, of the input or layout file the
+        /// error was found on.
+        pub fn line(&self) -> Option<usize> {
+            match *self {
+                Error::ColumnCheckFailed { line, .. } | Error::LayoutParseError { line, .. } => Some(line),
+                _ => None,
+            }
+        }
+
+        /// The position, in characters starting at 1, in `fragment()` the
+        /// error was found at.
+        pub fn column(&self) -> Option<usize> {
+            match *self {
+                Error::PatternError { pos, .. }
+                | Error::ComputeParseError { pos, .. }
+                | Error::ProjectionParseError { pos, .. } => Some(pos + 1),
+                _ => None,
+            }
+        }
+
+        /// The option value the error was found in.
+        pub fn fragment(&self) -> Option<&str> {
+            match self {
+                Error::RangeParseError { s }
+                | Error::RangeValueParseError { s }
+                | Error::InvalidDecreasingRange { s }
+                | Error::ColumnPatternParseError { s }
+                | Error::InvalidStyle { s }
+                | Error::ComputeParseError { s, .. }
+                | Error::SparkParseError { s }
+                | Error::ShapeParseError { s }
+                | Error::BooleansParseError { s }
+                | Error::ThresholdParseError { s }
+                | Error::SortKeyParseError { s }
+                | Error::ColumnDelimiterParseError { s }
+                | Error::ExtractionParseError { s }
+                | Error::ValidationParseError { s }
+                | Error::SizingStrategyParseError { s }
+                | Error::AlignmentParseError { s }
+                | Error::RenameParseError { s }
+                | Error::NoFilesMatch { s }
+                | Error::UnknownColumn { s }
+                | Error::ProjectionParseError { s, .. } => Some(s),
+                Error::PatternError { pattern, .. } => Some(pattern),
+                _ => None,
+            }
+        }
     }
 }
 
@@ -38,22 +304,703 @@ pub mod errors {
 pub struct Options {
     pub truncate: Option<Ranges>,
     pub ratio: f64,
+    /// The ratio used for truncated columns instead of `ratio`. Values cut
+    /// off in a truncated column do not push the rest of the row right, so
+    /// these columns can be compressed harder.
+    pub truncate_ratio: Option<f64>,
+    /// Count the widths of the values of a column in logarithmic buckets
+    /// rather than exactly, to bound the memory and the time used to size
+    /// columns whose values vary a lot in width.
+    pub bucket_widths: bool,
+    /// How the size with the lowest score is searched for.
+    pub sizing_strategy: SizingStrategy,
+    /// Ignore the widest `ignore_outliers` percent of the values of each
+    /// column when sizing it.
+    pub ignore_outliers: f64,
     pub lines: usize,
+    /// Estimate the column sizes from as many rows as fit in this many bytes,
+    /// instead of from `lines` rows.
+    pub estimate_mem: Option<usize>,
     pub include_cols: Option<Ranges>,
     pub exclude_cols: Ranges,
-    pub delim: String,
-    pub output_delim: String,
+    /// Also include and exclude the columns with these headers. Columns are
+    /// only included by name if `include_cols` is `None`. A name that is not
+    /// in the header is an error.
+    pub include_names: Vec<Vec<u8>>,
+    pub exclude_names: Vec<Vec<u8>>,
+    pub delim: Vec<u8>,
+    pub output_delim: Vec<u8>,
     pub strict_delim: bool,
+    pub strict_delims: Vec<u8>,
     pub print_info: bool,
+    /// Print the sizes considered for each column, their costs and the size
+    /// chosen, before the table. Written to the same place as the column
+    /// information.
+    pub explain_sizing: bool,
+    /// The columns to print detailed information about. All if `None`.
+    pub info_cols: Option<Ranges>,
+    /// Also print detailed information about the columns with these headers.
+    pub info_names: Vec<Vec<u8>>,
     pub online: bool,
+    pub find: Option<ColumnPattern>,
+    /// Patterns the values of columns must match. The values that do not are
+    /// highlighted when colors are enabled and followed by `invalid_mark`
+    /// otherwise, and processing fails with `Error::ValidationFailed` at the
+    /// end.
+    pub validate: Vec<Validation>,
+    pub invalid_mark: Vec<u8>,
+    /// Hash the values of the printed cells into `Summary::checksum`, which
+    /// does not depend on how they were padded or truncated.
+    pub checksum: bool,
+    /// Print a comment line after the table with the number of rows and
+    /// columns printed and how many values were truncated.
+    pub footer_info: bool,
+    /// Split the output into pages of this many rows, each starting with a
+    /// form feed, except the first, and the header.
+    pub page_length: Option<usize>,
+    /// Start every page with a line with its number.
+    pub page_numbers: bool,
+    /// Leave out warnings. With `find`, also leave out the table, so only
+    /// the result tells whether a row matched.
+    pub quiet: bool,
+    pub color: bool,
+    pub fit: bool,
+    /// In fit mode, hide the least important columns when the others cannot
+    /// be made to fit without cutting off almost all of their values.
+    pub auto_hide: bool,
+    pub width: Option<usize>,
+    pub pin: Option<Ranges>,
+    /// Columns kept at full size against the right edge in fit mode.
+    pub right_edge: Option<Ranges>,
+    pub weights: RangeMap<f64>,
+    pub collapse_repeats: Option<Ranges>,
+    pub ditto_mark: Vec<u8>,
+    pub group_sep: Option<u32>,
+    pub group_rule: Option<char>,
+    pub follow: bool,
+    pub tail: Option<usize>,
+    /// In online and follow mode, size the columns from only the last this
+    /// many rows, instead of from the first `lines` rows, so the layout
+    /// adapts when the shape of the input changes.
+    pub window: Option<usize>,
+    pub header: bool,
+    pub header_floor: bool,
+    /// Print a rule of this character under the header, as wide as each
+    /// column or its header, whichever is wider. In online mode the header
+    /// and the rule are printed again when the sizes of the columns change.
+    pub underline_header: Option<char>,
+    /// The style of the header when colors are enabled.
+    pub header_style: Option<Style>,
+    /// Where the headers are placed in their columns. Each header is aligned
+    /// like the values of its column if `None`.
+    pub header_align: Option<Alignment>,
+    /// New names for the columns of the header.
+    pub rename: Vec<Rename>,
+    /// Read the header but do not print it.
+    pub hide_header: bool,
+    /// Print a JSON object per line describing the layout and each row,
+    /// instead of aligned text.
+    pub events: bool,
+    pub soft_wrap: Option<Vec<u8>>,
+    /// Leave out the spaces at the end of every line.
+    pub no_trailing_space: bool,
+    /// Draw a frame of box-drawing characters around the table, with lines
+    /// between the columns and a rule under the header. No column is made
+    /// narrower than its header.
+    pub border: bool,
+    pub check_columns: bool,
+    /// Warn if the column sizes estimated from the first `lines` rows were too
+    /// small for many of the later rows.
+    pub warn_estimate: bool,
+    pub project: Option<Projection>,
+    pub compute: Vec<Computation>,
+    /// Append columns with values taken from the lines by patterns. They come
+    /// after as many fields as `max_columns`, or else the header or the first
+    /// row has: shorter rows are padded with empty cells and the fields of
+    /// longer rows from the last of them on are merged into one.
+    pub extract: Vec<Extraction>,
+    pub percent: Option<u32>,
+    pub spark: Option<Spark>,
+    pub thresholds: Vec<Threshold>,
+    pub precision: RangeMap<usize>,
+    /// Values that mean a value is missing, such as `NULL` or `-`. They are
+    /// replaced by empty cells, which are not taken as text or numbers.
+    pub null_values: Vec<Vec<u8>>,
+    /// Printed instead of empty cells. Empty cells are then left out of the
+    /// smallest and largest values in the column information.
+    pub empty_value: Option<Vec<u8>>,
+    /// What truthy and falsy values are rewritten to, per column.
+    pub booleans: RangeMap<Booleans>,
+    /// Where the values of columns are placed. Columns not in the map are
+    /// right-aligned if they only hold numbers and left-aligned otherwise.
+    pub align: RangeMap<Alignment>,
+    /// Left-align the columns not in `align` even if they only hold numbers.
+    pub left_align_numbers: bool,
+    pub align_numbers: bool,
+    /// Never truncate columns that hold numbers, even if they are in
+    /// `truncate` or have to be shrunk to fit, since cutting digits off
+    /// changes the value. They overflow instead.
+    pub protect_numbers: bool,
+    /// Measure and pad values by their length in bytes instead of by the
+    /// number of terminal columns they take up.
+    pub byte_widths: bool,
+    /// ANSI control sequences in the input, such as colors, take up no space
+    /// when values are measured. They are still printed.
+    pub ignore_ansi: bool,
+    /// Print lines with nothing but whitespace and ANSI control sequences as
+    /// they are, without measuring them or splitting them into columns.
+    pub pass_ansi_lines: bool,
+    /// Only tabulate these lines of the input, counted from 1. The other
+    /// lines are printed as they were read, or left out with
+    /// `drop_other_lines`. With `header`, the first of these lines is the
+    /// header.
+    pub line_ranges: Option<Ranges>,
+    /// Only tabulate the longest run of lines that split into the same number
+    /// of fields, at least two, and treat the other lines like the lines
+    /// outside `line_ranges`. The whole input is read first.
+    pub auto_detect_table: bool,
+    pub drop_other_lines: bool,
+    pub sort: Vec<SortKey>,
+    /// A previous layout; columns are never made narrower than in it, and
+    /// its locked columns are always as wide as in it.
+    pub layout: Option<Layout>,
+    /// Only measure the columns of all input, without printing it.
+    pub measure_only: bool,
+    /// Only keep every `every`-th row, starting with the first.
+    pub every: usize,
+    /// Merge the cells from this column on into one cell, so rows with very
+    /// many fields do not make as many columns.
+    pub max_columns: Option<usize>,
+    /// Stop after this many rows, without reading the rest of the input.
+    pub preview: Option<usize>,
+    /// Exclude the columns whose value is the same in every measured row.
+    pub drop_constant: bool,
+    /// Print the `name=value` of every dropped column before the table.
+    pub show_dropped: bool,
+    /// Exclude the columns that are empty in every measured row.
+    pub drop_empty: bool,
+    /// Exclude the columns where this pattern matches every measured value.
+    pub exclude_matching: Option<Regex>,
+    /// Append the lines that match this pattern to the last column of the
+    /// row before them, instead of reading them as rows of their own.
+    pub continuation: Option<Regex>,
+    /// Join lines that end in a backslash with the line after them, without
+    /// the backslash, before they are split into columns.
+    pub join_continuations: bool,
+    /// Split the rest of the line after a column with other delimiters. The
+    /// rest is one cell when the delimiters are empty.
+    pub column_delims: Vec<ColumnDelimiter>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            truncate: None,
+            ratio: 1.0,
+            truncate_ratio: None,
+            sizing_strategy: SizingStrategy::Auto,
+            bucket_widths: false,
+            ignore_outliers: 0.0,
+            lines: 1000,
+            estimate_mem: None,
+            include_cols: None,
+            exclude_cols: Ranges::new(),
+            include_names: Vec::new(),
+            exclude_names: Vec::new(),
+            delim: b" \t".to_vec(),
+            output_delim: b"  ".to_vec(),
+            strict_delim: false,
+            strict_delims: Vec::new(),
+            print_info: false,
+            explain_sizing: false,
+            info_cols: None,
+            info_names: Vec::new(),
+            online: false,
+            find: None,
+            validate: Vec::new(),
+            invalid_mark: b"!".to_vec(),
+            checksum: false,
+            footer_info: false,
+            page_length: None,
+            page_numbers: false,
+            quiet: false,
+            color: false,
+            fit: false,
+            auto_hide: false,
+            width: None,
+            pin: None,
+            right_edge: None,
+            weights: RangeMap::new(),
+            collapse_repeats: None,
+            ditto_mark: b"\"".to_vec(),
+            group_sep: None,
+            group_rule: None,
+            follow: false,
+            tail: None,
+            window: None,
+            header: false,
+            header_floor: false,
+            underline_header: None,
+            header_style: None,
+            header_align: None,
+            rename: Vec::new(),
+            hide_header: false,
+            events: false,
+            soft_wrap: None,
+            no_trailing_space: false,
+            border: false,
+            check_columns: false,
+            warn_estimate: false,
+            project: None,
+            compute: Vec::new(),
+            extract: Vec::new(),
+            percent: None,
+            spark: None,
+            thresholds: Vec::new(),
+            precision: RangeMap::new(),
+            null_values: Vec::new(),
+            empty_value: None,
+            booleans: RangeMap::new(),
+            align: RangeMap::new(),
+            left_align_numbers: false,
+            align_numbers: false,
+            protect_numbers: false,
+            byte_widths: false,
+            ignore_ansi: false,
+            pass_ansi_lines: false,
+            line_ranges: None,
+            auto_detect_table: false,
+            drop_other_lines: false,
+            sort: Vec::new(),
+            layout: None,
+            measure_only: false,
+            every: 1,
+            max_columns: None,
+            preview: None,
+            drop_constant: false,
+            show_dropped: false,
+            drop_empty: false,
+            exclude_matching: None,
+            continuation: None,
+            join_continuations: false,
+            column_delims: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::new()
+    }
+
+    /// Checks the options for values that are out of range or that conflict
+    /// with each other, and describes every problem found.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let visible = |col: u32| {
+            self.include_cols.as_ref().is_none_or(|rs| rs.any_contains(col)) && !self.exclude_cols.any_contains(col)
+        };
+        if !self.ratio.is_finite() || self.ratio < 0.0 {
+            problems.push(format!("the ratio must be a number of at least 0, not {}", self.ratio));
+        }
+        if let Some(ratio) = self.truncate_ratio {
+            if !ratio.is_finite() || ratio < 0.0 {
+                problems.push(format!("the ratio of truncated columns must be a number of at least 0, not {}", ratio));
+            }
+            if self.truncate.is_none() {
+                problems.push("the ratio of truncated columns has no effect when no column is truncated".to_string());
+            }
+        }
+        if !(0.0..100.0).contains(&self.ignore_outliers) {
+            problems.push(format!(
+                "the percentage of outliers to ignore must be at least 0 and less than 100, not {}",
+                self.ignore_outliers
+            ));
+        }
+        if self.delim.is_empty() {
+            problems.push("the input delimiter is empty".to_string());
+        }
+        if self.width == Some(0) {
+            problems.push("the output width is 0".to_string());
+        }
+        if self.fit && self.width.is_none() {
+            problems.push("fitting to the output width needs a width, but none is known".to_string());
+        }
+        if self.soft_wrap.is_some() && self.fit {
+            problems.push("soft wrapping has no effect when fitting to the output width".to_string());
+        }
+        if self.border && (self.soft_wrap.is_some() || self.underline_header.is_some()) {
+            problems.push("a border can not be combined with soft wrapping or an underlined header".to_string());
+        }
+        for (_, weight) in &self.weights.0 {
+            if !weight.is_finite() || *weight < 0.0 {
+                problems.push(format!("column weights must be at least 0, not {}", weight));
+            }
+        }
+        if self.window.is_some() && !(self.online || self.follow) {
+            problems.push("a window of rows only applies in online and follow mode".to_string());
+        }
+        if self.tabulates_some_lines() && (!self.sort.is_empty() || self.percent.is_some()) {
+            problems.push("rows can not be sorted or get percentages when only some lines are tabulated".to_string());
+        }
+        if self.page_length == Some(0) {
+            problems.push("pages must have at least one row".to_string());
+        }
+        if self.page_numbers && self.page_length.is_none() {
+            problems.push("page numbers need a page length".to_string());
+        }
+        if self.window == Some(0) {
+            problems.push("the window of rows is empty".to_string());
+        }
+        if !self.fit && (self.pin.is_some() || self.right_edge.is_some() || !self.weights.0.is_empty()) {
+            problems.push("pinned and right edge columns and weights only apply when fitting to the output width".to_string());
+        }
+        if let Some(ref find) = self.find {
+            if !visible(find.column) {
+                problems.push(format!("the column searched for a match ({}) is excluded", find.column));
+            }
+        }
+        for threshold in &self.thresholds {
+            if !visible(threshold.column) {
+                problems.push(format!("column {} has thresholds but is excluded", threshold.column));
+            }
+        }
+        if let Some(col) = self.group_sep {
+            if !visible(col) {
+                problems.push(format!("rows are grouped by column {}, which is excluded", col));
+            }
+        }
+        problems
+    }
+
+    /// The delimiter printed between columns: the output delimiter, or a line
+    /// of the frame with `border`.
+    pub(crate) fn column_delim(&self) -> &[u8] {
+        if self.border {
+            " │ ".as_bytes()
+        } else {
+            &self.output_delim
+        }
+    }
+
+    /// Whether nothing is printed, since only whether a row matches `find`
+    /// is wanted.
+    pub(crate) fn only_status(&self) -> bool {
+        self.quiet && self.find.is_some()
+    }
+
+    /// Whether only some lines of the input are tabulated, because of
+    /// `line_ranges` or `auto_detect_table`.
+    pub(crate) fn tabulates_some_lines(&self) -> bool {
+        self.line_ranges.is_some() || self.auto_detect_table
+    }
+
+    /// Whether `row` is a line to print as it was read rather than a row of
+    /// the table, for `pass_ansi_lines` and the lines that are not tabulated.
+    /// Such lines are read as rows without cells.
+    pub(crate) fn passes_through(&self, row: &Row) -> bool {
+        (self.pass_ansi_lines || self.tabulates_some_lines()) && row.is_empty()
+    }
+
+    /// The columns, counted from 0, with values in `row` that fail
+    /// `validate`.
+    pub(crate) fn invalid_columns(&self, row: &Row) -> Vec<usize> {
+        self.validate
+            .iter()
+            .filter(|rule| !rule.is_valid(row))
+            .map(|rule| rule.column as usize - 1)
+            .collect()
+    }
+
+    /// The number of terminal columns the delimiter between columns takes up.
+    pub(crate) fn delim_width(&self) -> usize {
+        self.widths().width(self.column_delim())
+    }
+
+    /// How values are measured.
+    pub(crate) fn widths(&self) -> Widths {
+        Widths {
+            bytes: self.byte_widths,
+            ansi: self.ignore_ansi,
+        }
+    }
+}
+
+/// What was processed: how many rows, and how they were laid out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of rows read, not counting the header, the rows left out by
+    /// `Options::every` and `Options::validate`, or the lines printed as they
+    /// were read because they are not part of the table.
+    pub rows: usize,
+    /// The number of columns that were not excluded.
+    pub columns: usize,
+    /// The final sizes of the columns that were not excluded.
+    pub widths: Vec<usize>,
+    /// The number of printed cells that were cut off to fit their column.
+    pub truncated: usize,
+    /// The hash of the printed values, with `Options::checksum`.
+    pub checksum: Option<u64>,
+    /// The cells that did not fit the column sizes estimated from the first
+    /// rows, with `Options::warn_estimate`.
+    pub overflow: Option<Overflow>,
+    /// The columns hidden to fit the output width with `Options::auto_hide`,
+    /// by header or as `Column N`, the first time any were.
+    pub hidden: Vec<String>,
+}
+
+impl Summary {
+    fn new(columns: &[Column], rows: usize, truncated: usize) -> Summary {
+        let widths: Vec<usize> = columns.iter().filter(|c| !c.is_excluded()).map(|c| c.size()).collect();
+        Summary {
+            rows,
+            columns: widths.len(),
+            widths,
+            truncated,
+            checksum: None,
+            overflow: None,
+            hidden: Vec::new(),
+        }
+    }
+}
+
+/// Tabulates the rows read from `input` and writes them to `output`.
+pub fn process<R: BufRead, W: Write>(input: R, output: W, opts: &Options) -> Result<Summary> {
+    process_impl(input, output, None, None, opts).map(|(summary, _)| summary)
+}
+
+/// Like `process`, but also returns the layout of the columns once all input
+/// has been processed.
+pub fn process_with_layout<R: BufRead, W: Write>(input: R, output: W, opts: &Options) -> Result<(Summary, Layout)> {
+    process_impl(input, output, None, None, opts)
+}
+
+/// Like `process`, but the column information requested by
+/// `Options::print_info` is written to `info` while the data is still
+/// written to `output`.
+pub fn process_with_info<R: BufRead, W: Write, I: Write>(
+    input: R,
+    output: W,
+    mut info: I,
+    opts: &Options,
+) -> Result<Summary> {
+    process_impl(input, output, Some(&mut info), None, opts).map(|(summary, _)| summary)
+}
+
+/// Like `process`, but the rows that fail `Options::validate` are printed to
+/// `invalid` instead of the output.
+pub fn process_with_invalid<R: BufRead, W: Write, V: Write>(
+    input: R,
+    output: W,
+    mut invalid: V,
+    opts: &Options,
+) -> Result<Summary> {
+    process_impl(input, output, None, Some(&mut invalid), opts).map(|(summary, _)| summary)
+}
+
+/// Like `process`, but the rows that are left out by `Options::every` or fail
+/// `Options::validate` are written to `rejects` as they were read, instead of
+/// being dropped or printed.
+pub fn process_with_rejects<R: BufRead, W: Write, X: Write + Send>(
+    input: R,
+    output: W,
+    rejects: X,
+    opts: &Options,
+) -> Result<Summary> {
+    if opts.auto_detect_table {
+        let mut reader = RowReader::detecting_table(utils::byte_lines(input), opts)?;
+        reader.rejects = Some(Box::new(rejects));
+        return process_rows(reader, output, None, None, opts).map(|(summary, _)| summary);
+    }
+    let mut reader = RowReader::new(utils::byte_lines(input), opts);
+    reader.rejects = Some(Box::new(rejects));
+    process_rows(reader, output, None, None, opts).map(|(summary, _)| summary)
+}
+
+/// Tabulates two inputs with the same column sizes and prints them next to
+/// each other, separated by `|` between two output delimiters. The column
+/// sizes are measured from the first `Options::lines` rows of both inputs.
+pub fn process_side_by_side<L: BufRead, R: BufRead, W: Write>(
+    left: L,
+    right: R,
+    mut output: W,
+    opts: &Options,
+) -> Result<()> {
+    let mut measure_columns = Vec::new();
+    let mut sides = Vec::with_capacity(2);
+    for lines in [utils::byte_lines(Box::new(left) as Box<dyn BufRead>), utils::byte_lines(Box::new(right))] {
+        let mut reader = RowReader::new(lines, opts);
+        let header = match opts.header {
+            true => reader.read_header(opts)?,
+            false => None,
+        };
+        if let Some(ref header) = header {
+            set_header(&mut measure_columns, header, opts)?;
+        }
+        let mut rows = Vec::new();
+        let mut row = Row::new();
+        while reader.read_row(&mut row, opts)? {
+            if opts.lines == 0 || rows.len() < opts.lines {
+                update_columns(&mut measure_columns, &row, opts);
+            }
+            rows.push(std::mem::take(&mut row));
+        }
+        reader.check.finish()?;
+        sides.push((header, rows));
+    }
+
+    let mut columns = Vec::new();
+    layout_columns(&mut columns, &measure_columns, opts);
+    let mut printed = Vec::with_capacity(2);
+    for (header, rows) in &sides {
+        let mut buf = Vec::new();
+        let mut printer = RowPrinter::new(opts);
+        if let Some(header) = header {
+            printer.print_header(&mut buf, &columns[..], header)?;
+        }
+        for row in rows {
+            printer.print_row(&mut buf, &columns[..], row)?;
+        }
+        printed.push(buf);
+    }
+    let lines = |buf: &[u8]| -> Vec<Vec<u8>> {
+        match buf.strip_suffix(b"\n") {
+            Some(buf) => buf.split(|&b| b == b'\n').map(<[u8]>::to_vec).collect(),
+            None => Vec::new(),
+        }
+    };
+    let (left, right) = (lines(&printed[0]), lines(&printed[1]));
+    let line_width = |l: &[u8]| opts.widths().width(l);
+    let width = left.iter().map(|l| line_width(l)).max().unwrap_or(0);
+    let empty = Vec::new();
+    for i in 0..left.len().max(right.len()) {
+        let l = left.get(i).unwrap_or(&empty);
+        output.write_all(l)?;
+        write!(output, "{:1$}", "", width - line_width(l))?;
+        output.write_all(&opts.output_delim)?;
+        output.write_all(b"|")?;
+        match right.get(i) {
+            Some(r) if !r.is_empty() => {
+                output.write_all(&opts.output_delim)?;
+                output.write_all(r)?;
+            }
+            _ => {}
+        }
+        output.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Measures the columns of two inputs and prints how each column differs
+/// between them: its estimated size, its largest value and whether it holds
+/// numbers or text. The columns are measured from the first
+/// `Options::lines` rows of each input.
+pub fn column_info_diff<L: BufRead, R: BufRead, W: Write>(
+    left: L,
+    right: R,
+    mut output: W,
+    opts: &Options,
+) -> Result<()> {
+    let left = measure_input(left, opts)?;
+    let right = measure_input(right, opts)?;
+    let describe = |measured: &MeasureColumn| {
+        let col = measured.calculate_size(sizing_ratio(measured, opts));
+        let kind = if measured.is_empty() {
+            "empty"
+        } else if col.is_numeric() {
+            "number"
+        } else {
+            "text"
+        };
+        (col.size(), col.max_size(), kind)
+    };
+    for i in 0..left.len().max(right.len()) {
+        write!(output, "Column {}: ", i + 1)?;
+        match (left.get(i).map(describe), right.get(i).map(describe)) {
+            (Some(a), Some(b)) if a == b => {
+                writeln!(output, "same (size {}, max {}, {})", a.0, a.1, a.2)?;
+            }
+            (Some(a), Some(b)) => {
+                let mut changes = Vec::new();
+                if a.0 != b.0 {
+                    changes.push(format!("size {} -> {}", a.0, b.0));
+                }
+                if a.1 != b.1 {
+                    changes.push(format!("max {} -> {}", a.1, b.1));
+                }
+                if a.2 != b.2 {
+                    changes.push(format!("type {} -> {}", a.2, b.2));
+                }
+                writeln!(output, "{}", changes.join(", "))?;
+            }
+            (Some(a), None) => writeln!(output, "only in the first input (size {}, max {}, {})", a.0, a.1, a.2)?,
+            (None, Some(b)) => writeln!(output, "only in the second input (size {}, max {}, {})", b.0, b.1, b.2)?,
+            (None, None) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Measures the columns of the first `Options::lines` rows of `input`.
+fn measure_input<R: BufRead>(input: R, opts: &Options) -> Result<Vec<MeasureColumn>> {
+    let mut reader = RowReader::new(utils::byte_lines(input), opts);
+    let mut columns = Vec::new();
+    if opts.header {
+        if let Some(header) = reader.read_header(opts)? {
+            set_header(&mut columns, &header, opts)?;
+        }
+    }
+    let mut row = Row::new();
+    let mut measured = 0;
+    while (opts.lines == 0 || measured < opts.lines) && reader.read_row(&mut row, opts)? {
+        update_columns(&mut columns, &row, opts);
+        measured += 1;
+    }
+    Ok(columns)
+}
+
+/// Like `process`, but the input is read and parsed into rows on another
+/// thread while the rows are measured and printed on this one.
+pub fn process_threaded<R: BufRead + Send, W: Write>(input: R, output: W, opts: &Options) -> Result<Summary> {
+    // All of the input is read before the table is found, so there is
+    // nothing for another thread to do.
+    if opts.auto_detect_table {
+        return process(input, output, opts);
+    }
+    std::thread::scope(|scope| {
+        let (rows, received) = mpsc::sync_channel(THREADED_ROWS);
+        scope.spawn(move || send_rows(RowReader::new(utils::byte_lines(input), opts), rows, opts));
+        process_rows(ThreadedReader::new(received), output, None, None, opts).map(|(summary, _)| summary)
+    })
 }
 
-pub fn process<R: BufRead, W: Write>(input: R, mut output: W, opts: &Options) -> Result<()> {
+fn process_impl<R: BufRead, W: Write>(
+    input: R,
+    output: W,
+    info: Option<&mut dyn Write>,
+    invalid: Option<&mut dyn Write>,
+    opts: &Options,
+) -> Result<(Summary, Layout)> {
+    if opts.auto_detect_table {
+        let reader = RowReader::detecting_table(utils::byte_lines(input), opts)?;
+        return process_rows(reader, output, info, invalid, opts);
+    }
+    process_rows(RowReader::new(utils::byte_lines(input), opts), output, info, invalid, opts)
+}
+
+fn process_rows<S: ReadRows, W: Write>(
+    mut reader: S,
+    mut output: W,
+    mut info: Option<&mut dyn Write>,
+    invalid: Option<&mut dyn Write>,
+    opts: &Options,
+) -> Result<(Summary, Layout)> {
     #[derive(Debug)]
     enum ProcessingState {
         Measuring {
             lines_measured: usize,
             backlog: Vec<Row>,
+            backlog_bytes: usize,
         },
         PrintBacklog {
             backlog: Vec<Row>,
@@ -64,42 +1011,85 @@ pub fn process<R: BufRead, W: Write>(input: R, mut output: W, opts: &Options) ->
     let mut state = ProcessingState::Measuring {
         lines_measured: 1,
         backlog: Vec::new(),
+        backlog_bytes: 0,
     };
     let mut measure_columns = Vec::new();
     let mut columns = Vec::new();
-    let parser = RowParser::new(opts.delim.clone(), opts.strict_delim);
+    // The sizes of the columns before they are dropped or fitted, kept up to
+    // date in online mode.
+    let mut sized = Vec::new();
     let mut row = Row::new();
-    let mut lines = input.lines();
+    let mut printer = RowPrinter::new(opts);
+    printer.invalid = invalid.map(|out| out as &mut dyn Write);
+    let mut overflow = Overflow::default();
+    let mut hidden = Vec::new();
+    let mut rows_read = 0;
+    // The rows measured in online mode with `Options::window`.
+    let mut window: VecDeque<Row> = VecDeque::new();
+
+    let mut header = None;
+    if opts.header {
+        if let Some(row) = reader.read_header(opts)? {
+            set_header(&mut measure_columns, &row, opts)?;
+            header = Some(row);
+        }
+    }
+
+    if let Some(n) = opts.tail {
+        let (columns, summary) = process_tail(&mut reader, output, header.as_ref(), measure_columns, n, opts)?;
+        reader.finish()?;
+        return Ok((summary, Layout::from_columns(&columns, opts.output_delim.clone())));
+    }
 
+    let online = opts.online || opts.follow;
+    if let (true, Some(header)) = (online, header.as_ref()) {
+        layout_columns(&mut columns, &measure_columns, opts);
+        printer.print_header(&mut output, &columns[..], header)?;
+    }
     loop {
         state = match state {
             ProcessingState::Measuring {
                 lines_measured,
                 mut backlog,
+                mut backlog_bytes,
             } => {
-                if let Some(line) = lines.next() {
-                    let line = line?;
-                    parser.parse_into(&mut row, line);
-                    update_columns(
-                        &mut measure_columns,
-                        &row,
-                        opts.include_cols.as_ref(),
-                        &opts.exclude_cols,
-                        opts.truncate.as_ref(),
-                        opts.print_info,
-                    );
-                    if opts.online {
-                        columns.clear();
-                        columns
-                            .extend(measure_columns.iter().map(|c| c.calculate_size(opts.ratio)));
-                        print_row(&mut output, &columns[..], &row, &opts.output_delim)?;
-                    } else {
+                if reader.read_row(&mut row, opts)? {
+                    if !opts.passes_through(&row) {
+                        rows_read += 1;
+                    }
+                    update_columns(&mut measure_columns, &row, opts);
+                    if let (true, Some(n)) = (online, opts.window) {
+                        window.push_back(row.clone());
+                        if window.len() > n {
+                            if let Some(old) = window.pop_front() {
+                                for (col, cell) in measure_columns.iter_mut().zip(old.get_parts()) {
+                                    col.remove_sample(cell);
+                                }
+                            }
+                        }
+                    }
+                    if online {
+                        if update_layout(&mut columns, &mut sized, &measure_columns, opts) {
+                            note_hidden_columns(&columns, &mut hidden, opts);
+                        }
+                        printer.print_row(&mut output, &columns[..], &row)?;
+                        if opts.follow {
+                            output.flush()?;
+                        }
+                    } else if !opts.measure_only {
+                        backlog_bytes += memory_size(&row);
                         backlog.push(row.clone());
                     }
-                    if opts.lines == 0 || lines_measured < opts.lines {
+                    let measure_more = match opts.estimate_mem {
+                        Some(budget) => backlog_bytes < budget,
+                        None if online && opts.window.is_some() => true,
+                        None => opts.lines == 0 || lines_measured < opts.lines,
+                    };
+                    if opts.measure_only || measure_more {
                         ProcessingState::Measuring {
                             lines_measured: lines_measured + 1,
                             backlog,
+                            backlog_bytes,
                         }
                     } else {
                         ProcessingState::PrintBacklog { backlog }
@@ -109,29 +1099,57 @@ pub fn process<R: BufRead, W: Write>(input: R, mut output: W, opts: &Options) ->
                 }
             }
             ProcessingState::PrintBacklog { backlog } => {
-                columns.clear();
-                columns.extend(measure_columns.iter().map(|c| c.calculate_size(opts.ratio)));
+                layout_columns(&mut columns, &measure_columns, opts);
+                note_hidden_columns(&columns, &mut hidden, opts);
+                if opts.measure_only {
+                    break;
+                }
+
+                if opts.explain_sizing {
+                    match info {
+                        Some(ref mut info) => print_sizing_explanation(*info, &columns, &measure_columns, opts)?,
+                        None => print_sizing_explanation(&mut output, &columns, &measure_columns, opts)?,
+                    }
+                }
 
                 if opts.print_info {
-                    for (i, col) in columns.iter_mut().enumerate() {
-                        writeln!(output, "Column {}", i + 1)?;
-                        col.print_info(&mut output)?;
-                        writeln!(output)?;
+                    match info {
+                        Some(ref mut info) => print_column_info(*info, &mut columns, opts)?,
+                        None => {
+                            print_column_info(&mut output, &mut columns, opts)?;
+                            let summary = Summary::new(&columns, rows_read, 0);
+                            return Ok((summary, Layout::from_columns(&columns, opts.output_delim.clone())));
+                        }
                     }
-                    return Ok(());
                 }
 
+                if opts.show_dropped {
+                    print_dropped_columns(&mut output, &columns, &measure_columns, header.as_ref())?;
+                }
+                if let (false, Some(header)) = (online, header.as_ref()) {
+                    printer.print_header(&mut output, &columns[..], header)?;
+                }
                 for row in backlog {
-                    print_row(&mut output, &columns[..], &row, &opts.output_delim)?;
+                    printer.print_row(&mut output, &columns[..], &row)?;
                 }
 
                 ProcessingState::ProcessInput
             }
             ProcessingState::ProcessInput => {
-                if let Some(line) = lines.next() {
-                    let line = line?;
-                    parser.parse_into(&mut row, line);
-                    print_row(&mut output, &columns[..], &row, &opts.output_delim)?;
+                if opts.only_status() && printer.matched_rows > 0 {
+                    break;
+                }
+                if reader.read_row(&mut row, opts)? {
+                    if !opts.passes_through(&row) {
+                        rows_read += 1;
+                    }
+                    if opts.warn_estimate {
+                        overflow.check_row(&columns, &row, opts.widths());
+                    }
+                    printer.print_row(&mut output, &columns[..], &row)?;
+                    if opts.follow {
+                        output.flush()?;
+                    }
 
                     ProcessingState::ProcessInput
                 } else {
@@ -141,78 +1159,1030 @@ pub fn process<R: BufRead, W: Write>(input: R, mut output: W, opts: &Options) ->
         }
     }
 
-    Ok(())
+    printer.finish(&mut output, &columns)?;
+    if opts.footer_info && !opts.measure_only {
+        printer.print_footer(&mut output, &columns)?;
+    }
+    let layout = Layout::from_columns(&columns, opts.output_delim.clone());
+    if opts.find.is_some() && !opts.measure_only && printer.matched_rows == 0 {
+        return Err(Error::NoMatch);
+    }
+    reader.finish()?;
+    if printer.invalid_rows > 0 && !opts.measure_only {
+        return Err(Error::ValidationFailed {
+            rows: printer.invalid_rows,
+        });
+    }
+    let summary = Summary {
+        checksum: printer.checksum(),
+        overflow: opts.warn_estimate.then_some(overflow),
+        hidden,
+        ..Summary::new(&columns, rows_read, printer.truncated_cells())
+    };
+    Ok((summary, layout))
 }
 
-fn update_columns(
-    columns: &mut Vec<MeasureColumn>,
-    row: &Row,
-    include_cols: Option<&Ranges>,
-    excluded_cols: &Ranges,
-    truncate_cols: Option<&Ranges>,
-    collect_info: bool,
-) {
-    for i in 0..min(columns.len(), row.len()) {
-        columns[i].add_sample(&row[i]);
-    }
-    #[allow(clippy::needless_range_loop)]
-    for i in columns.len()..row.len() {
-        let mut col = MeasureColumn::new(collect_info);
-        let col_num = (i + 1) as u32;
-
-        let included = include_cols
-            .map(|rs| rs.any_contains(col_num))
-            .unwrap_or(true);
-
-        let excluded = excluded_cols.any_contains(col_num);
+/// A source of the rows to measure and print.
+trait ReadRows {
+    fn read_header(&mut self, opts: &Options) -> Result<Option<Row>>;
 
-        let truncated = truncate_cols
-            .map(|rs| rs.any_contains(col_num))
-            .unwrap_or(false);
+    /// Reads the next row into `row`. Returns false at the end of the rows.
+    fn read_row(&mut self, row: &mut Row, opts: &Options) -> Result<bool>;
 
-        col.set_excluded(!included || excluded);
-        col.set_truncated(truncated);
+    /// Called after the last row; fails if some rows did not pass
+    /// `Options::check_columns`.
+    fn finish(&mut self) -> Result<()>;
+}
 
-        col.add_sample(&row[i]);
+impl<I: Iterator<Item = io::Result<Vec<u8>>>> ReadRows for RowReader<'_, I> {
+    fn read_header(&mut self, opts: &Options) -> Result<Option<Row>> {
+        RowReader::read_header(self, opts)
+    }
 
-        columns.push(col);
+    fn read_row(&mut self, row: &mut Row, opts: &Options) -> Result<bool> {
+        RowReader::read_row(self, row, opts)
     }
-}
 
-fn print_row<W: Write>(out: &mut W, columns: &[Column], row: &Row, output_delim: &str) -> io::Result<()> {
-    let mut overflow: usize = 0;
-    for ((cell, col), first, last) in utils::first_last_iter(
-        row.get_parts()
-            .zip(columns)
-            .filter(|&(_, col)| !col.is_excluded()),
-    ) {
-        if !first {
-            write!(out, "{}", output_delim)?;
+    fn finish(&mut self) -> Result<()> {
+        if let Some(ref mut rejects) = self.rejects {
+            rejects.flush()?;
         }
-        overflow = col.print_cell(out, cell, overflow, last)?;
+        self.check.finish()
     }
-    writeln!(out)?;
-    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::BufReader;
+/// The number of rows that can be waiting between the threads of
+/// `process_threaded`.
+const THREADED_ROWS: usize = 1024;
 
-    #[test]
-    fn basic_test() {
-        let opts = Options {
-            truncate: None,
-            ratio: 1.0,
-            lines: 1000,
-            include_cols: None,
+/// What the reading thread of `process_threaded` sends.
+enum Message {
+    Header(Option<Row>),
+    Row(Row),
+    /// Reading the input failed.
+    Error(Error),
+    /// All rows were sent, and the result of `ReadRows::finish`.
+    End(Result<()>),
+}
+
+/// Reads the rows of `reader` and sends them, until the input ends or the
+/// receiver goes away.
+fn send_rows<I>(mut reader: RowReader<'_, I>, rows: mpsc::SyncSender<Message>, opts: &Options)
+where
+    I: Iterator<Item = io::Result<Vec<u8>>>,
+{
+    let mut send = || -> Result<bool> {
+        if opts.header {
+            let header = reader.read_header(opts)?;
+            if rows.send(Message::Header(header)).is_err() {
+                return Ok(false);
+            }
+        }
+        let mut row = Row::new();
+        while reader.read_row(&mut row, opts)? {
+            if rows.send(Message::Row(std::mem::replace(&mut row, Row::new()))).is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    };
+    // Nobody is listening if sending fails, so there is nothing to do then.
+    let _ = match send() {
+        Ok(true) => rows.send(Message::End(reader.check.finish())),
+        Ok(false) => Ok(()),
+        Err(e) => rows.send(Message::Error(e)),
+    };
+}
+
+/// Receives the rows read on the other thread of `process_threaded`.
+struct ThreadedReader {
+    rows: mpsc::Receiver<Message>,
+    end: Option<Result<()>>,
+}
+
+impl ThreadedReader {
+    fn new(rows: mpsc::Receiver<Message>) -> ThreadedReader {
+        ThreadedReader { rows, end: None }
+    }
+}
+
+impl ReadRows for ThreadedReader {
+    fn read_header(&mut self, opts: &Options) -> Result<Option<Row>> {
+        debug_assert!(opts.header);
+        match self.rows.recv() {
+            Ok(Message::Header(header)) => Ok(header),
+            Ok(Message::Error(e)) => Err(e),
+            Ok(Message::Row(_)) | Ok(Message::End(_)) | Err(_) => Ok(None),
+        }
+    }
+
+    fn read_row(&mut self, row: &mut Row, _opts: &Options) -> Result<bool> {
+        match self.rows.recv() {
+            Ok(Message::Row(r)) => {
+                *row = r;
+                Ok(true)
+            }
+            Ok(Message::Error(e)) => Err(e),
+            Ok(Message::End(result)) => {
+                self.end = Some(result);
+                Ok(false)
+            }
+            Ok(Message::Header(_)) | Err(_) => Ok(false),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.end.take().unwrap_or(Ok(()))
+    }
+}
+
+/// Parses input lines into rows and applies the transformations of the rows
+/// that happen before they are measured and printed.
+struct RowReader<'a, I: Iterator> {
+    lines: Peekable<I>,
+    parser: RowParser,
+    /// The parsers for the rest of the line after a column, by column, or
+    /// `None` to keep the rest as one cell.
+    rest_parsers: Vec<(usize, Option<RowParser>)>,
+    check: ColumnCheck,
+    spark: Option<SparkHistory>,
+    /// All remaining rows with the lines they were read from, when a
+    /// transformation needs to see every row first.
+    buffered: Option<VecDeque<(Row, Vec<u8>)>>,
+    /// Where to write the lines of rejected rows, and the line of the last
+    /// row read, with its continuation lines, if there is such a place.
+    rejects: Option<Box<dyn Write + Send + 'a>>,
+    original: Vec<u8>,
+    /// The number of rows read so far, for `every`, and how many of them
+    /// were kept, for `preview`.
+    rows_read: usize,
+    rows_kept: usize,
+    /// The number of lines read so far, whether the last of them is not to be
+    /// tabulated, and the lines that are not that were read before the
+    /// header, to be printed before the rows.
+    lines_read: usize,
+    outside: bool,
+    before_header: VecDeque<Row>,
+    /// The lines of the table found for `Options::auto_detect_table`.
+    table_lines: Option<Ranges>,
+    /// The number of fields the columns of `Options::extract` come after,
+    /// once it is known.
+    extract_after: Option<usize>,
+}
+
+impl<'a, I: Iterator<Item = io::Result<Vec<u8>>>> RowReader<'a, I> {
+    fn new(lines: I, opts: &Options) -> RowReader<'a, I> {
+        let parser = row_parser(opts);
+        let mut rest_parsers: Vec<(usize, Option<RowParser>)> = opts
+            .column_delims
+            .iter()
+            .map(|d| {
+                let parser = (!d.delim.is_empty()).then(|| RowParser::new(d.delim.clone(), opts.strict_delim));
+                (d.column as usize, parser)
+            })
+            .collect();
+        rest_parsers.sort_by_key(|&(column, _)| column);
+        RowReader {
+            lines: lines.peekable(),
+            parser,
+            rest_parsers,
+            check: ColumnCheck::new(),
+            spark: opts.spark.map(SparkHistory::new),
+            buffered: None,
+            rejects: None,
+            original: Vec::new(),
+            rows_read: 0,
+            rows_kept: 0,
+            lines_read: 0,
+            outside: false,
+            before_header: VecDeque::new(),
+            table_lines: None,
+            extract_after: None,
+        }
+    }
+
+    fn read_header(&mut self, opts: &Options) -> Result<Option<Row>> {
+        while let Some(line) = self.lines.next() {
+            let line = line?;
+            if self.next_line_outside(opts) {
+                if !opts.drop_other_lines {
+                    self.before_header.push_back(Row::from_spans(line, Vec::new()));
+                }
+                continue;
+            }
+            let mut row = Row::new();
+            self.read_header_into(&mut row, line, opts);
+            return Ok(Some(row));
+        }
+        Ok(None)
+    }
+
+    /// Counts a line that is read and tells whether it is outside
+    /// `Options::line_ranges` or the table that was found.
+    fn next_line_outside(&mut self, opts: &Options) -> bool {
+        self.lines_read += 1;
+        let line = self.lines_read as u32;
+        self.outside = [&opts.line_ranges, &self.table_lines]
+            .iter()
+            .any(|ranges| ranges.as_ref().is_some_and(|ranges| !ranges.any_contains(line)));
+        self.outside
+    }
+
+    /// Reads the next row that is kept by `every` into `row`. Returns false at
+    /// the end of the input, or once `preview` rows have been read. With
+    /// rejects, the rows that are not kept or fail validation are written
+    /// there.
+    fn read_row(&mut self, row: &mut Row, opts: &Options) -> Result<bool> {
+        if opts.preview.is_some_and(|n| self.rows_kept >= n) {
+            return Ok(false);
+        }
+        while self.next_row(row, opts)? {
+            if opts.passes_through(row) {
+                if self.outside && opts.drop_other_lines {
+                    continue;
+                }
+                return Ok(true);
+            }
+            self.rows_read += 1;
+            let sampled = (self.rows_read - 1).is_multiple_of(opts.every.max(1));
+            let valid = self.rejects.is_none() || opts.validate.iter().all(|rule| rule.is_valid(row));
+            if sampled && valid {
+                self.rows_kept += 1;
+                return Ok(true);
+            }
+            if let Some(ref mut rejects) = self.rejects {
+                rejects.write_all(&self.original)?;
+                rejects.write_all(b"\n")?;
+            }
+        }
+        Ok(false)
+    }
+
+    fn next_row(&mut self, row: &mut Row, opts: &Options) -> Result<bool> {
+        if opts.percent.is_some() || !opts.sort.is_empty() {
+            if self.buffered.is_none() {
+                let mut rows = Vec::new();
+                while let Some(line) = self.lines.next() {
+                    let mut row = Row::new();
+                    self.read_into(&mut row, line?, opts);
+                    rows.push((row, std::mem::take(&mut self.original)));
+                }
+                rows.sort_by(|a, b| sort::compare_rows(&a.0, &b.0, &opts.sort));
+                let (mut rows, originals): (Vec<Row>, Vec<Vec<u8>>) = rows.into_iter().unzip();
+                // Columns are counted from 1, so 0 is no column.
+                if let Some(col) = opts.percent.and_then(|col| (col as usize).checked_sub(1)) {
+                    crate::compute::append_percentages(&mut rows, col);
+                }
+                self.buffered = Some(rows.into_iter().zip(originals).collect());
+            }
+            return Ok(match self.buffered.as_mut().and_then(|rows| rows.pop_front()) {
+                Some((r, original)) => {
+                    *row = r;
+                    self.original = original;
+                    true
+                }
+                None => false,
+            });
+        }
+        if let Some(r) = self.before_header.pop_front() {
+            *row = r;
+            self.outside = true;
+            return Ok(true);
+        }
+        match self.lines.next() {
+            Some(line) => {
+                self.read_into(row, line?, opts);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn read_into(&mut self, row: &mut Row, line: Vec<u8>, opts: &Options) {
+        // Such lines are kept as rows without cells, which are printed as
+        // they were read.
+        let outside = self.next_line_outside(opts);
+        if outside || (opts.pass_ansi_lines && width::is_blank(&line)) {
+            if self.rejects.is_some() {
+                self.original.clone_from(&line);
+            }
+            *row = Row::from_spans(line, Vec::new());
+            return;
+        }
+        self.parse_into(row, line, opts);
+        if !row.is_empty() && self.extract_after.is_none() {
+            self.extract_after = Some(opts.max_columns.unwrap_or(row.len()));
+        }
+        pattern::apply_extractions(row, &opts.extract, self.extract_after.unwrap_or(0));
+        for computation in &opts.compute {
+            computation.apply(row);
+        }
+        clear_null_values(row, &opts.null_values);
+        numeric::apply_precision(row, &opts.precision);
+        boolean::apply_booleans(row, &opts.booleans);
+        if let Some(ref mut spark) = self.spark {
+            spark.apply(row);
+        }
+    }
+
+    /// Like `read_into`, but computed columns get their name instead of a value.
+    fn read_header_into(&mut self, row: &mut Row, line: Vec<u8>, opts: &Options) {
+        self.parse_into(row, line, opts);
+        if !opts.extract.is_empty() {
+            let fields = *self.extract_after.get_or_insert(opts.max_columns.unwrap_or(row.len()));
+            row.set_len(fields);
+        }
+        for extraction in &opts.extract {
+            row.push(extraction.name().as_bytes());
+        }
+        for computation in &opts.compute {
+            row.push(computation.name().as_bytes());
+        }
+        if opts.spark.is_some() {
+            row.push(b"spark");
+        }
+        if opts.percent.is_some() {
+            row.push(b"%");
+        }
+        rename::rename_header(row, &opts.rename);
+    }
+
+    fn parse_into(&mut self, row: &mut Row, mut line: Vec<u8>, opts: &Options) {
+        if self.rejects.is_some() {
+            self.original.clone_from(&line);
+        }
+        if opts.join_continuations {
+            // A failed read is left for the next row to report.
+            while ends_in_backslash(&line) {
+                let next = match self.lines.next_if(|l| l.is_ok()) {
+                    Some(Ok(next)) => next,
+                    _ => break,
+                };
+                self.lines_read += 1;
+                if self.rejects.is_some() {
+                    self.original.push(b'\n');
+                    self.original.extend_from_slice(&next);
+                }
+                line.pop();
+                line.extend_from_slice(&next);
+            }
+        }
+        self.parser.parse_into(row, line);
+        for (column, parser) in &self.rest_parsers {
+            match parser {
+                Some(parser) => parser.parse_rest_into(row, *column),
+                None => row.merge_from(column + 1),
+            }
+        }
+        if let Some(ref pattern) = opts.continuation {
+            // A failed read is left for the next row to report.
+            while let Some(Ok(line)) = self.lines.next_if(|l| l.as_ref().is_ok_and(|l| pattern.is_match(l))) {
+                self.lines_read += 1;
+                if self.rejects.is_some() {
+                    self.original.push(b'\n');
+                    self.original.extend_from_slice(&line);
+                }
+                row.append_to_last(line.trim_ascii());
+            }
+        }
+        self.check.check_row(row, opts);
+        if let Some(n) = opts.max_columns {
+            row.merge_from(n);
+        }
+        if let Some(ref projection) = opts.project {
+            projection.apply(row);
+        }
+    }
+}
+
+impl<'a> RowReader<'a, std::vec::IntoIter<io::Result<Vec<u8>>>> {
+    /// Reads all of `lines` and finds the table in them, for
+    /// `Options::auto_detect_table`. If there is none, no line is tabulated.
+    fn detecting_table<I: Iterator<Item = io::Result<Vec<u8>>>>(lines: I, opts: &Options) -> Result<Self> {
+        let lines = lines.collect::<io::Result<Vec<Vec<u8>>>>()?;
+        let table = detect::find_table(&lines, &row_parser(opts));
+        let mut reader = RowReader::new(lines.into_iter().map(Ok).collect::<Vec<_>>().into_iter(), opts);
+        reader.table_lines = Some(match table {
+            Some((first, last)) => Ranges(vec![Range::Between(first as u32, last as u32)]),
+            None => Ranges::new(),
+        });
+        Ok(reader)
+    }
+}
+
+/// The parser for the lines of the input.
+fn row_parser(opts: &Options) -> RowParser {
+    RowParser::builder()
+        .delimiters(opts.delim.clone())
+        .strict(opts.strict_delim)
+        .strict_delimiters(opts.strict_delims.clone())
+        .build()
+}
+
+/// Whether `line` ends in a backslash that is not itself escaped.
+fn ends_in_backslash(line: &[u8]) -> bool {
+    line.iter().rev().take_while(|&&b| b == b'\\').count() % 2 == 1
+}
+
+/// Approximately how many bytes `row` takes up in memory.
+fn memory_size(row: &Row) -> usize {
+    std::mem::size_of::<Row>() + row.as_line().len() + row.len() * std::mem::size_of::<(usize, usize)>()
+}
+
+/// The cells that did not fit in their column after the column sizes were
+/// estimated, for `Options::warn_estimate`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overflow {
+    /// The number of cells checked.
+    pub cells: usize,
+    /// The number of cells that did not fit, by column.
+    pub overflowed: Vec<usize>,
+}
+
+impl Overflow {
+    fn check_row(&mut self, columns: &[Column], row: &Row, widths: Widths) {
+        // Values in the last column never push other columns aside.
+        let n = columns.len().saturating_sub(1);
+        for (i, (col, cell)) in columns[..n].iter().zip(row.get_parts()).enumerate() {
+            if col.is_excluded() {
+                continue;
+            }
+            self.cells += 1;
+            if widths.width(cell) > col.size() {
+                if self.overflowed.len() <= i {
+                    self.overflowed.resize(i + 1, 0);
+                }
+                self.overflowed[i] += 1;
+            }
+        }
+    }
+
+    /// The percentage of the cells checked that did not fit, rounded up.
+    pub fn percent(&self) -> f64 {
+        let overflowed: usize = self.overflowed.iter().sum();
+        if overflowed == 0 {
+            return 0.0;
+        }
+        (overflowed as f64 / self.cells as f64 * 100.0).ceil()
+    }
+
+    /// The columns, counted from 1, with cells that did not fit.
+    pub fn columns(&self) -> Vec<usize> {
+        (0..self.overflowed.len())
+            .filter(|&i| self.overflowed[i] > 0)
+            .map(|i| i + 1)
+            .collect()
+    }
+}
+
+/// Verifies that every row has as many columns as the first row, for
+/// `Options::check_columns`.
+struct ColumnCheck {
+    line: usize,
+    expected: Option<usize>,
+    /// The line and the number of columns of the rows that failed.
+    mismatches: Vec<(usize, usize)>,
+}
+
+impl ColumnCheck {
+    fn new() -> ColumnCheck {
+        ColumnCheck {
+            line: 0,
+            expected: None,
+            mismatches: Vec::new(),
+        }
+    }
+
+    fn check_row(&mut self, row: &Row, opts: &Options) {
+        if !opts.check_columns {
+            return;
+        }
+        self.line += 1;
+        let expected = *self.expected.get_or_insert(row.len());
+        if row.len() != expected {
+            self.mismatches.push((self.line, row.len()));
+        }
+    }
+
+    fn finish(&self) -> Result<()> {
+        match (self.mismatches.first(), self.expected) {
+            (Some(&(line, _)), Some(expected)) => Err(Error::ColumnCheckFailed {
+                rows: self.mismatches.len(),
+                line,
+                expected,
+                mismatches: self.mismatches.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Keeps only the last `n` rows of the input. In follow mode the rows are
+/// redrawn, replacing the previously drawn rows, every time a row is read.
+/// Returns the final layout of the columns and a summary of the rows read.
+fn process_tail<S, W>(
+    reader: &mut S,
+    mut output: W,
+    header: Option<&Row>,
+    mut measure_columns: Vec<MeasureColumn>,
+    n: usize,
+    opts: &Options,
+) -> Result<(Vec<Column>, Summary)>
+where
+    S: ReadRows,
+    W: Write,
+{
+    let mut rows: VecDeque<Row> = VecDeque::with_capacity(n);
+    let mut columns = Vec::new();
+    let mut drawn_lines = 0;
+    let mut buf = Vec::new();
+    let mut rows_read = 0;
+    let mut truncated = 0;
+
+    let mut row = Row::new();
+    while reader.read_row(&mut row, opts)? {
+        if !opts.passes_through(&row) {
+            rows_read += 1;
+        }
+        if opts.follow {
+            update_columns(&mut measure_columns, &row, opts);
+        }
+        if rows.len() == n {
+            rows.pop_front();
+        }
+        if n > 0 {
+            rows.push_back(std::mem::take(&mut row));
+        }
+
+        if opts.follow {
+            layout_columns(&mut columns, &measure_columns, opts);
+            buf.clear();
+            let mut printer = RowPrinter::new(opts);
+            if let Some(header) = header {
+                printer.print_header(&mut buf, &columns[..], header)?;
+            }
+            for row in &rows {
+                printer.print_row(&mut buf, &columns[..], row)?;
+            }
+            printer.finish(&mut buf, &columns)?;
+            truncated = printer.truncated_cells();
+            if drawn_lines > 0 {
+                // Move the cursor up to the first drawn line and clear everything below it.
+                write!(output, "\x1b[{}A\x1b[J", drawn_lines)?;
+            }
+            output.write_all(&buf)?;
+            output.flush()?;
+            drawn_lines = buf.iter().filter(|&&b| b == b'\n').count();
+        }
+    }
+
+    if !opts.follow {
+        for row in &rows {
+            update_columns(&mut measure_columns, row, opts);
+        }
+        layout_columns(&mut columns, &measure_columns, opts);
+        if opts.show_dropped {
+            print_dropped_columns(&mut output, &columns, &measure_columns, header)?;
+        }
+        let mut printer = RowPrinter::new(opts);
+        if let Some(header) = header {
+            printer.print_header(&mut output, &columns[..], header)?;
+        }
+        for row in &rows {
+            printer.print_row(&mut output, &columns[..], row)?;
+        }
+        printer.finish(&mut output, &columns)?;
+        truncated = printer.truncated_cells();
+    }
+    let summary = Summary::new(&columns, rows_read, truncated);
+    Ok((columns, summary))
+}
+
+/// Prints information about the columns in `Options::info_cols` and
+/// `Options::info_names`, and a one-line summary of the other columns.
+/// Columns are called by their header, if they have one.
+fn print_column_info<W: Write + ?Sized>(out: &mut W, columns: &mut [Column], opts: &Options) -> io::Result<()> {
+    for (i, col) in columns.iter_mut().enumerate() {
+        let title = column_title(i, col);
+        if is_info_column(i, col.name(), opts) {
+            writeln!(out, "{}", title)?;
+            col.print_info(out)?;
+            writeln!(out)?;
+        } else {
+            let excluded = if col.is_excluded() { ", excluded" } else { "" };
+            writeln!(out, "{}: size {}{}", title, col.size(), excluded)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints, for every column, the sizes evaluated by the cost model with their
+/// waste, overflow and score, and the size the column ended up with.
+fn print_sizing_explanation<W: Write + ?Sized>(
+    out: &mut W,
+    columns: &[Column],
+    measure_columns: &[MeasureColumn],
+    opts: &Options,
+) -> io::Result<()> {
+    for (i, (col, measured)) in columns.iter().zip(measure_columns).enumerate() {
+        let ratio = sizing_ratio(measured, opts);
+        writeln!(out, "{} (ratio {}):", column_title(i, col), ratio)?;
+        let candidates = measured.size_candidates(ratio);
+        if candidates.is_empty() {
+            writeln!(out, "  no sizes evaluated, the widest value is used")?;
+        } else {
+            writeln!(out, "  {:>6}  {:>10}  {:>10}  {:>10}", "size", "waste", "overflow", "score")?;
+            for c in candidates {
+                writeln!(
+                    out,
+                    "  {:>6}  {:>10.3}  {:>10.3}  {:>10.3}",
+                    // Both costs are sums that can be -0.0.
+                    c.size,
+                    c.waste.abs(),
+                    c.overflow.abs(),
+                    c.score
+                )?;
+            }
+        }
+        let excluded = if col.is_excluded() { ", excluded" } else { "" };
+        writeln!(out, "  chosen size: {}{}", col.size(), excluded)?;
+    }
+    Ok(())
+}
+
+/// The header of the column, or its number if it has no header.
+fn column_title(i: usize, col: &Column) -> String {
+    match col.name().filter(|name| !name.is_empty()) {
+        Some(name) => String::from_utf8_lossy(name).into_owned(),
+        None => format!("Column {}", i + 1),
+    }
+}
+
+fn is_info_column(i: usize, name: Option<&[u8]>, opts: &Options) -> bool {
+    let by_name = name.is_some_and(|name| opts.info_names.iter().any(|n| n[..] == *name));
+    match opts.info_cols {
+        Some(ref rs) => rs.any_contains(i as u32 + 1) || by_name,
+        None if !opts.info_names.is_empty() => by_name,
+        None => true,
+    }
+}
+
+fn update_columns(columns: &mut Vec<MeasureColumn>, row: &Row, opts: &Options) {
+    add_columns(columns, row.len(), opts);
+    for (col, cell) in columns.iter_mut().zip(row.get_parts()) {
+        col.add_sample(cell);
+    }
+    // Without colors, invalid values are printed with a mark after them.
+    if !opts.color && !opts.validate.is_empty() {
+        let widths = opts.widths();
+        for i in opts.invalid_columns(row).into_iter().filter(|&i| i < row.len()) {
+            columns[i].raise_min_size(widths.width(&row[i]) + widths.width(&opts.invalid_mark));
+        }
+    }
+}
+
+/// Registers the header row, which is not used as a sample for the column sizes.
+/// Fails if a column named by the options is not in the header.
+fn set_header(columns: &mut Vec<MeasureColumn>, header: &Row, opts: &Options) -> Result<()> {
+    if let Some(name) = opts
+        .include_names
+        .iter()
+        .chain(&opts.exclude_names)
+        .chain(&opts.info_names)
+        .find(|&name| !header.get_parts().any(|cell| cell == &name[..]))
+    {
+        return Err(Error::UnknownColumn {
+            s: String::from_utf8_lossy(name).into_owned(),
+        });
+    }
+    add_columns(columns, header.len(), opts);
+    for (i, (col, cell)) in columns.iter_mut().zip(header.get_parts()).enumerate() {
+        col.set_name(cell);
+        if !opts.include_names.is_empty() || !opts.exclude_names.is_empty() {
+            col.set_excluded(!is_selected(i, Some(cell), opts));
+        }
+    }
+    // Headers wider than their column would break the frame of a border.
+    if opts.header_floor || opts.border {
+        for (col, cell) in columns.iter_mut().zip(header.get_parts()) {
+            col.set_min_size(opts.widths().width(cell));
+        }
+    }
+    Ok(())
+}
+
+/// Whether column `i`, with the header `name` if there is one, is printed
+/// according to the include and exclude options.
+fn is_selected(i: usize, name: Option<&[u8]>, opts: &Options) -> bool {
+    let col_num = i as u32 + 1;
+    let named = |names: &[Vec<u8>]| name.is_some_and(|name| names.iter().any(|n| n[..] == *name));
+    let included = match opts.include_cols {
+        Some(ref rs) => rs.any_contains(col_num) || named(&opts.include_names),
+        None if !opts.include_names.is_empty() => named(&opts.include_names),
+        None => true,
+    };
+    included && !opts.exclude_cols.any_contains(col_num) && !named(&opts.exclude_names)
+}
+
+fn add_columns(columns: &mut Vec<MeasureColumn>, n: usize, opts: &Options) {
+    for i in columns.len()..n {
+        let mut col = MeasureColumn::new(opts.print_info);
+        let col_num = (i + 1) as u32;
+
+        let truncated = opts
+            .truncate
+            .as_ref()
+            .map(|rs| rs.any_contains(col_num))
+            .unwrap_or(false);
+
+        let pinned = opts
+            .pin
+            .as_ref()
+            .map(|rs| rs.any_contains(col_num))
+            .unwrap_or(false);
+
+        col.set_excluded(!is_selected(i, None, opts));
+        col.set_truncated(truncated);
+        col.set_pinned(pinned);
+        col.set_right_edge(opts.right_edge.as_ref().is_some_and(|rs| rs.any_contains(col_num)));
+        col.set_weight(opts.weights.get(col_num).copied().unwrap_or(1.0));
+        col.set_align_numbers(opts.align_numbers);
+        col.set_protect_numbers(opts.protect_numbers);
+        col.set_widths(opts.widths());
+        col.set_alignment(match opts.align.get(col_num) {
+            Some(&alignment) => Some(alignment),
+            None if opts.left_align_numbers => Some(Alignment::Left),
+            None => None,
+        });
+        if let Some(ref pattern) = opts.exclude_matching {
+            col.set_pattern(pattern.clone());
+        }
+        col.set_ignore_outliers(opts.ignore_outliers);
+        col.set_sizing_strategy(opts.sizing_strategy);
+        col.set_bucketed(opts.bucket_widths);
+        col.set_windowed(opts.window.is_some());
+        if opts.empty_value.is_some() || !opts.null_values.is_empty() {
+            col.set_missing_width(opts.empty_value.as_ref().map_or(0, |v| opts.widths().width(v)));
+        }
+
+        columns.push(col);
+    }
+}
+
+/// Empties the cells of `row` that are one of `null_values`, ignoring
+/// surrounding whitespace.
+fn clear_null_values(row: &mut Row, null_values: &[Vec<u8>]) {
+    let is_null = |cell: &[u8]| null_values.iter().any(|v| v[..] == *cell.trim_ascii());
+    if null_values.is_empty() || !row.get_parts().any(is_null) {
+        return;
+    }
+    let mut line = Vec::with_capacity(row.as_line().len());
+    let mut spans = Vec::with_capacity(row.len());
+    for cell in row.get_parts() {
+        let start = line.len();
+        if !is_null(cell) {
+            line.extend_from_slice(cell);
+        }
+        spans.push((start, line.len()));
+    }
+    *row = Row::from_spans(line, spans);
+}
+
+/// The ratio used to size `col`, which depends on whether it is truncated.
+fn sizing_ratio(col: &MeasureColumn, opts: &Options) -> f64 {
+    match opts.truncate_ratio {
+        Some(ratio) if col.is_truncated() => ratio,
+        _ => opts.ratio,
+    }
+}
+
+fn layout_columns(columns: &mut Vec<Column>, measure_columns: &[MeasureColumn], opts: &Options) {
+    columns.clear();
+    columns.extend(measure_columns.iter().map(|c| c.calculate_size(sizing_ratio(c, opts))));
+    adjust_columns(columns, measure_columns, opts);
+}
+
+/// Lays out the columns again in online mode, after a row was measured. The
+/// sizes in `sized` are updated in place, and `columns` is only laid out
+/// again from them if one of them changed or a column would be dropped or
+/// kept differently. Returns whether `columns` was laid out again.
+fn update_layout(
+    columns: &mut Vec<Column>,
+    sized: &mut Vec<Column>,
+    measure_columns: &[MeasureColumn],
+    opts: &Options,
+) -> bool {
+    let mut changed = sized.len() != measure_columns.len() || columns.len() != sized.len();
+    if sized.len() != measure_columns.len() {
+        sized.extend(measure_columns[sized.len()..].iter().map(|c| c.calculate_size(sizing_ratio(c, opts))));
+    }
+    for (col, measured) in sized.iter_mut().zip(measure_columns) {
+        changed |= measured.update_size(col, sizing_ratio(measured, opts));
+    }
+    if !changed && (opts.drop_constant || opts.drop_empty || opts.exclude_matching.is_some()) {
+        let dropped = dropped_columns(sized, measure_columns, opts);
+        changed = sized
+            .iter()
+            .zip(columns.iter())
+            .enumerate()
+            .any(|(i, (s, c))| c.is_excluded() != (s.is_excluded() || dropped.contains(&i)));
+    }
+    if changed {
+        columns.clone_from(sized);
+        adjust_columns(columns, measure_columns, opts);
+    }
+    changed
+}
+
+/// Drops, widens and fits freshly sized columns as asked for by the options.
+fn adjust_columns(columns: &mut [Column], measure_columns: &[MeasureColumn], opts: &Options) {
+    if opts.drop_constant || opts.drop_empty || opts.exclude_matching.is_some() {
+        drop_columns(columns, measure_columns, opts);
+    }
+    if let Some(ref layout) = opts.layout {
+        for (col, saved) in columns.iter_mut().zip(&layout.columns) {
+            let size = col.size().max(saved.size);
+            col.set_size(size);
+            if let Some(w) = saved.numbers {
+                col.widen_numbers(w);
+            }
+            if saved.locked {
+                col.lock(saved.size);
+            }
+        }
+    }
+    if let (true, Some(width)) = (opts.fit, opts.width) {
+        // Leave room for the left and right edges of the frame.
+        let width = if opts.border { width.saturating_sub(4) } else { width };
+        if opts.auto_hide {
+            fit::hide_columns(columns, width, opts.delim_width());
+        }
+        fit::fit_columns(columns, width, opts.delim_width());
+    }
+}
+
+/// Notes which columns were hidden to fit the output width in `hidden`, the
+/// first time any are.
+fn note_hidden_columns(columns: &[Column], hidden: &mut Vec<String>, opts: &Options) {
+    if !hidden.is_empty() || !opts.auto_hide {
+        return;
+    }
+    hidden.extend(
+        (0..columns.len())
+            .filter(|&i| columns[i].is_hidden())
+            .map(|i| column_title(i, &columns[i])),
+    );
+}
+
+/// Excludes the columns that are constant or empty in every measured row, or
+/// where every measured value matches `exclude_matching`, as asked for by the
+/// options, unless that would leave no column to print.
+fn drop_columns(columns: &mut [Column], measure_columns: &[MeasureColumn], opts: &Options) {
+    for i in dropped_columns(columns, measure_columns, opts) {
+        columns[i].set_excluded(true);
+    }
+}
+
+/// The indices of the columns that `drop_columns` excludes.
+fn dropped_columns(columns: &[Column], measure_columns: &[MeasureColumn], opts: &Options) -> Vec<usize> {
+    let dropped: Vec<usize> = (0..columns.len())
+        .filter(|&i| {
+            let measured = &measure_columns[i];
+            !columns[i].is_excluded()
+                && ((opts.drop_constant && measured.constant_value().is_some())
+                    || (opts.drop_empty && measured.is_empty())
+                    || measured.all_match())
+        })
+        .collect();
+    let visible = columns.iter().filter(|c| !c.is_excluded()).count();
+    if dropped.len() < visible {
+        dropped
+    } else {
+        Vec::new()
+    }
+}
+
+/// Prints `name=value` for every column that was dropped for being constant.
+/// The name is the header of the column, or its number.
+fn print_dropped_columns<W: Write>(
+    out: &mut W,
+    columns: &[Column],
+    measure_columns: &[MeasureColumn],
+    header: Option<&Row>,
+) -> io::Result<()> {
+    for (i, (col, measured)) in columns.iter().zip(measure_columns).enumerate() {
+        let value = match measured.constant_value() {
+            Some(value) if col.is_excluded() && !measured.is_excluded() => value,
+            _ => continue,
+        };
+        match header.filter(|h| i < h.len() && !h[i].is_empty()) {
+            Some(header) => out.write_all(&header[i])?,
+            None => write!(out, "{}", i + 1)?,
+        }
+        out.write_all(b"=")?;
+        out.write_all(value)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn basic_test() {
+        let opts = Options {
+            truncate: None,
+            ratio: 1.0,
+            truncate_ratio: None,
+            sizing_strategy: SizingStrategy::Auto,
+            bucket_widths: false,
+            ignore_outliers: 0.0,
+            lines: 1000,
+            estimate_mem: None,
+            include_cols: None,
             exclude_cols: Ranges::new(),
-            delim: " \t".to_string(),
-            output_delim: "  ".to_string(),
+            include_names: Vec::new(),
+            exclude_names: Vec::new(),
+            delim: b" \t".to_vec(),
+            output_delim: b"  ".to_vec(),
             strict_delim: false,
+            strict_delims: Vec::new(),
             print_info: false,
+            explain_sizing: false,
+            info_cols: None,
+            info_names: Vec::new(),
             online: false,
+            find: None,
+            validate: Vec::new(),
+            invalid_mark: b"!".to_vec(),
+            checksum: false,
+            footer_info: false,
+            page_length: None,
+            page_numbers: false,
+            quiet: false,
+            color: false,
+            fit: false,
+            auto_hide: false,
+            width: None,
+            pin: None,
+            right_edge: None,
+            weights: RangeMap::new(),
+            collapse_repeats: None,
+            ditto_mark: b"\"".to_vec(),
+            group_sep: None,
+            group_rule: None,
+            follow: false,
+            tail: None,
+            window: None,
+            header: false,
+            header_floor: false,
+            underline_header: None,
+            header_style: None,
+            header_align: None,
+            rename: Vec::new(),
+            hide_header: false,
+            events: false,
+            soft_wrap: None,
+            no_trailing_space: false,
+            border: false,
+            check_columns: false,
+            warn_estimate: false,
+            project: None,
+            compute: Vec::new(),
+            extract: Vec::new(),
+            percent: None,
+            spark: None,
+            thresholds: Vec::new(),
+            precision: RangeMap::new(),
+            null_values: Vec::new(),
+            empty_value: None,
+            booleans: RangeMap::new(),
+            align: RangeMap::new(),
+            left_align_numbers: false,
+            align_numbers: false,
+            protect_numbers: false,
+            byte_widths: false,
+            ignore_ansi: false,
+            pass_ansi_lines: false,
+            line_ranges: None,
+            auto_detect_table: false,
+            drop_other_lines: false,
+            sort: Vec::new(),
+            layout: None,
+            measure_only: false,
+            every: 1,
+            max_columns: None,
+            preview: None,
+            drop_constant: false,
+            show_dropped: false,
+            drop_empty: false,
+            exclude_matching: None,
+            continuation: None,
+            join_continuations: false,
+            column_delims: Vec::new(),
         };
 
         let reader = BufReader::new(&b"aa bb cc\n1 2 3\n"[..]);
@@ -226,14 +2196,93 @@ mod tests {
         let mut opts = Options {
             truncate: None,
             ratio: 1.0,
+            truncate_ratio: None,
+            sizing_strategy: SizingStrategy::Auto,
+            bucket_widths: false,
+            ignore_outliers: 0.0,
             lines: 1000,
+            estimate_mem: None,
             include_cols: None,
             exclude_cols: Ranges(vec![Range::Between(2, 2)]),
-            delim: " \t".to_string(),
-            output_delim: "  ".to_string(),
+            include_names: Vec::new(),
+            exclude_names: Vec::new(),
+            delim: b" \t".to_vec(),
+            output_delim: b"  ".to_vec(),
             strict_delim: false,
+            strict_delims: Vec::new(),
             print_info: false,
+            explain_sizing: false,
+            info_cols: None,
+            info_names: Vec::new(),
             online: false,
+            find: None,
+            validate: Vec::new(),
+            invalid_mark: b"!".to_vec(),
+            checksum: false,
+            footer_info: false,
+            page_length: None,
+            page_numbers: false,
+            quiet: false,
+            color: false,
+            fit: false,
+            auto_hide: false,
+            width: None,
+            pin: None,
+            right_edge: None,
+            weights: RangeMap::new(),
+            collapse_repeats: None,
+            ditto_mark: b"\"".to_vec(),
+            group_sep: None,
+            group_rule: None,
+            follow: false,
+            tail: None,
+            window: None,
+            header: false,
+            header_floor: false,
+            underline_header: None,
+            header_style: None,
+            header_align: None,
+            rename: Vec::new(),
+            hide_header: false,
+            events: false,
+            soft_wrap: None,
+            no_trailing_space: false,
+            border: false,
+            check_columns: false,
+            warn_estimate: false,
+            project: None,
+            compute: Vec::new(),
+            extract: Vec::new(),
+            percent: None,
+            spark: None,
+            thresholds: Vec::new(),
+            precision: RangeMap::new(),
+            null_values: Vec::new(),
+            empty_value: None,
+            booleans: RangeMap::new(),
+            align: RangeMap::new(),
+            left_align_numbers: false,
+            align_numbers: false,
+            protect_numbers: false,
+            byte_widths: false,
+            ignore_ansi: false,
+            pass_ansi_lines: false,
+            line_ranges: None,
+            auto_detect_table: false,
+            drop_other_lines: false,
+            sort: Vec::new(),
+            layout: None,
+            measure_only: false,
+            every: 1,
+            max_columns: None,
+            preview: None,
+            drop_constant: false,
+            show_dropped: false,
+            drop_empty: false,
+            exclude_matching: None,
+            continuation: None,
+            join_continuations: false,
+            column_delims: Vec::new(),
         };
 
         let input: &[u8] = b"aa bb cc\n1 2 3\n";
@@ -262,14 +2311,93 @@ mod tests {
         let opts = Options {
             truncate: None,
             ratio: 1.0,
+            truncate_ratio: None,
+            sizing_strategy: SizingStrategy::Auto,
+            bucket_widths: false,
+            ignore_outliers: 0.0,
             lines: 1,
+            estimate_mem: None,
             include_cols: None,
             exclude_cols: Ranges::new(),
-            delim: " \t".to_string(),
-            output_delim: "  ".to_string(),
+            include_names: Vec::new(),
+            exclude_names: Vec::new(),
+            delim: b" \t".to_vec(),
+            output_delim: b"  ".to_vec(),
             strict_delim: false,
+            strict_delims: Vec::new(),
             print_info: false,
+            explain_sizing: false,
+            info_cols: None,
+            info_names: Vec::new(),
             online: false,
+            find: None,
+            validate: Vec::new(),
+            invalid_mark: b"!".to_vec(),
+            checksum: false,
+            footer_info: false,
+            page_length: None,
+            page_numbers: false,
+            quiet: false,
+            color: false,
+            fit: false,
+            auto_hide: false,
+            width: None,
+            pin: None,
+            right_edge: None,
+            weights: RangeMap::new(),
+            collapse_repeats: None,
+            ditto_mark: b"\"".to_vec(),
+            group_sep: None,
+            group_rule: None,
+            follow: false,
+            tail: None,
+            window: None,
+            header: false,
+            header_floor: false,
+            underline_header: None,
+            header_style: None,
+            header_align: None,
+            rename: Vec::new(),
+            hide_header: false,
+            events: false,
+            soft_wrap: None,
+            no_trailing_space: false,
+            border: false,
+            check_columns: false,
+            warn_estimate: false,
+            project: None,
+            compute: Vec::new(),
+            extract: Vec::new(),
+            percent: None,
+            spark: None,
+            thresholds: Vec::new(),
+            precision: RangeMap::new(),
+            null_values: Vec::new(),
+            empty_value: None,
+            booleans: RangeMap::new(),
+            align: RangeMap::new(),
+            left_align_numbers: false,
+            align_numbers: false,
+            protect_numbers: false,
+            byte_widths: false,
+            ignore_ansi: false,
+            pass_ansi_lines: false,
+            line_ranges: None,
+            auto_detect_table: false,
+            drop_other_lines: false,
+            sort: Vec::new(),
+            layout: None,
+            measure_only: false,
+            every: 1,
+            max_columns: None,
+            preview: None,
+            drop_constant: false,
+            show_dropped: false,
+            drop_empty: false,
+            exclude_matching: None,
+            continuation: None,
+            join_continuations: false,
+            column_delims: Vec::new(),
         };
 
         let reader = BufReader::new(&b"1 1\naaaa aaaa\n"[..]);
@@ -283,14 +2411,93 @@ mod tests {
         let opts = Options {
             truncate: None,
             ratio: 1.0,
+            truncate_ratio: None,
+            sizing_strategy: SizingStrategy::Auto,
+            bucket_widths: false,
+            ignore_outliers: 0.0,
             lines: 1,
+            estimate_mem: None,
             include_cols: None,
             exclude_cols: Ranges::new(),
-            delim: " \t".to_string(),
-            output_delim: "  ".to_string(),
+            include_names: Vec::new(),
+            exclude_names: Vec::new(),
+            delim: b" \t".to_vec(),
+            output_delim: b"  ".to_vec(),
             strict_delim: false,
+            strict_delims: Vec::new(),
             print_info: false,
+            explain_sizing: false,
+            info_cols: None,
+            info_names: Vec::new(),
             online: false,
+            find: None,
+            validate: Vec::new(),
+            invalid_mark: b"!".to_vec(),
+            checksum: false,
+            footer_info: false,
+            page_length: None,
+            page_numbers: false,
+            quiet: false,
+            color: false,
+            fit: false,
+            auto_hide: false,
+            width: None,
+            pin: None,
+            right_edge: None,
+            weights: RangeMap::new(),
+            collapse_repeats: None,
+            ditto_mark: b"\"".to_vec(),
+            group_sep: None,
+            group_rule: None,
+            follow: false,
+            tail: None,
+            window: None,
+            header: false,
+            header_floor: false,
+            underline_header: None,
+            header_style: None,
+            header_align: None,
+            rename: Vec::new(),
+            hide_header: false,
+            events: false,
+            soft_wrap: None,
+            no_trailing_space: false,
+            border: false,
+            check_columns: false,
+            warn_estimate: false,
+            project: None,
+            compute: Vec::new(),
+            extract: Vec::new(),
+            percent: None,
+            spark: None,
+            thresholds: Vec::new(),
+            precision: RangeMap::new(),
+            null_values: Vec::new(),
+            empty_value: None,
+            booleans: RangeMap::new(),
+            align: RangeMap::new(),
+            left_align_numbers: false,
+            align_numbers: false,
+            protect_numbers: false,
+            byte_widths: false,
+            ignore_ansi: false,
+            pass_ansi_lines: false,
+            line_ranges: None,
+            auto_detect_table: false,
+            drop_other_lines: false,
+            sort: Vec::new(),
+            layout: None,
+            measure_only: false,
+            every: 1,
+            max_columns: None,
+            preview: None,
+            drop_constant: false,
+            show_dropped: false,
+            drop_empty: false,
+            exclude_matching: None,
+            continuation: None,
+            join_continuations: false,
+            column_delims: Vec::new(),
         };
 
         // a  a  aaaaaaaaaaa  a
@@ -310,14 +2517,93 @@ mod tests {
         let opts = Options {
             truncate: None,
             ratio: 1.0,
+            truncate_ratio: None,
+            sizing_strategy: SizingStrategy::Auto,
+            bucket_widths: false,
+            ignore_outliers: 0.0,
             lines: 1,
+            estimate_mem: None,
             include_cols: None,
             exclude_cols: Ranges::new(),
-            delim: " \t".to_string(),
-            output_delim: " & ".to_string(),
+            include_names: Vec::new(),
+            exclude_names: Vec::new(),
+            delim: b" \t".to_vec(),
+            output_delim: b" & ".to_vec(),
             strict_delim: false,
+            strict_delims: Vec::new(),
             print_info: false,
+            explain_sizing: false,
+            info_cols: None,
+            info_names: Vec::new(),
             online: false,
+            find: None,
+            validate: Vec::new(),
+            invalid_mark: b"!".to_vec(),
+            checksum: false,
+            footer_info: false,
+            page_length: None,
+            page_numbers: false,
+            quiet: false,
+            color: false,
+            fit: false,
+            auto_hide: false,
+            width: None,
+            pin: None,
+            right_edge: None,
+            weights: RangeMap::new(),
+            collapse_repeats: None,
+            ditto_mark: b"\"".to_vec(),
+            group_sep: None,
+            group_rule: None,
+            follow: false,
+            tail: None,
+            window: None,
+            header: false,
+            header_floor: false,
+            underline_header: None,
+            header_style: None,
+            header_align: None,
+            rename: Vec::new(),
+            hide_header: false,
+            events: false,
+            soft_wrap: None,
+            no_trailing_space: false,
+            border: false,
+            check_columns: false,
+            warn_estimate: false,
+            project: None,
+            compute: Vec::new(),
+            extract: Vec::new(),
+            percent: None,
+            spark: None,
+            thresholds: Vec::new(),
+            precision: RangeMap::new(),
+            null_values: Vec::new(),
+            empty_value: None,
+            booleans: RangeMap::new(),
+            align: RangeMap::new(),
+            left_align_numbers: false,
+            align_numbers: false,
+            protect_numbers: false,
+            byte_widths: false,
+            ignore_ansi: false,
+            pass_ansi_lines: false,
+            line_ranges: None,
+            auto_detect_table: false,
+            drop_other_lines: false,
+            sort: Vec::new(),
+            layout: None,
+            measure_only: false,
+            every: 1,
+            max_columns: None,
+            preview: None,
+            drop_constant: false,
+            show_dropped: false,
+            drop_empty: false,
+            exclude_matching: None,
+            continuation: None,
+            join_continuations: false,
+            column_delims: Vec::new(),
         };
 
         // a & a & aaaaaaaaaaa & a
@@ -331,4 +2617,1524 @@ mod tests {
         process(reader, &mut output, &opts).unwrap();
         assert_eq!(std::str::from_utf8(&output).unwrap(), expected);
     }
-}
+
+    #[test]
+    fn non_utf8_input() {
+        let opts = Options {
+            delim: b"\x1f".to_vec(),
+            output_delim: b"\x1f\x1f".to_vec(),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a\xff\x1fb\x1fc\r\nabc\x1f\xfe\x1fc\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            &output[..],
+            &b"a\xff \x1f\x1fb\x1f\x1fc\nabc\x1f\x1f\xfe\x1f\x1fc\n"[..]
+        );
+    }
+
+    #[test]
+    fn strict_delimiters() {
+        let opts = Options {
+            ratio: 0.0,
+            strict_delims: b";".to_vec(),
+            output_delim: b"|".to_vec(),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a  b;;c\nxx yy;z;\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a |b | |c\nxx|yy|z|\n");
+    }
+
+    #[test]
+    fn find_rows() {
+        let mut opts = Options {
+            find: Some("2 ^b+$".parse().unwrap()),
+            color: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a b\nb a\nc bb\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a  \x1b[1;31mb\x1b[0m\nb  a\nc  \x1b[1;31mbb\x1b[0m\n"
+        );
+
+        opts.quiet = true;
+        output.clear();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert!(output.is_empty());
+
+        opts.find = Some("1 x".parse().unwrap());
+        assert!(matches!(
+            process(BufReader::new(input), &mut output, &opts),
+            Err(Error::NoMatch)
+        ));
+    }
+
+    #[test]
+    fn fit_with_weights() {
+        let opts = Options {
+            ratio: 0.0,
+            fit: true,
+            width: Some(24),
+            weights: RangeMap(vec![(Ranges(vec![Range::Between(1, 1)]), 4.0)]),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"2022-11-09 aaaaaaaaaaaaaaaaaaaa\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "2022-11-09  aaaaaaaaaaa…\n");
+    }
+
+    #[test]
+    fn fit_to_width() {
+        let mut opts = Options {
+            ratio: 0.0,
+            fit: true,
+            width: Some(16),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"2022-11-09 aaaaaaaa bbbbbbbbbbbb\n2022-11-10 a b\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "202…  aa…  bbbb…\n202…  a    b\n"
+        );
+
+        opts.pin = Some(Ranges(vec![Range::Between(1, 1)]));
+        opts.width = Some(20);
+        output.clear();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "2022-11-09  a…  bbb…\n2022-11-10  a   b\n"
+        );
+    }
+
+    #[test]
+    fn fit_with_right_edge() {
+        let opts = Options {
+            ratio: 0.0,
+            fit: true,
+            width: Some(24),
+            right_edge: Some(Ranges(vec![Range::From(3)])),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"rw- alice 12 Jan\nrwx bob 1024 Feb\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "rw-  alice       12  Jan\nrwx  bob       1024  Feb\n"
+        );
+    }
+
+    #[test]
+    fn collapse_repeats() {
+        let mut opts = Options {
+            collapse_repeats: Some(Ranges(vec![Range::To(2)])),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a x 1\na x 1\na y 1\nb y 2\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a  x  1\n\"  \"  1\n\"  y  1\nb  \"  2\n"
+        );
+
+        opts.ditto_mark = Vec::new();
+        output.clear();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a  x  1\n      1\n   y  1\nb     2\n"
+        );
+    }
+
+    #[test]
+    fn group_separator() {
+        let mut opts = Options {
+            group_sep: Some(1),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a 1\na 2\nb 3\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  1\na  2\n\nb  3\n");
+
+        opts.group_rule = Some('-');
+        output.clear();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  1\na  2\n----\nb  3\n");
+    }
+
+    #[test]
+    fn tail() {
+        let mut opts = Options {
+            tail: Some(2),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"aaaaaa 1\nb 2\nc 3\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "b  2\nc  3\n");
+
+        opts.follow = true;
+        output.clear();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "aaaaaa  1\n\x1b[1A\x1b[Jaaaaaa  1\nb       2\n\x1b[2A\x1b[Jb       2\nc       3\n"
+        );
+    }
+
+    #[test]
+    fn column_info_separate_output() {
+        let opts = Options {
+            print_info: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"aa b\nc dd\n";
+        let mut output: Vec<u8> = Vec::new();
+        let mut info: Vec<u8> = Vec::new();
+        process_with_info(BufReader::new(input), &mut output, &mut info, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "aa  b\nc   dd\n");
+        assert!(std::str::from_utf8(&info).unwrap().starts_with("Column 1\n"));
+    }
+
+    #[test]
+    fn header() {
+        let mut opts = Options {
+            header: true,
+            truncate: Some(Ranges(vec![Range::From(1)])),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"NAME SIZE TYPE\na 1 x\nbb 2 y\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "NAME  SIZE  TYPE\na   1  x\nbb  2  y\n"
+        );
+
+        opts.header_floor = true;
+        output.clear();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "NAME  SIZE  TYPE\na        1  x\nbb       2  y\n"
+        );
+    }
+
+    #[test]
+    fn soft_wrap() {
+        let opts = Options {
+            ratio: 0.0,
+            width: Some(12),
+            soft_wrap: Some(b"> ".to_vec()),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"aaaa bbbb cccc dddd\na b c d\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "aaaa  bbbb\n> cccc  dddd\na     b   \n> c     d\n"
+        );
+    }
+
+    #[test]
+    fn check_columns() {
+        let opts = Options {
+            check_columns: true,
+            quiet: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a b\nc d\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  b\nc  d\n");
+
+        let input: &[u8] = b"a b\nc\nd e f\n";
+        match process(BufReader::new(input), Vec::new(), &opts) {
+            Err(Error::ColumnCheckFailed {
+                rows: 2,
+                line: 2,
+                expected: 2,
+                ref mismatches,
+            }) if mismatches[..] == [(2, 1), (3, 3)] => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn project() {
+        let opts = Options {
+            header: true,
+            project: Some("3,upper(1)".parse().unwrap()),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name size path\nfoo 1 /a\nbarbaz 22 /b/c\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "path  NAME\n/a    FOO\n/b/c  BARBAZ\n"
+        );
+    }
+
+    #[test]
+    fn compute() {
+        let opts = Options {
+            header: true,
+            compute: vec!["ratio=col1/col2".parse().unwrap()],
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a b\n1 4\n3 x\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a  b  ratio\n1  4  0.25\n3  x  \n"
+        );
+    }
+
+    #[test]
+    fn percent() {
+        let opts = Options {
+            header: true,
+            percent: Some(2),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"file size\na 30\nb 10\nc -\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "file  size  %\na  30  75.0%\nb  10  25.0%\nc  -   \n"
+        );
+    }
+
+    #[test]
+    fn thresholds() {
+        let opts = Options {
+            color: true,
+            thresholds: vec!["2:>10=red".parse().unwrap()],
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a 5 x\nb 20 y\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a   5  x\nb  \x1b[31m20\x1b[0m  y\n"
+        );
+    }
+
+    #[test]
+    fn align_numbers() {
+        let opts = Options {
+            align_numbers: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a 1.5ms 12%\nb 120ms 3.25%\nc 7s 100%\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a    1.5ms   12%\nb  120ms      3.25%\nc    7s     100%\n"
+        );
+    }
+
+    #[test]
+    fn sort() {
+        let opts = Options {
+            header: true,
+            sort: vec!["2:version".parse().unwrap()],
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"pkg version\na 1.10\nb 1.9\nc 1.9.1\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "pkg  version\nb  1.9\nc  1.9.1\na  1.10\n"
+        );
+    }
+
+    #[test]
+    fn saved_layout() {
+        let opts = Options {
+            layout: Some(layout::Layout::new(vec![4, 1], "  ")),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a bbb\nc d\n";
+        let mut output: Vec<u8> = Vec::new();
+        let (_, layout) = process_with_layout(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a     bbb\nc     d\n");
+        assert_eq!(layout.visible_sizes().collect::<Vec<_>>(), [4, 3]);
+    }
+
+    #[test]
+    fn measure_only() {
+        let opts = Options {
+            lines: 1,
+            measure_only: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a bb\nccc d\n";
+        let mut output: Vec<u8> = Vec::new();
+        let (_, layout) = process_with_layout(BufReader::new(input), &mut output, &opts).unwrap();
+        assert!(output.is_empty());
+        assert_eq!(layout.visible_sizes().collect::<Vec<_>>(), [3, 2]);
+    }
+
+    #[test]
+    fn column_info_subset() {
+        let opts = Options {
+            print_info: true,
+            info_cols: Some(Ranges(vec!["2".parse().unwrap()])),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a bb c\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "Column 1: size 1");
+        assert_eq!(lines[1], "Column 2");
+        assert_eq!(lines[lines.len() - 1], "Column 3: size 1");
+    }
+
+    #[test]
+    fn column_info_by_name() {
+        let opts = Options {
+            header: true,
+            print_info: true,
+            info_names: vec![b"count".to_vec()],
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name count\na 1\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "name: size 1");
+        assert_eq!(lines[1], "count");
+        assert_eq!(lines[2], "  Computed column size:  1");
+    }
+
+    #[test]
+    fn estimate_check() {
+        let measure: Vec<MeasureColumn> = (0..3)
+            .map(|_| {
+                let mut col = MeasureColumn::new(false);
+                col.add_sample(b"ab");
+                col
+            })
+            .collect();
+        let columns: Vec<Column> = measure.iter().map(|c| c.calculate_size(0.0)).collect();
+
+        let parser = RowParser::new(" ", false);
+        let mut check = Overflow::default();
+        let mut row = Row::new();
+        for line in &["a b c", "abc b cccc", "a bbb c"] {
+            parser.parse_into(&mut row, *line);
+            check.check_row(&columns, &row, Widths::default());
+        }
+        assert_eq!(check.cells, 6);
+        assert_eq!(check.overflowed, [1, 1]);
+        assert_eq!(check.percent(), 34.0);
+        assert_eq!(check.columns(), [1, 2]);
+
+        let opts = Options {
+            lines: 1,
+            warn_estimate: true,
+            ..Options::default()
+        };
+        let input: &[u8] = b"a b\nc d\naaaaa b\n";
+        let summary = process(BufReader::new(input), io::sink(), &opts).unwrap();
+        let overflow = summary.overflow.unwrap();
+        assert_eq!((overflow.cells, overflow.columns()), (2, vec![1]));
+    }
+
+    #[test]
+    fn sizing_strategies() {
+        let mut col = MeasureColumn::new(false);
+        for sample in ["a", "a", "a", "aaaa", "aaaaaaaaaa"] {
+            col.add_sample(sample.as_bytes());
+        }
+
+        col.set_sizing_strategy(SizingStrategy::FirstMinimum);
+        let first = col.size_candidates(50.0);
+        col.set_sizing_strategy(SizingStrategy::Exhaustive);
+        let all = col.size_candidates(50.0);
+        assert!(first.len() < all.len());
+        assert_eq!(all.len(), 10);
+        assert_eq!(first[..], all[..first.len()]);
+
+        let size = col.calculate_size(50.0).size();
+        col.set_sizing_strategy(SizingStrategy::FirstMinimum);
+        assert_eq!(col.calculate_size(50.0).size(), size);
+
+        assert!("exhaustive".parse::<SizingStrategy>().is_ok());
+        assert!("best".parse::<SizingStrategy>().is_err());
+    }
+
+    #[test]
+    fn bucketed_widths() {
+        let mut exact = MeasureColumn::new(false);
+        let mut bucketed = MeasureColumn::new(false);
+        bucketed.set_bucketed(true);
+        for n in (1..2000).map(|i| i * 7 % 1000 + 1) {
+            let sample = vec![b'x'; n];
+            exact.add_sample(&sample);
+            bucketed.add_sample(&sample);
+        }
+
+        let (exact, bucketed) = (exact.calculate_size(1.0), bucketed.calculate_size(1.0));
+        assert_eq!(bucketed.max_size(), exact.max_size());
+        assert!(bucketed.size().abs_diff(exact.size()) <= exact.size() / 16);
+    }
+
+    #[test]
+    fn merge_measure_columns() {
+        let samples: [&[u8]; 6] = [b"1.5", b"22", b"333", b"1.25", b"", b"4444"];
+        let mut all = MeasureColumn::new(true);
+        let mut first = MeasureColumn::new(true);
+        let mut second = MeasureColumn::new(true);
+        for (i, sample) in samples.iter().enumerate() {
+            all.add_sample(sample);
+            if i < 2 { &mut first } else { &mut second }.add_sample(sample);
+        }
+        first.merge(&second);
+
+        let (mut all, mut merged) = (all.calculate_size(1.0), first.calculate_size(1.0));
+        assert_eq!(merged.size(), all.size());
+        assert_eq!(merged.max_size(), all.max_size());
+        assert_eq!(merged.is_numeric(), all.is_numeric());
+        assert_eq!(first.constant_value(), None);
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        all.print_info(&mut a).unwrap();
+        merged.print_info(&mut b).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn remove_samples() {
+        let mut col = MeasureColumn::new(false);
+        col.set_windowed(true);
+        for sample in ["n/a", "12.5", "3"] {
+            col.add_sample(sample.as_bytes());
+        }
+        assert!(!col.calculate_size(0.0).is_numeric());
+
+        col.remove_sample(b"n/a");
+        let size = col.calculate_size(0.0);
+        assert_eq!(size.number_width().map(|w| (w.integer, w.fraction)), Some((2, 2)));
+        col.remove_sample(b"12.5");
+        assert_eq!(col.calculate_size(0.0).size(), 1);
+    }
+
+    #[test]
+    fn no_panic_on_edge_cases() {
+        let opts = Options {
+            lines: 1,
+            online: true,
+            truncate: Some(Ranges(vec![Range::From(1)])),
+            align_numbers: true,
+            byte_widths: true,
+            ..Options::default()
+        };
+        // Wider numbers than measured, and truncation inside a UTF-8 character.
+        let input = "1.5 ab x\n100.25 \u{e6}\u{f8}\u{e5} x\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input.as_bytes()), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "1.5  ab  x\n10…  …   x\n");
+
+        let opts = Options {
+            fit: true,
+            width: Some(5),
+            weights: RangeMap(vec![(Ranges(vec![Range::From(1)]), 0.0)]),
+            ..Options::default()
+        };
+        output.clear();
+        process(BufReader::new(input.as_bytes()), &mut output, &opts).unwrap();
+        assert!("99999999999".parse::<Range>().is_ok());
+    }
+
+    #[test]
+    fn follow_flushes_every_row() {
+        struct CountFlushes(Vec<u8>, usize);
+        impl Write for CountFlushes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.1 += 1;
+                Ok(())
+            }
+        }
+
+        let mut opts = Options {
+            follow: true,
+            ..Options::default()
+        };
+        let input: &[u8] = b"a\nb\nc\n";
+        let mut output = CountFlushes(Vec::new(), 0);
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(output.1, 3);
+
+        opts.follow = false;
+        opts.online = true;
+        let mut output = CountFlushes(Vec::new(), 0);
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!((&output.0[..], output.1), (&b"a\nb\nc\n"[..], 0));
+    }
+
+    #[test]
+    fn every_nth_row() {
+        let opts = Options {
+            header: true,
+            every: 2,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name n\na 1\nbbbbbb 2\nc 3\nd 4\ne 5\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name  n\na  1\nc  3\ne  5\n");
+    }
+
+    #[test]
+    fn drop_constant() {
+        let mut opts = Options {
+            header: true,
+            drop_constant: true,
+            show_dropped: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"host name pid\nweb1 a 1\nweb1 bb 2\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "host=web1\nname  pid\na   1\nbb  2\n");
+
+        // Every column is constant, so nothing is dropped.
+        opts.header = false;
+        let input: &[u8] = b"a b\na b\n";
+        output.clear();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  b\na  b\n");
+    }
+
+    #[test]
+    fn drop_empty() {
+        let opts = Options {
+            strict_delim: true,
+            delim: b",".to_vec(),
+            drop_empty: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a,,1,\nbb,,2,\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a   1\nbb  2\n");
+    }
+
+    #[test]
+    fn exclude_matching() {
+        let opts = Options {
+            exclude_matching: Some("^-?$".parse().unwrap()),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a - 1 -\nb - 2 x\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  1  -\nb  2  x\n");
+    }
+
+    #[test]
+    fn side_by_side() {
+        let opts = Options::default();
+        let left: &[u8] = b"a 1\nbbb 2\n";
+        let right: &[u8] = b"aa 10\nb 2\nc 3\n";
+        let mut output: Vec<u8> = Vec::new();
+        process_side_by_side(left, right, &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a     1  |  aa   10\nbbb   2  |  b     2\n         |  c     3\n"
+        );
+    }
+
+    #[test]
+    fn hide_header() {
+        let opts = Options {
+            header: true,
+            header_floor: true,
+            hide_header: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name n\na 1\nb 2\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a     1\nb     2\n");
+    }
+
+    #[test]
+    fn column_info_diff_between_inputs() {
+        let opts = Options::default();
+        let left: &[u8] = b"a 1 x\nbb 22 y\n";
+        let right: &[u8] = b"a 1.5\nbbbb n/a\n";
+        let mut output: Vec<u8> = Vec::new();
+        column_info_diff(left, right, &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "Column 1: size 2 -> 4, max 2 -> 4\n\
+             Column 2: size 2 -> 3, max 2 -> 3, type number -> text\n\
+             Column 3: only in the first input (size 1, max 1, text)\n"
+        );
+    }
+
+    #[test]
+    fn json_events() {
+        let opts = Options {
+            online: true,
+            exclude_cols: Ranges(vec![Range::Between(3, 3)]),
+            events: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a \"1\" x\nbbb 2 x\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "{\"type\":\"layout\",\"columns\":[{\"size\":1,\"excluded\":false},{\"size\":3,\"excluded\":false},{\"size\":1,\"excluded\":true}]}\n\
+             {\"type\":\"row\",\"cells\":[\"a\",\"\\\"1\\\"\"]}\n\
+             {\"type\":\"layout\",\"columns\":[{\"size\":3,\"excluded\":false},{\"size\":3,\"excluded\":false},{\"size\":1,\"excluded\":true}]}\n\
+             {\"type\":\"row\",\"cells\":[\"bbb\",\"2\"]}\n"
+        );
+    }
+
+    #[test]
+    fn max_columns() {
+        let opts = Options {
+            max_columns: Some(2),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a b c  d\naa b\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a   b c  d\naa  b\n");
+    }
+
+    #[test]
+    fn preview() {
+        let opts = Options {
+            header: true,
+            preview: Some(2),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name n\na 1\nbb 2\ncccccc 3\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name  n\na   1\nbb  2\n");
+    }
+
+    #[test]
+    fn truncate_ratio() {
+        let opts = Options {
+            truncate: Some(Ranges(vec![Range::Between(1, 1)])),
+            ratio: 0.0,
+            truncate_ratio: Some(50.0),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a a\nb b\nc c\ndddddddddd dddddddddd\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  a\nb  b\nc  c\n…  dddddddddd\n");
+    }
+
+    #[test]
+    fn explain_sizing() {
+        let opts = Options {
+            explain_sizing: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a 1\nbb 2\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            concat!(
+                "Column 1 (ratio 1):\n",
+                "    size       waste    overflow       score\n",
+                "       1       0.000       0.500     258.602\n",
+                "       2       0.500       0.000     115.990\n",
+                "  chosen size: 2\n",
+                "Column 2 (ratio 1):\n",
+                "    size       waste    overflow       score\n",
+                "       1       0.000       0.000     429.490\n",
+                "  chosen size: 1\n",
+                "a   1\n",
+                "bb  2\n",
+            )
+        );
+    }
+
+    #[test]
+    fn null_values() {
+        let opts = Options {
+            null_values: vec![b"NULL".to_vec(), b"-".to_vec()],
+            empty_value: Some(b"n/a".to_vec()),
+            align_numbers: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a 1.5\nb NULL\nc -\nd 10\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a   1.5\nb   n/a\nc   n/a\nd  10\n");
+    }
+
+    #[test]
+    fn protect_numbers() {
+        let opts = Options {
+            truncate: Some(Ranges(vec![Range::From(1)])),
+            ignore_outliers: 25.0,
+            protect_numbers: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a 1 x\nb 2 y\nc 3 z\ndddddd 123456 wwwwww\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  1  x\nb  2  y\nc  3  z\n…  123456  wwwwww\n");
+    }
+
+    #[test]
+    fn underline_header() {
+        let mut opts = Options {
+            header: true,
+            underline_header: Some('-'),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name n\na 1\nbbb 22\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name  n\n----  --\na     1\nbbb  22\n");
+
+        opts.online = true;
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name  n\n----  -\na  1\nname  n\n----  --\nbbb  22\n");
+    }
+
+    #[test]
+    fn no_trailing_space() {
+        let mut opts = Options {
+            strict_delim: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a b c\nbb  \n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a   b  c\nbb     \n");
+
+        opts.no_trailing_space = true;
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a   b  c\nbb\n");
+    }
+
+    #[test]
+    fn sliding_window() {
+        let opts = Options {
+            online: true,
+            window: Some(2),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"aaaaaa 1.25\nb 1\nc 22\nd 3\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "aaaaaa  1.25\nb          1\nc  22\nd   3\n");
+    }
+
+    #[test]
+    fn online_layout_updates() {
+        let opts = Options {
+            online: true,
+            ratio: 0.0,
+            drop_constant: true,
+            ..Options::default()
+        };
+        let parser = RowParser::new(" ", false);
+        let (mut measure_columns, mut columns, mut sized) = (Vec::new(), Vec::new(), Vec::new());
+        let mut changes = Vec::new();
+        for line in &["a x 1", "b x 2", "cc x 3", "d y 4", "e x 5"] {
+            let mut row = Row::new();
+            parser.parse_into(&mut row, *line);
+            update_columns(&mut measure_columns, &row, &opts);
+            changes.push(update_layout(&mut columns, &mut sized, &measure_columns, &opts));
+        }
+        assert_eq!(changes, [true, true, true, true, false]);
+        let excluded: Vec<bool> = columns.iter().map(|c| c.is_excluded()).collect();
+        assert_eq!(excluded, [false, false, false]);
+    }
+
+    #[test]
+    fn continuation_lines() {
+        let opts = Options {
+            continuation: Some("^\\s".parse().unwrap()),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"INFO started\nERROR failed: x\n  at a()\n\tat b()\nINFO done\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "INFO   started\nERROR  failed:  x at a() at b()\nINFO   done\n"
+        );
+    }
+
+    #[test]
+    fn column_delimiters() {
+        let mut opts = Options {
+            column_delims: vec!["3:".parse().unwrap()],
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"Jan 1 host up  since boot\nFeb 12 db down\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "Jan   1  host  up  since boot\nFeb  12  db    down\n");
+
+        opts.column_delims = vec!["2:,".parse().unwrap()];
+        let input: &[u8] = b"a b c,d e\nf g h\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  b  c  d e\nf  g  h\n");
+    }
+
+    #[test]
+    fn extract_columns() {
+        let opts = Options {
+            header: true,
+            extract: vec![r"status=status=(\d+)".parse().unwrap()],
+            column_delims: vec!["1:".parse().unwrap()],
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"time message\n10:00 request status=200\n10:01 failed status=503 retry\n10:02 started\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "time   message                  status\n\
+             10:00  request status=200       200\n\
+             10:01  failed status=503 retry  503\n\
+             10:02  started                  \n"
+        );
+    }
+
+    #[test]
+    fn extract_columns_ragged() {
+        let mut opts = Options {
+            extract: vec![r"status=status=(\d+)".parse().unwrap()],
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a msg status=404\nb msg status=200 x\nc\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a  msg  status=404    404\n\
+             b  msg  status=200 x  200\n\
+             c                     \n"
+        );
+
+        opts.max_columns = Some(4);
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a  msg  status=404     404\n\
+             b  msg  status=200  x  200\n\
+             c                      \n"
+        );
+    }
+
+    #[test]
+    fn validate_columns() {
+        let mut opts = Options {
+            validate: vec![r"2:^\d+$".parse().unwrap(), "1:^[a-z]+$".parse().unwrap()],
+            color: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a 1\nB 2x\nc 3\n";
+        let mut output: Vec<u8> = Vec::new();
+        let err = process(BufReader::new(input), &mut output, &opts).unwrap_err();
+        assert!(matches!(err, Error::ValidationFailed { rows: 1 }), "{:?}", err);
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "a   1\n\x1b[1;31mB\x1b[0m  \x1b[1;31m2x\x1b[0m\nc   3\n"
+        );
+
+        // Without colors the invalid values are marked, and their columns
+        // are wide enough for the mark.
+        opts.color = false;
+        let mut output: Vec<u8> = Vec::new();
+        assert!(process(BufReader::new(input), &mut output, &opts).is_err());
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a     1\nB!  2x!\nc     3\n");
+
+        let mut output: Vec<u8> = Vec::new();
+        let mut invalid: Vec<u8> = Vec::new();
+        let err = process_with_invalid(BufReader::new(input), &mut output, &mut invalid, &opts).unwrap_err();
+        assert!(matches!(err, Error::ValidationFailed { rows: 1 }), "{:?}", err);
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a     1\nc     3\n");
+        assert_eq!(std::str::from_utf8(&invalid).unwrap(), "B    2x\n");
+
+        let input: &[u8] = b"a 1\nc 3\n";
+        assert!(process(BufReader::new(input), Vec::new(), &opts).is_ok());
+    }
+
+    #[test]
+    fn rejected_rows() {
+        let mut opts = Options {
+            header: true,
+            every: 2,
+            validate: vec![r"2:^\d+$".parse().unwrap()],
+            precision: vec![range::parse_range_value("2=0").unwrap()].into_iter().collect(),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name n\na 1.5\nb 2.25\nc x\nd 4\ne 5\n";
+        let mut output: Vec<u8> = Vec::new();
+        let mut rejects: Vec<u8> = Vec::new();
+        process_with_rejects(BufReader::new(input), &mut output, &mut rejects, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name  n\na  2\ne  5\n");
+        assert_eq!(std::str::from_utf8(&rejects).unwrap(), "b 2.25\nc x\nd 4\n");
+
+        opts.sort = vec!["1".parse().unwrap()];
+        let input: &[u8] = b"name n\nc x\nb 2\na 1\n";
+        let mut output: Vec<u8> = Vec::new();
+        let mut rejects: Vec<u8> = Vec::new();
+        process_with_rejects(BufReader::new(input), &mut output, &mut rejects, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name  n\na  1\n");
+        assert_eq!(std::str::from_utf8(&rejects).unwrap(), "b 2\nc x\n");
+    }
+
+    #[test]
+    fn checksum_ignores_formatting() {
+        fn checksum(input: &[u8], opts: &Options) -> Option<u64> {
+            let mut reader = RowReader::new(utils::byte_lines(input), opts);
+            let (mut measure_columns, mut columns, mut rows) = (Vec::new(), Vec::new(), Vec::new());
+            let mut row = Row::new();
+            while reader.read_row(&mut row, opts).unwrap() {
+                update_columns(&mut measure_columns, &row, opts);
+                rows.push(row.clone());
+            }
+            layout_columns(&mut columns, &measure_columns, opts);
+            let mut printer = RowPrinter::new(opts);
+            for row in &rows {
+                printer.print_row(&mut io::sink(), &columns, row).unwrap();
+            }
+            printer.checksum()
+        }
+
+        let mut opts = Options {
+            checksum: true,
+            ..Options::default()
+        };
+        let input: &[u8] = b"a 1\nbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb 22\nc 3\n";
+        let plain = checksum(input, &opts);
+        assert!(plain.is_some());
+        assert_eq!(process(BufReader::new(input), io::sink(), &opts).unwrap().checksum, plain);
+
+        opts.output_delim = b" | ".to_vec();
+        opts.truncate = Some(Ranges(vec![Range::From(1)]));
+        assert_eq!(checksum(input, &opts), plain);
+
+        assert_ne!(checksum(b"a 1\nb 22\nc 3\n", &opts), plain);
+        assert_ne!(checksum(b"a 1 bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\n22\nc 3\n", &opts), plain);
+    }
+
+    #[test]
+    fn footer_info() {
+        let opts = Options {
+            header: true,
+            truncate: Some(Ranges(vec![Range::From(1)])),
+            ratio: 100.0,
+            footer_info: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name n\na 1\nb 2\nc 3\nd 4\nlonglonglonglong 5\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "name  n\na  1\nb  2\nc  3\nd  4\n…  5\n# 5 rows, 2 columns, 1 truncated\n"
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let opts = Options {
+            header: true,
+            truncate: Some(Ranges(vec![Range::From(1)])),
+            ratio: 100.0,
+            exclude_cols: Ranges(vec![Range::From(3)]),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"name n x\na 1 x\nb 2 x\nlonglonglonglong 3 x\n";
+        let summary = process(BufReader::new(input), Vec::new(), &opts).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                rows: 3,
+                columns: 2,
+                widths: vec![1, 1],
+                truncated: 1,
+                checksum: None,
+                overflow: None,
+                hidden: Vec::new(),
+            }
+        );
+
+        // Lines printed as they were read are not rows.
+        let input: &[u8] = b"Report\na 1\nb 2\n\nTotal 3\n";
+        for online in [false, true] {
+            let opts = Options {
+                line_ranges: Some(Ranges(vec![Range::Between(2, 3)])),
+                online,
+                ..Options::default()
+            };
+            assert_eq!(process(BufReader::new(input), Vec::new(), &opts).unwrap().rows, 2);
+            assert_eq!(process_threaded(BufReader::new(input), Vec::new(), &opts).unwrap().rows, 2);
+        }
+        let opts = Options {
+            pass_ansi_lines: true,
+            ..Options::default()
+        };
+        let input: &[u8] = b"\x1b[2J\na 1\n";
+        assert_eq!(process(BufReader::new(input), Vec::new(), &opts).unwrap().rows, 1);
+    }
+
+    #[test]
+    fn auto_hide() {
+        let mut opts = Options {
+            fit: true,
+            width: Some(20),
+            auto_hide: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"alpha beta gamma delta epsilon zeta\n";
+        let mut output: Vec<u8> = Vec::new();
+        let summary = process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "alpha  beta  gamma\n");
+        assert_eq!(summary.hidden, ["Column 4", "Column 5", "Column 6"]);
+
+        opts.weights = vec![range::parse_range_value("1=0.5").unwrap()].into_iter().collect();
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "beta  gamma  delta\n");
+    }
+
+    #[test]
+    fn display_widths() {
+        let mut opts = Options::default();
+        let input = "名前 x\nbob y\ncafe\u{301} z\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input.as_bytes()), &mut output, &opts).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "名前  x\nbob   y\ncafe\u{301}  z\n");
+
+        opts.byte_widths = true;
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input.as_bytes()), &mut output, &opts).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "名前  x\nbob     y\ncafe\u{301}  z\n");
+    }
+
+    #[test]
+    fn ignore_ansi() {
+        let mut opts = Options {
+            ignore_ansi: true,
+            truncate: Some(Ranges(vec![Range::Between(1, 1)])),
+            ..Options::default()
+        };
+        let input: &[u8] = b"\x1b[01;34mdir\x1b[0m a\nfile b\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(output, b"\x1b[01;34mdir\x1b[0m   a\nfile  b\n");
+
+        opts.fit = true;
+        opts.width = Some(6);
+        let input: &[u8] = b"\x1b[31mlonger\x1b[0m a\nx b\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(output, "\x1b[31mlo…\x1b[0m  a\nx    b\n".as_bytes());
+    }
+
+    #[test]
+    fn wide_output_delimiter() {
+        let opts = Options {
+            fit: true,
+            width: Some(8),
+            output_delim: " │ ".as_bytes().to_vec(),
+            ..Options::default()
+        };
+        let input: &[u8] = b"aaa bb\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "aaa │ bb\n");
+    }
+
+    #[test]
+    fn align() {
+        let opts = Options {
+            align: vec![
+                range::parse_range_value("1=right").unwrap(),
+                range::parse_range_value("2-=center").unwrap(),
+            ]
+            .into_iter()
+            .collect(),
+            ..Options::default()
+        };
+        let input: &[u8] = b"a bb c\nccc d eee\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "  a  bb   c\nccc  d   eee\n");
+        assert!("middle".parse::<Alignment>().is_err());
+    }
+
+    #[test]
+    fn numbers_right_aligned() {
+        let mut opts = Options::default();
+        let input: &[u8] = b"a 5 x\nbb 120 y\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a     5  x\nbb  120  y\n");
+
+        opts.left_align_numbers = true;
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a   5    x\nbb  120  y\n");
+    }
+
+    #[test]
+    fn join_continuations() {
+        let opts = Options {
+            join_continuations: true,
+            ..Options::default()
+        };
+        let input: &[u8] = b"a b \\\n  c\nd\\\\ e\nf g\\";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a    b   c\nd\\\\  e\nf    g\\\n");
+    }
+
+    #[test]
+    fn header_style() {
+        let mut opts = Options {
+            header: true,
+            header_style: Some("bold+underline".parse().unwrap()),
+            ..Options::default()
+        };
+        let input: &[u8] = b"name value\nlonger_name x\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name         value\nlonger_name  x\n");
+
+        opts.color = true;
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "\x1b[1;4mname\x1b[0m         \x1b[1;4mvalue\x1b[0m\nlonger_name  x\n"
+        );
+    }
+
+    #[test]
+    fn pass_ansi_lines() {
+        let opts = Options {
+            pass_ansi_lines: true,
+            every: 2,
+            ..Options::default()
+        };
+        let input: &[u8] = b"a 1\n\x1b[2K\x1b[1G\nskipped 0\n  \nbbb 22\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a     1\n\x1b[2K\x1b[1G\n  \nbbb  22\n");
+    }
+
+    #[test]
+    fn locked_columns() {
+        let layout = Layout::read("3 locked\n2\n".as_bytes(), "  ").unwrap();
+        let opts = Options {
+            layout: Some(layout),
+            online: true,
+            fit: true,
+            width: Some(12),
+            ..Options::default()
+        };
+        let input: &[u8] = b"a bbbbbbbbbbbb\nccccc d\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a    bbbbbb…\nccccc  d\n");
+    }
+
+    #[test]
+    fn select_columns_by_name() {
+        let mut opts = Options {
+            header: true,
+            include_names: vec![b"NAME".to_vec(), b"STATUS".to_vec()],
+            ..Options::default()
+        };
+        let input: &[u8] = b"ID NAME STATUS AGE\n1 web up 3d\n2 db down 1h\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "NAME  STATUS\nweb  up\ndb   down\n");
+
+        opts.include_names.clear();
+        opts.include_cols = Some(Ranges(vec![Range::Between(1, 2)]));
+        opts.exclude_names = vec![b"NAME".to_vec()];
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "ID\n1\n2\n");
+
+        opts.exclude_names = vec![b"NOPE".to_vec()];
+        let err = process(BufReader::new(input), Vec::new(), &opts).unwrap_err();
+        assert!(matches!(err, Error::UnknownColumn { ref s } if s == "NOPE"), "{:?}", err);
+        opts.exclude_names.clear();
+        opts.info_names = vec![b"NOPE".to_vec()];
+        assert!(process(BufReader::new(input), Vec::new(), &opts).is_err());
+    }
+
+    #[test]
+    fn header_align() {
+        let opts = Options {
+            header: true,
+            header_align: Some("c".parse().unwrap()),
+            ..Options::default()
+        };
+        let input: &[u8] = b"id name\n12345 a\n6 abcdefg\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), " id     name\n12345  a\n    6  abcdefg\n");
+    }
+
+    #[test]
+    fn border() {
+        let opts = Options {
+            header: true,
+            border: true,
+            group_sep: Some(1),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"kind n note\na 1 x\na 22\nb 3 hello\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            concat!(
+                "┌──────┬────┬───────┐\n",
+                "│ kind │  n │ note  │\n",
+                "├──────┼────┼───────┤\n",
+                "│ a    │  1 │ x     │\n",
+                "│ a    │ 22 │       │\n",
+                "├──────┼────┼───────┤\n",
+                "│ b    │  3 │ hello │\n",
+                "└──────┴────┴───────┘\n",
+            )
+        );
+    }
+
+    #[test]
+    fn line_ranges() {
+        let mut opts = Options {
+            header: true,
+            line_ranges: Some(Ranges(vec![Range::Between(3, 5)])),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"Report:\n\nname n\nalpha 1\nb 22\n\nend of report\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "Report:\n\nname    n\nalpha   1\nb      22\n\nend of report\n"
+        );
+
+        opts.drop_other_lines = true;
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name    n\nalpha   1\nb      22\n");
+    }
+
+    #[test]
+    fn auto_detect_table() {
+        let mut opts = Options {
+            header: true,
+            auto_detect_table: true,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"Report for today\n\nname n\nalpha 1\nb 22\n\nTotal: 2\n";
+        let mut output: Vec<u8> = Vec::new();
+        process_threaded(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "Report for today\n\nname    n\nalpha   1\nb      22\n\nTotal: 2\n"
+        );
+
+        opts.drop_other_lines = true;
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name    n\nalpha   1\nb      22\n");
+
+        let input: &[u8] = b"no table\nhere\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "");
+    }
+
+    #[test]
+    fn pages() {
+        let mut opts = Options {
+            header: true,
+            page_length: Some(2),
+            ..Options::default()
+        };
+        let input: &[u8] = b"name n\na 1\nb 2\nc 3\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "name  n\na  1\nb  2\n\u{c}name  n\nc  3\n");
+
+        opts.header = false;
+        opts.page_numbers = true;
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "Page 1\nname  n\na     1\n\u{c}Page 2\nb     2\nc     3\n");
+    }
+
+    #[test]
+    fn threaded() {
+        let mut opts = Options {
+            header: true,
+            check_columns: true,
+            ..Options::default()
+        };
+        let input: &[u8] = b"name n\na 1\nbbb 22\n";
+        let mut expected: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut expected, &opts).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        process_threaded(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(output, expected);
+
+        opts.quiet = true;
+        let input: &[u8] = b"name n\na 1\nbbb\n";
+        let result = process_threaded(BufReader::new(input), io::sink(), &opts);
+        assert!(matches!(result, Err(Error::ColumnCheckFailed { rows: 1, line: 3, .. })));
+    }
+
+    #[test]
+    fn ignore_outliers() {
+        let opts = Options {
+            ratio: 0.0,
+            ignore_outliers: 25.0,
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"a 1\nb 2\nc 3\ndddddddddd 4\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "a  1\nb  2\nc  3\ndddddddddd  4\n");
+    }
+
+    #[test]
+    fn check_options() {
+        assert!(Options::default().check().is_empty());
+
+        let opts = Options {
+            ratio: -1.0,
+            exclude_cols: Ranges(vec![Range::From(2)]),
+            find: Some("3 x".parse().unwrap()),
+            pin: Some(Ranges(vec![Range::Between(1, 1)])),
+            ..Options::default()
+        };
+        assert_eq!(
+            opts.check(),
+            [
+                "the ratio must be a number of at least 0, not -1",
+                "pinned and right edge columns and weights only apply when fitting to the output width",
+                "the column searched for a match (3) is excluded",
+            ]
+        );
+    }
+
+    #[test]
+    fn estimate_mem() {
+        let row_size = memory_size(&{
+            let mut row = Row::new();
+            RowParser::new(" ", false).parse_into(&mut row, "aa bb");
+            row
+        });
+        let opts = Options {
+            lines: 1,
+            estimate_mem: Some(row_size + 1),
+            ..Options::default()
+        };
+
+        let input: &[u8] = b"aa bb\nc d\neeee f\n";
+        let mut output: Vec<u8> = Vec::new();
+        process(BufReader::new(input), &mut output, &opts).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "aa  bb\nc   d\neeee  f\n");
+    }
+}
+
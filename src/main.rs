@@ -1,9 +1,28 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 use clap::Parser;
 
 use tabulate::{
+    bench::Shape,
+    boolean::Booleans,
+    color::{Style, Threshold},
+    compute::Computation,
     errors::*,
-    range::Ranges,
-    Options,
+    layout::Layout,
+    parser::ColumnDelimiter,
+    pattern::{ColumnPattern, Extraction, Regex, Validation},
+    project::Projection,
+    range::{Range, Ranges},
+    rename::Rename,
+    sort::SortKey,
+    spark::Spark,
+    Alignment, AtomicFile, Options, SizingStrategy,
 };
 
 const BUILD_INFO: &str = include_str!(concat!(env!("OUT_DIR"), "/build-info.txt"));
@@ -41,16 +60,26 @@ impl clap::builder::TypedValueParser for RangesValueParser {
    }
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, about, long_about = None)]
 #[command(version = format!("{}{}", env!("CARGO_PKG_VERSION"), BUILD_INFO))]
 #[command(next_line_help = true, color = clap::ColorChoice::Never)]
+#[command(group = clap::ArgGroup::new("streaming").args(["online", "follow"]).multiple(true))]
 #[command(after_help = r#"LIST should be a comma-separated list of ranges. Each range should be of one of the following forms:
 
   N       N'th column, starting at 1
   N-      from N'th column to end of line
   N-M     from N'th to M'th column
-  -M      from first to M'th column"#)]
+  -M      from first to M'th column
+
+Exit status:
+
+  0       success
+  1       no row matched --find
+  2       invalid arguments
+  3       I/O error
+  4       some rows failed --check-columns
+  5       some rows failed --validate"#)]
 struct Args {
     /// Truncate data that does not fit in a column.
     /// Takes an optional list of columns that should be truncated.
@@ -63,72 +92,1274 @@ struct Args {
     #[arg(short = 'c', long = "compress-cols", value_name = "RATIO", num_args = 1, default_value = "1.0")]
     pub ratio: f64,
 
+    /// The RATIO for the columns selected by --truncate. Values that do not fit
+    /// in a truncated column are cut off, so it can be compressed harder.
+    #[arg(long, value_name = "RATIO", num_args = 1, requires = "truncate")]
+    pub truncate_ratio: Option<f64>,
+
+    /// How the best size of a column is searched for: first-minimum stops at the
+    /// first size that is no better than the previous one, exhaustive tries every
+    /// size, and auto is exhaustive for columns whose values vary little in width.
+    #[arg(long, value_name = "STRATEGY", num_args = 1, default_value = "auto", value_parser = parse_from_str::<SizingStrategy>)]
+    pub sizing_strategy: SizingStrategy,
+
+    /// Count the widths of the values in buckets, which are wider the wider the
+    /// values are, instead of exactly. Bounds memory use and speeds up sizing for
+    /// long inputs whose values vary a lot in width, at the cost of sizes that
+    /// may be off by a few percent for values longer than 64 characters.
+    #[arg(long)]
+    pub bucket_widths: bool,
+
+    /// Ignore the widest P percent of the values of each column when sizing it,
+    /// so a few very long values do not widen an otherwise narrow column.
+    #[arg(long, value_name = "P", num_args = 1, default_value = "0")]
+    pub ignore_outliers: f64,
+
     /// Estimate column sizes from the first N lines. The value 0 means all lines.
     #[arg(short = 'n', long = "estimate-count", value_name = "N", num_args = 1, default_value_t = 1000)]
     pub lines: usize,
 
-    /// Select which columns to include in the output.
-    #[arg(short = 'i', long = "include", value_name = "LIST", value_delimiter = ',', num_args = 1.., value_parser = RangesValueParser)]
-    pub include_cols: Option<Ranges>,
+    /// Estimate column sizes from as many lines as fit in SIZE bytes of memory,
+    /// instead of a fixed number of lines. SIZE may end with K, M or G.
+    #[arg(long, value_name = "SIZE", num_args = 1, value_parser = parse_size, conflicts_with = "lines")]
+    pub estimate_mem: Option<usize>,
+
+    /// Select which columns to include in the output. With --header, the list
+    /// may contain headers as well as ranges.
+    #[arg(short = 'i', long = "include", value_name = "LIST", value_delimiter = ',', num_args = 1..)]
+    pub include_cols: Option<Vec<String>>,
 
-    /// Select which columns should be excluded from the output.
+    /// Select which columns should be excluded from the output. With --header,
+    /// the list may contain headers as well as ranges.
     /// This option takes precedence over --include.
-    #[arg(short = 'x', long = "exclude", value_name = "LIST", value_delimiter = ',', num_args = 1.., value_parser = RangesValueParser)]
-    pub exclude_cols: Option<Ranges>,
+    #[arg(short = 'x', long = "exclude", value_name = "LIST", value_delimiter = ',', num_args = 1..)]
+    pub exclude_cols: Option<Vec<String>>,
 
     /// Use characters of DELIM as column delimiters.
+    /// Bytes that are not valid UTF-8 are treated as single-byte delimiters.
     #[arg(short = 'd', long = "delimiter", value_name = "DELIM", num_args = 1, default_value = " \t")]
-    pub delim: String,
+    pub delim: OsString,
 
     /// Specify the delimiter to use to separate columns in the output.
     #[arg(short = 'o', long = "output-delimiter", value_name = "DELIM", num_args = 1, default_value = "  ")]
-    pub output_delim: String,
+    pub output_delim: OsString,
 
     /// Parse columns as strictly being delimited by a single delimiter.
     #[arg(short = 's', long = "strict")]
     pub strict_delim: bool,
 
+    /// Use characters of DELIM as strict column delimiters, while the other
+    /// delimiters still collapse.
+    #[arg(short = 'S', long = "strict-delimiter", value_name = "DELIM", num_args = 1)]
+    pub strict_delims: Option<OsString>,
+
     /// Print lines during column size estimation phase.
     #[arg(long)]
     pub online: bool,
 
-    /// Print information about the columns.
-    #[arg(long = "column-info", conflicts_with = "online")]
-    pub print_info: bool,
+    /// Treat the first line as a header. The header is never truncated and is
+    /// not used when estimating column sizes.
+    #[arg(long)]
+    pub header: bool,
+
+    /// Never make a column narrower than its header.
+    #[arg(long, requires = "header")]
+    pub header_floor: bool,
+
+    /// Print a rule of CHAR under the header, as wide as each column or its
+    /// header, whichever is wider. In online mode the header and the rule are
+    /// printed again whenever the column sizes change.
+    #[arg(long, value_name = "CHAR", num_args = 1, requires = "header")]
+    pub underline_header: Option<char>,
+
+    /// Print the header in STYLE, such as 'bold' or 'bold+underline+cyan',
+    /// when colors are enabled.
+    #[arg(long, value_name = "STYLE", num_args = 1, requires = "header", value_parser = parse_from_str::<Style>)]
+    pub header_style: Option<Style>,
+
+    /// Align the headers to the left (l), center (c) or right (r) of their
+    /// columns, instead of like the values below them.
+    #[arg(long, value_name = "l|c|r", num_args = 1, requires = "header", value_parser = parse_from_str::<Alignment>)]
+    pub header_align: Option<Alignment>,
+
+    /// Rename columns of the header, e.g. 'req_count=Requests,3=Bytes'. A
+    /// column is given by its name in the input or its number.
+    #[arg(long, value_name = "OLD=NEW,...", value_delimiter = ',', num_args = 1.., requires = "header", value_parser = parse_from_str::<Rename>)]
+    pub rename: Vec<Rename>,
+
+    /// Read the header, for --rename and --header-floor, but leave it out of
+    /// the output.
+    #[arg(long, requires = "header")]
+    pub no_header_out: bool,
+
+    /// Instead of aligned text, print a JSON object per line: a "layout"
+    /// event with the size of every column whenever it changes, and a "header"
+    /// or "row" event with the cells of every printed line.
+    #[arg(long, conflicts_with_all = ["tail", "side_by_side", "column_info_diff"])]
+    pub events: bool,
+
+    /// Print every line as soon as it is read and flush the output, for
+    /// following input that grows over time.
+    #[arg(short = 'f', long)]
+    pub follow: bool,
+
+    /// With --online or --follow, buffer the output and flush it every
+    /// DURATION (e.g. 100ms or 2s) instead of after every line.
+    #[arg(long, value_name = "DURATION", num_args = 1, value_parser = parse_duration, requires = "streaming")]
+    pub flush_interval: Option<Duration>,
+
+    /// Only print every N-th row, starting with the first, for thinning out
+    /// fast streams. The header is always printed.
+    #[arg(long, value_name = "N", num_args = 1, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub every: u64,
+
+    /// Put everything from the N-th column on into the N-th column, for
+    /// lines with very many fields.
+    #[arg(long, value_name = "N", num_args = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_columns: Option<u64>,
+
+    /// Only read and print the first N lines, for trying out options on a
+    /// large input.
+    #[arg(long, value_name = "N", num_args = 1, conflicts_with = "follow")]
+    pub preview: Option<usize>,
+
+    /// Check the options for values that are out of range or conflict with
+    /// each other, and exit without reading any input.
+    #[arg(long, conflicts_with_all = ["output", "in_place"])]
+    pub check_config: bool,
+
+    /// Leave out the columns whose value is the same in every measured line.
+    #[arg(long, conflicts_with_all = ["online", "follow"])]
+    pub drop_constant: bool,
+
+    /// Print NAME=VALUE for every column left out by --drop-constant before
+    /// the table. NAME is the header of the column or its number.
+    #[arg(long, requires = "drop_constant")]
+    pub show_dropped: bool,
+
+    /// Leave out the columns that are empty in every measured line.
+    #[arg(long)]
+    pub drop_empty: bool,
+
+    /// Leave out the columns where REGEX matches every measured value, e.g.
+    /// '^-?$' for columns that only hold dashes or nothing.
+    #[arg(long, value_name = "REGEX", num_args = 1, value_parser = parse_from_str::<Regex>)]
+    pub exclude_matching: Option<Regex>,
+
+    /// Append the lines that match REGEX to the last column of the row before
+    /// them, e.g. '^\s' for the indented lines of stack traces in logs.
+    #[arg(long, value_name = "REGEX", num_args = 1, value_parser = parse_from_str::<Regex>)]
+    pub continuation: Option<Regex>,
+
+    /// Join lines that end in a backslash with the next line, as in shell
+    /// scripts and many config formats. The backslash is removed.
+    #[arg(long)]
+    pub join_continuations: bool,
+
+    /// Split the rest of the line after column COL on the characters of DELIM
+    /// instead, or keep it as one column if DELIM is empty. Can be given more
+    /// than once.
+    #[arg(long = "col-delim", value_name = "COL:DELIM", num_args = 1, value_parser = parse_from_str::<ColumnDelimiter>)]
+    pub column_delims: Vec<ColumnDelimiter>,
+
+    /// Read FILE1 and FILE2 instead of the standard input and print them next
+    /// to each other with the same column sizes.
+    #[arg(long, value_names = ["FILE1", "FILE2"], num_args = 2, conflicts_with_all = ["online", "follow", "tail", "measure_only", "append_layout", "print_info", "column_info_file"])]
+    pub side_by_side: Option<Vec<PathBuf>>,
+
+    /// Read FILE1 and FILE2 instead of the standard input and print how the
+    /// size and type of each column differ between them.
+    #[arg(long, value_names = ["FILE1", "FILE2"], num_args = 2, conflicts_with_all = ["online", "follow", "tail", "measure_only", "append_layout", "print_info", "column_info_file", "side_by_side"])]
+    pub column_info_diff: Option<Vec<PathBuf>>,
+
+    /// Only show the last N rows. With --follow the rows are redrawn in place
+    /// whenever a new row is read.
+    #[arg(long, value_name = "N", num_args = 1)]
+    pub tail: Option<usize>,
+
+    /// With --follow or --online, size the columns from the last N rows instead
+    /// of from the first rows, so the layout adapts when the input changes shape.
+    #[arg(long, value_name = "N", num_args = 1, conflicts_with = "tail", value_parser = clap::value_parser!(u64).range(1..))]
+    pub window: Option<u64>,
+
+    /// Read and parse the input on one thread while measuring and printing the
+    /// rows on another, which is faster for large inputs on multi-core machines.
+    #[arg(long, conflicts_with_all = ["follow", "tail", "measure_only", "append_layout", "column_info_file"])]
+    pub threads: bool,
+
+    /// Print information about the columns. Takes an optional list of columns to
+    /// print details about; the other columns are summarized in one line each.
+    /// With --header, columns are shown by their header and the list may
+    /// contain headers as well as ranges.
+    #[arg(long = "column-info", value_name = "LIST", value_delimiter = ',', num_args = 0.., default_missing_value = "1-", conflicts_with_all = ["online", "follow", "tail"])]
+    pub print_info: Option<Vec<String>>,
+
+    /// Before the table, print the sizes evaluated for each column with their
+    /// waste, overflow and score, and the size chosen. Useful when tuning -c.
+    /// Written to the --column-info-file FILE if there is one.
+    #[arg(long)]
+    pub explain_sizing: bool,
+
+    /// Write information about the columns to FILE, while still printing the data.
+    #[arg(long = "column-info-file", value_name = "FILE", num_args = 1, conflicts_with = "print_info")]
+    pub column_info_file: Option<PathBuf>,
+
+    /// Write the rows left out by --every and the rows that fail --validate to
+    /// FILE, unchanged, instead of dropping or printing them.
+    #[arg(long, value_name = "FILE", num_args = 1,
+          conflicts_with_all = ["threads", "measure_only", "append_layout", "column_info_file", "column_info_diff", "side_by_side"])]
+    pub rejects: Option<PathBuf>,
+
+    /// Write the output to FILE instead of the standard output. FILE is only
+    /// replaced, all at once, after all of the input has been processed.
+    #[arg(long, value_name = "FILE", num_args = 1, conflicts_with_all = ["follow", "tail"])]
+    pub output: Option<PathBuf>,
+
+    /// Read FILE instead of the standard input and replace it with the output,
+    /// all at once, after all of it has been processed.
+    #[arg(long, value_name = "FILE", num_args = 1, group = "rewrite",
+          conflicts_with_all = ["output", "follow", "tail", "side_by_side", "column_info_diff", "generate", "bench"])]
+    pub in_place: Option<PathBuf>,
+
+    /// Replace every file matching GLOB, such as 'reports/*.txt', with its
+    /// output, or write the output to --batch-dir. Quote GLOB so the shell
+    /// does not expand it.
+    #[arg(long, value_name = "GLOB", num_args = 1, group = "rewrite",
+          conflicts_with_all = ["output", "in_place", "follow", "tail", "side_by_side", "column_info_diff", "measure_only",
+                                "append_layout", "rejects", "column_info_file", "generate", "bench"])]
+    pub batch: Option<String>,
+
+    /// Write the output for every file of --batch to DIR, instead of replacing
+    /// the file. Files keep their path below the part of GLOB before the first
+    /// wildcard, e.g. 'reports/*/log.txt' writes 'DIR/2024/log.txt' for
+    /// 'reports/2024/log.txt'.
+    #[arg(long, value_name = "DIR", num_args = 1, requires = "batch")]
+    pub batch_dir: Option<PathBuf>,
+
+    /// Measure all files of --batch first and give the columns of every file
+    /// the same sizes.
+    #[arg(long, requires = "batch")]
+    pub shared_layout: bool,
+
+    /// Print a unified diff of how --in-place or --batch would change each
+    /// file, instead of changing it.
+    #[arg(long, requires = "rewrite", conflicts_with = "batch_dir")]
+    pub diff: bool,
+
+    /// Let the process be terminated by SIGPIPE when the reader of the output
+    /// goes away, e.g. when a pager is closed, instead of handling the error.
+    #[arg(long)]
+    pub default_sigpipe: bool,
+
+    /// Process generated input of ROWS rows of COLS columns, with values
+    /// MIN-MAX bytes wide, and report the lines per second, and the allocations
+    /// if built with the count-allocations feature.
+    #[arg(long, value_name = "ROWS,COLS[,MIN-MAX]", num_args = 1, hide = true, value_parser = parse_from_str::<Shape>,
+          conflicts_with_all = ["follow", "threads", "measure_only", "append_layout", "column_info_file", "column_info_diff", "side_by_side", "output"])]
+    pub bench: Option<Shape>,
+
+    /// Print a table of ROWS rows of COLS columns of random words and numbers,
+    /// instead of reading input. The same SEED always gives the same table.
+    #[arg(long, value_name = "ROWS,COLS[,SEED]", num_args = 1, conflicts_with_all = ["bench", "output"], value_parser = parse_table_shape)]
+    pub generate: Option<Shape>,
+
+    /// Never make columns narrower than in the layout saved in FILE, and save the
+    /// layout, widened where the input needs it, back to FILE. Keeps the columns
+    /// of a report consistent when it is appended to by repeated runs.
+    #[arg(long, value_name = "FILE", num_args = 1, conflicts_with = "column_info_file")]
+    pub append_layout: Option<PathBuf>,
+
+    /// Only measure the columns of all input and print their layout, to be used
+    /// with --load-layout in a second pass. Nothing is kept in memory.
+    #[arg(long, conflicts_with_all = ["online", "follow", "tail", "print_info", "column_info_file", "append_layout"])]
+    pub measure_only: bool,
+
+    /// Never make columns narrower than in the layout in FILE, as written by
+    /// --measure-only, and print every row as soon as it is read. Columns
+    /// marked 'locked' in FILE are kept at exactly their size, even with --fit.
+    #[arg(long, value_name = "FILE", num_args = 1, conflicts_with_all = ["append_layout", "measure_only"])]
+    pub load_layout: Option<PathBuf>,
+
+    /// Do not print a hint on stderr when reading from a terminal.
+    #[arg(long)]
+    pub no_tty_hint: bool,
+
+    /// Highlight rows where column COL matches the regular expression REGEX.
+    /// The exit status is 1 if no row matched.
+    #[arg(long, value_name = "COL REGEX", num_args = 1, value_parser = parse_from_str::<ColumnPattern>)]
+    pub find: Option<ColumnPattern>,
+
+    /// Check that every value of column COL matches REGEX, e.g. '2:^\d+$'. The
+    /// values that do not are highlighted, or followed by --invalid-mark when
+    /// colors are off, see also --invalid. The exit status is 5 if any row
+    /// failed. Can be given more than once.
+    #[arg(long, value_name = "COL:REGEX", num_args = 1, value_parser = parse_from_str::<Validation>)]
+    pub validate: Vec<Validation>,
+
+    /// What to do with rows that fail --validate: flag their invalid values,
+    /// or print the rows to stderr instead of the output.
+    #[arg(long, value_name = "WHERE", value_enum, default_value_t = InvalidRows::Flag, requires = "validate",
+          conflicts_with_all = ["append_layout", "rejects", "column_info_file", "threads"])]
+    pub invalid: InvalidRows,
+
+    /// The mark printed after values that fail --validate when colors are off.
+    #[arg(long = "invalid-mark", value_name = "MARK", num_args = 1, default_value = "!")]
+    pub invalid_mark: OsString,
+
+    /// Print a hash of the printed values to stderr at the end. It does not
+    /// depend on how the values were padded or truncated, so runs with
+    /// different formatting options print the same hash for the same data.
+    #[arg(long, conflicts_with_all = ["tail", "measure_only", "events", "column_info_diff", "side_by_side"])]
+    pub checksum: bool,
+
+    /// Print a `cut -b` range and an `awk` expression for every printed column
+    /// to stderr at the end, for scripts that slice columns out of the output.
+    /// `cut` counts bytes, so its ranges only hold when the values are ASCII.
+    #[arg(long, conflicts_with_all = ["tail", "measure_only", "events", "column_info_diff", "side_by_side",
+                                      "rejects", "column_info_file", "threads", "invalid"])]
+    pub emit_cut: bool,
+
+    /// Print a comment line after the table with the number of rows and
+    /// columns and how many values were truncated, so readers of a saved
+    /// table can tell whether it is complete.
+    #[arg(long, conflicts_with_all = ["tail", "measure_only", "events"])]
+    pub footer_info: bool,
+
+    /// Split the output into pages of N rows for printers and fixed-height
+    /// displays. Every page starts with the header, and pages after the first
+    /// with a form feed.
+    #[arg(long, value_name = "N", conflicts_with_all = ["measure_only", "events"],
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub page_length: Option<u64>,
+
+    /// Start every page with a line with the page number.
+    #[arg(long, requires = "page_length")]
+    pub page_numbers: bool,
+
+    /// Do not print warnings. With --find, do not print the table either, only
+    /// set the exit status.
+    #[arg(short = 'q', long)]
+    pub quiet: bool,
+
+    /// Check that every row has the same number of columns as the first row.
+    /// Rows that do not are reported as warnings and the exit status is 4.
+    #[arg(long)]
+    pub check_columns: bool,
+
+    /// Warn on stderr if values after the first N rows (see -n) often did not
+    /// fit in the estimated column sizes.
+    #[arg(long)]
+    pub warn_estimate: bool,
+
+    /// Build the output columns from the input columns. EXPRS is a comma-separated
+    /// list of column numbers, "string literals" and the functions upper(E),
+    /// lower(E), trim(E), len(E) and concat(E, ...), e.g. '1,3,"-",upper(4)'.
+    #[arg(long, value_name = "EXPRS", num_args = 1, value_parser = parse_from_str::<Projection>)]
+    pub project: Option<Projection>,
+
+    /// Append a column computed from other columns. EXPR can use + - * /,
+    /// parentheses, numbers and the columns col1, col2, ..., e.g. 'ratio=col3/col4'.
+    /// NAME is used as the header. The value is empty if it cannot be computed.
+    #[arg(long, value_name = "NAME=EXPR", num_args = 1, value_parser = parse_from_str::<Computation>)]
+    pub compute: Vec<Computation>,
+
+    /// Append a column with the first group matched by REGEX in the line, or
+    /// the whole match if it has no groups, e.g. 'status=status=(\d+)'. NAME is
+    /// used as the header. The value is empty if REGEX does not match. The
+    /// column comes after as many fields as --max-columns, or else the header
+    /// or the first row has, and longer rows have their last fields merged.
+    #[arg(long, value_name = "NAME=REGEX", num_args = 1, value_parser = parse_from_str::<Extraction>)]
+    pub extract: Vec<Extraction>,
+
+    /// Append a column with the value of column COL as a percentage of the total
+    /// of the column. All input is read before anything is printed.
+    #[arg(long, value_name = "COL", num_args = 1, conflicts_with = "follow", value_parser = clap::value_parser!(u32).range(1..))]
+    pub percent: Option<u32>,
+
+    /// Append a sparkline of the last WINDOW (default 8) numeric values of
+    /// column COL, for watching a value change with --follow or --online.
+    #[arg(long, value_name = "COL[:WINDOW]", num_args = 1, value_parser = parse_from_str::<Spark>)]
+    pub spark: Option<Spark>,
+
+    /// Color the numeric cells of column COL depending on their value, e.g.
+    /// '3:>100=red,>50=yellow'. The first matching rule is used. The comparisons
+    /// are <, <=, =, >= and >. A style is a color name, optionally combined with
+    /// bold, underline or reverse, e.g. 'bold+red'. Only used when colors are on.
+    #[arg(long, value_name = "COL:RULES", num_args = 1, value_parser = parse_from_str::<Threshold>)]
+    pub threshold: Vec<Threshold>,
+
+    /// Round the numbers in the columns in LIST to N decimals before the columns
+    /// are measured.
+    #[arg(long, value_name = "LIST=N", num_args = 1, value_parser = parse_range_value::<usize>)]
+    pub precision: Vec<(Ranges, usize)>,
+
+    /// Treat the values in LIST, e.g. 'NULL,\N,None,-', as missing: they are
+    /// printed as empty cells or as --empty-value, and are not taken as text when
+    /// deciding whether a column holds numbers.
+    #[arg(long, value_name = "LIST", value_delimiter = ',', num_args = 1..)]
+    pub null_values: Vec<OsString>,
+
+    /// Print VALUE instead of empty cells and values given to --null-values.
+    #[arg(long, value_name = "VALUE", num_args = 1)]
+    pub empty_value: Option<OsString>,
+
+    /// Rewrite truthy values (true, t, yes, y, on, 1) in the columns in LIST to
+    /// TRUE and falsy values (false, f, no, n, off, 0) to FALSE, ignoring case,
+    /// e.g. '3,5=yes/no' or '2=✓/✗'.
+    #[arg(long, value_name = "LIST=TRUE/FALSE", num_args = 1, value_parser = parse_range_value::<Booleans>)]
+    pub booleans: Vec<(Ranges, Booleans)>,
+
+    /// Align the values in the columns in LIST to the left, right or center of
+    /// the column. By default columns where every value is a number are
+    /// right-aligned and other columns are left-aligned.
+    #[arg(long, value_name = "LIST=ALIGN", num_args = 1, value_parser = parse_range_value::<Alignment>)]
+    pub align: Vec<(Ranges, Alignment)>,
+
+    /// Left-align columns where every value is a number, unless they are
+    /// aligned otherwise with --align.
+    #[arg(long)]
+    pub left_align_numbers: bool,
+
+    /// Right-align columns where every value is a number, lining up the decimal
+    /// points. Numbers may have a currency symbol like $ or a unit like %, ms or KiB.
+    #[arg(long)]
+    pub align_numbers: bool,
+
+    /// Never truncate columns where every value is a number, not even with
+    /// --truncate or --fit, since cutting off digits changes the value. Such
+    /// columns overflow instead.
+    #[arg(long)]
+    pub protect_numbers: bool,
+
+    /// Measure and pad values by their length in bytes instead of by how many
+    /// terminal columns they take up. Faster, but misaligns wide and combining
+    /// characters.
+    #[arg(long)]
+    pub byte_widths: bool,
+
+    /// Do not count ANSI escape sequences, such as the colors from `ls
+    /// --color` or `grep --color=always`, towards the width of values. They
+    /// are still printed.
+    #[arg(long)]
+    pub ignore_ansi: bool,
+
+    /// Print lines with nothing but whitespace and ANSI escape sequences, such
+    /// as the spinners and cursor movements of the program writing the input,
+    /// as they are, without measuring them.
+    #[arg(long)]
+    pub pass_ansi_lines: bool,
+
+    /// Only tabulate the lines in LIST, such as 5-20, counted from 1, and
+    /// print the other lines as they are. With --header, the first of these
+    /// lines is the header.
+    #[arg(long, value_name = "LIST", num_args = 1, value_parser = RangesValueParser, group = "some_lines",
+          conflicts_with_all = ["sort", "percent"])]
+    pub lines_range: Option<Ranges>,
+
+    /// Only tabulate the longest run of lines that split into the same number
+    /// of fields, and print the prose around it as it is. All of the input is
+    /// read first.
+    #[arg(long, group = "some_lines", conflicts_with_all = ["sort", "percent"])]
+    pub auto_detect_table: bool,
+
+    /// Leave out the lines that are not in --lines-range or the table found by
+    /// --auto-detect-table.
+    #[arg(long, requires = "some_lines")]
+    pub drop_other_lines: bool,
+
+    /// Sort the rows by column COL. ORDER is text (the default), numeric, version,
+    /// which compares numbers in the values numerically like `sort -V`, or natural,
+    /// which also ignores case (file2 < File10). Repeat to sort rows that are equal
+    /// by one key by the next. All input is read before anything is printed.
+    #[arg(long, value_name = "COL[:ORDER]", num_args = 1, conflicts_with = "follow", value_parser = parse_from_str::<SortKey>)]
+    pub sort: Vec<SortKey>,
+
+    /// Fit the output to the terminal width by compressing and truncating columns.
+    #[arg(long)]
+    pub fit: bool,
+
+    /// With --fit, leave out the least important columns, by --weight and then
+    /// from the right, when the others cannot be made to fit without cutting
+    /// them down to a few characters. The hidden columns are listed on stderr.
+    #[arg(long, requires = "fit")]
+    pub auto_hide: bool,
+
+    /// Width of the output used by --fit. Defaults to the width of the terminal, or
+    /// $COLUMNS if that cannot be detected.
+    #[arg(short = 'w', long, value_name = "N", num_args = 1)]
+    pub width: Option<usize>,
+
+    /// Leave N columns of the terminal width unused, e.g. when the output will
+    /// be prefixed or viewed in a pane slightly narrower than the terminal.
+    /// Only applies to the detected width, not to --width.
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "width")]
+    pub margin: usize,
+
+    /// Move columns that do not fit in the output width to continuation lines
+    /// starting with MARKER, instead of letting the terminal wrap lines. Has no
+    /// effect together with --fit.
+    #[arg(long, value_name = "MARKER", num_args = 0..=1, default_missing_value = "↪ ")]
+    pub soft_wrap: Option<OsString>,
+
+    /// Leave out the spaces at the end of every output line, e.g. the padding
+    /// before an empty last value, for clean diffs and checksums.
+    #[arg(long)]
+    pub no_trailing_space: bool,
+
+    /// Draw a frame of box-drawing characters around the table, with lines
+    /// between the columns and a rule under the header.
+    #[arg(long, conflicts_with_all = ["soft_wrap", "underline_header", "events", "side_by_side"])]
+    pub border: bool,
+
+    /// Columns that --fit must never truncate or compress.
+    #[arg(long, value_name = "LIST", num_args = 1, value_parser = RangesValueParser)]
+    pub pin: Option<Ranges>,
+
+    /// Columns that --fit keeps at full size against the right edge of the
+    /// output. The column before them takes up the remaining space.
+    #[arg(long, value_name = "LIST", num_args = 1, value_parser = RangesValueParser, requires = "fit")]
+    pub right_edge: Option<Ranges>,
+
+    /// Relative importance W of the columns in LIST when --fit has to shrink
+    /// columns. Columns with a higher weight are shrunk less. The default weight is 1.
+    #[arg(long = "weight", value_name = "LIST=W", num_args = 1, value_parser = parse_weight)]
+    pub weights: Vec<(Ranges, f64)>,
+
+    /// Replace values in the columns in LIST that are equal to the value above
+    /// them with a ditto mark.
+    #[arg(long, value_name = "LIST", num_args = 1, value_parser = RangesValueParser)]
+    pub collapse_repeats: Option<Ranges>,
+
+    /// The mark used by --collapse-repeats. Use an empty string to leave repeated values blank.
+    #[arg(long = "ditto-mark", value_name = "MARK", num_args = 1, default_value = "\"")]
+    pub ditto_mark: OsString,
+
+    /// Separate groups of rows with a blank line when the value of column COL changes.
+    #[arg(long, value_name = "COL", num_args = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub group_sep: Option<u32>,
+
+    /// Draw a rule of CHAR between the groups of --group-sep instead of a blank line.
+    #[arg(long, value_name = "CHAR", num_args = 1, requires = "group_sep")]
+    pub group_rule: Option<char>,
+
+    /// When to use colors in the output.
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum InvalidRows {
+    Flag,
+    Stderr,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => {
+                enable_vt_processing();
+                true
+            }
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal() && enable_vt_processing()
+            }
+        }
+    }
+}
+
+fn parse_from_str<T>(s: &str) -> std::result::Result<T, String>
+where
+    T: std::str::FromStr<Err = Error>,
+{
+    s.parse().map_err(|e: Error| e.to_string())
 }
 
 fn main() {
-    match run() {
+    let args = Args::parse();
+    if args.default_sigpipe {
+        reset_sigpipe();
+    }
+    let quiet = args.quiet;
+    match run(args) {
         Ok(..) => {}
         Err(Error::Io(ref e)) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+        Err(Error::NoMatch) => ::std::process::exit(1),
+        Err(ref e @ Error::ColumnCheckFailed { expected, ref mismatches, .. }) => {
+            if !quiet {
+                for &(line, columns) in mismatches {
+                    eprintln!("tabulate: warning: line {} has {} columns, expected {}", line, columns, expected);
+                }
+                eprintln!("{}", e);
+            }
+            ::std::process::exit(exit_code(e));
+        }
         Err(ref e) => {
             eprintln!("{}", e);
-            ::std::process::exit(1);
+            ::std::process::exit(exit_code(e));
         }
     }
 }
 
-fn run() -> Result<()> {
-    let args = Args::parse();
-    dbg!(&args);
+/// The exit status for an error, as documented in the help text.
+fn exit_code(e: &Error) -> i32 {
+    match *e {
+        Error::NoMatch => 1,
+        Error::Io(..) => 3,
+        Error::ColumnCheckFailed { .. } => 4,
+        Error::ValidationFailed { .. } => 5,
+        _ => 2,
+    }
+}
+
+fn run(args: Args) -> Result<()> {
+    if let Some(ref shape) = args.generate {
+        return Ok(shape.write(BufWriter::new(std::io::stdout().lock()))?);
+    }
+
+    let mut opts = options(args.clone())?;
+
+    if args.check_config {
+        let problems = opts.check();
+        if !problems.is_empty() {
+            return Err(Error::InvalidOptions { problems });
+        }
+        println!("tabulate: the options are valid");
+        return Ok(());
+    }
+
+    if let Some(ref shape) = args.bench {
+        return bench(shape, &opts);
+    }
+
+    if args.follow && args.tail.is_some() {
+        enable_vt_processing();
+    }
+
+    if let Some(ref pattern) = args.batch {
+        return run_batch(&args, &mut opts, pattern);
+    }
+
+    let reads_terminal = args.in_place.is_none() && args.side_by_side.is_none() && args.column_info_diff.is_none();
+    if reads_terminal && std::io::stdin().is_terminal() && !args.no_tty_hint && !args.quiet {
+        eprintln!("tabulate: reading from the terminal, press Ctrl-D to end the input (see --help)");
+    }
+
+    if let Some(ref path) = args.in_place {
+        return rewrite(&args, &mut opts, path, path);
+    }
+    match args.output {
+        Some(ref path) => {
+            let mut file = AtomicFile::create(path)?;
+            write_output(&args, &mut opts, None, &mut file)?;
+            Ok(file.commit()?)
+        }
+        None => {
+            let stdout = std::io::stdout();
+            let stdout: Box<dyn Write> = match args.flush_interval {
+                Some(interval) => Box::new(IntervalWriter::new(stdout, interval)),
+                None => Box::new(stdout.lock()),
+            };
+            write_output(&args, &mut opts, None, stdout)
+        }
+    }
+}
 
-    let opts = Options {
+/// Tabulates the file at `path` and replaces `target` with the output, or with
+/// --diff prints how the output differs from the file.
+fn rewrite(args: &Args, opts: &mut Options, path: &Path, target: &Path) -> Result<()> {
+    if args.diff {
+        let mut output = Vec::new();
+        write_output(args, opts, Some(path), &mut output)?;
+        let name = path.display().to_string();
+        let mut stdout = std::io::stdout().lock();
+        return Ok(tabulate::diff::write_unified_diff(&mut stdout, &name, &name, &std::fs::read(path)?, &output)?);
+    }
+    let mut file = AtomicFile::create(target)?;
+    write_output(args, opts, Some(path), &mut file)?;
+    Ok(file.commit()?)
+}
+
+/// The options for the library, from the arguments.
+fn options(args: Args) -> Result<Options> {
+    let margin = args.margin;
+    let saved_layout = match args.append_layout.as_ref().or(args.load_layout.as_ref()) {
+        Some(path) if args.load_layout.is_some() || path.exists() => {
+            let file = BufReader::new(File::open(path)?);
+            Some(Layout::read(file, os_bytes(args.output_delim.clone()))?)
+        }
+        _ => None,
+    };
+    let (info_cols, info_names) = match args.print_info {
+        Some(ref list) => parse_columns(list, args.header)?,
+        None => (None, Vec::new()),
+    };
+    let (include_cols, include_names) = match args.include_cols {
+        Some(ref list) => parse_columns(list, args.header)?,
+        None => (None, Vec::new()),
+    };
+    let (exclude_cols, exclude_names) = match args.exclude_cols {
+        Some(ref list) => parse_columns(list, args.header)?,
+        None => (None, Vec::new()),
+    };
+    Ok(Options {
         truncate: args.truncate,
         ratio: args.ratio,
+        truncate_ratio: args.truncate_ratio,
+        sizing_strategy: args.sizing_strategy,
+        bucket_widths: args.bucket_widths,
+        ignore_outliers: args.ignore_outliers,
         lines: args.lines,
-        include_cols: args.include_cols,
-        exclude_cols: args.exclude_cols.unwrap_or(Ranges::new()),
-        delim: args.delim,
-        output_delim: args.output_delim,
+        estimate_mem: args.estimate_mem,
+        include_cols,
+        exclude_cols: exclude_cols.unwrap_or_default(),
+        include_names,
+        exclude_names,
+        delim: os_bytes(args.delim),
+        output_delim: os_bytes(args.output_delim),
         strict_delim: args.strict_delim,
-        print_info: args.print_info,
-        online: args.online,
+        strict_delims: args.strict_delims.map(os_bytes).unwrap_or_default(),
+        print_info: args.print_info.is_some() || args.column_info_file.is_some(),
+        explain_sizing: args.explain_sizing,
+        info_cols,
+        info_names,
+        online: args.online || args.load_layout.is_some(),
+        find: args.find,
+        validate: args.validate,
+        invalid_mark: os_bytes(args.invalid_mark),
+        checksum: args.checksum,
+        footer_info: args.footer_info,
+        page_length: args.page_length.map(|n| n as usize),
+        page_numbers: args.page_numbers,
+        quiet: args.quiet,
+        color: args.color.enabled(),
+        fit: args.fit,
+        auto_hide: args.auto_hide,
+        width: args.width.or_else(|| terminal_width().map(|w| w.saturating_sub(margin))),
+        pin: args.pin,
+        right_edge: args.right_edge,
+        weights: args.weights.into_iter().collect(),
+        collapse_repeats: args.collapse_repeats,
+        ditto_mark: os_bytes(args.ditto_mark),
+        group_sep: args.group_sep,
+        group_rule: args.group_rule,
+        follow: args.follow,
+        tail: args.tail,
+        window: args.window.map(|n| n as usize),
+        header: args.header,
+        header_floor: args.header_floor,
+        underline_header: args.underline_header,
+        header_style: args.header_style,
+        header_align: args.header_align,
+        rename: args.rename,
+        hide_header: args.no_header_out,
+        events: args.events,
+        soft_wrap: args.soft_wrap.map(os_bytes),
+        no_trailing_space: args.no_trailing_space,
+        border: args.border,
+        check_columns: args.check_columns,
+        warn_estimate: args.warn_estimate,
+        project: args.project,
+        compute: args.compute,
+        extract: args.extract,
+        percent: args.percent,
+        spark: args.spark,
+        thresholds: args.threshold,
+        precision: args.precision.into_iter().collect(),
+        null_values: args.null_values.into_iter().map(os_bytes).collect(),
+        empty_value: args.empty_value.map(os_bytes),
+        booleans: args.booleans.into_iter().collect(),
+        align: args.align.into_iter().collect(),
+        left_align_numbers: args.left_align_numbers,
+        align_numbers: args.align_numbers,
+        protect_numbers: args.protect_numbers,
+        byte_widths: args.byte_widths,
+        ignore_ansi: args.ignore_ansi,
+        pass_ansi_lines: args.pass_ansi_lines,
+        line_ranges: args.lines_range,
+        auto_detect_table: args.auto_detect_table,
+        drop_other_lines: args.drop_other_lines,
+        sort: args.sort,
+        layout: saved_layout,
+        measure_only: args.measure_only,
+        every: args.every as usize,
+        max_columns: args.max_columns.map(|n| n as usize),
+        preview: args.preview,
+        drop_constant: args.drop_constant,
+        show_dropped: args.show_dropped,
+        drop_empty: args.drop_empty,
+        exclude_matching: args.exclude_matching,
+        continuation: args.continuation,
+        join_continuations: args.join_continuations,
+        column_delims: args.column_delims,
+    })
+}
+
+/// Tabulates every file matching `pattern`, for --batch: in place, or into
+/// --batch-dir under the same name. With --shared-layout the columns of all
+/// files are measured first, so they all get the same sizes.
+fn run_batch(args: &Args, opts: &mut Options, pattern: &str) -> Result<()> {
+    let paths = tabulate::glob::expand(pattern)?;
+    if paths.is_empty() {
+        return Err(Error::NoFilesMatch { s: pattern.to_string() });
+    }
+    if args.shared_layout {
+        let mut shared = opts.layout.take();
+        opts.measure_only = true;
+        for path in &paths {
+            let (_, layout) = tabulate::process_with_layout(BufReader::new(File::open(path)?), std::io::sink(), opts)?;
+            match shared {
+                Some(ref mut shared) => shared.widen(&layout),
+                None => shared = Some(layout),
+            }
+        }
+        opts.measure_only = false;
+        opts.layout = shared;
+        opts.online = true;
+    }
+    // Files keep their path below the directories the pattern starts with, so
+    // that files of the same name in different directories do not collide.
+    let root = tabulate::glob::root(pattern);
+    for path in &paths {
+        let target = match args.batch_dir {
+            Some(ref dir) => {
+                let target = dir.join(path.strip_prefix(&root).unwrap_or(path));
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                target
+            }
+            None => path.clone(),
+        };
+        rewrite(args, opts, path, &target)?;
+    }
+    Ok(())
+}
+
+/// Reads `input`, or the standard input, and writes the output that the
+/// arguments ask for to `output`.
+fn write_output<W: Write>(args: &Args, opts: &mut Options, input: Option<&Path>, mut output: W) -> Result<()> {
+    let reader: Box<dyn BufRead> = match input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(std::io::stdin().lock()),
+    };
+
+    if let Some(ref paths) = args.column_info_diff {
+        let left = BufReader::new(File::open(&paths[0])?);
+        let right = BufReader::new(File::open(&paths[1])?);
+        return tabulate::column_info_diff(left, right, output, opts);
+    }
+
+    if let Some(ref paths) = args.side_by_side {
+        let left = BufReader::new(File::open(&paths[0])?);
+        let right = BufReader::new(File::open(&paths[1])?);
+        return tabulate::process_side_by_side(left, right, output, opts);
+    }
+
+    if args.measure_only {
+        let (_, layout) = tabulate::process_with_layout(reader, std::io::sink(), opts)?;
+        layout.write(&mut output)?;
+        return Ok(());
+    }
+
+    let summary = if let Some(ref path) = args.append_layout {
+        let (summary, mut layout) = tabulate::process_with_layout(reader, output, opts)?;
+        // The commands are for the output, not for the widened layout.
+        if args.emit_cut {
+            layout.write_cut_commands(&mut std::io::stderr().lock())?;
+        }
+        if let Some(saved) = opts.layout.take() {
+            layout.widen(&saved);
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        layout.write(&mut file)?;
+        file.flush()?;
+        summary
+    } else if args.emit_cut {
+        let (summary, layout) = tabulate::process_with_layout(reader, output, opts)?;
+        layout.write_cut_commands(&mut std::io::stderr().lock())?;
+        summary
+    } else if let Some(ref path) = args.rejects {
+        let rejects = BufWriter::new(File::create(path)?);
+        tabulate::process_with_rejects(reader, output, rejects, opts)?
+    } else if let Some(ref path) = args.column_info_file {
+        let info = BufWriter::new(File::create(path)?);
+        tabulate::process_with_info(reader, output, info, opts)?
+    } else if args.invalid == InvalidRows::Stderr {
+        tabulate::process_with_invalid(reader, output, std::io::stderr().lock(), opts)?
+    } else if args.threads {
+        // The other thread could not read while this one holds the lock.
+        drop(reader);
+        match input {
+            Some(path) => tabulate::process_threaded(BufReader::new(File::open(path)?), output, opts)?,
+            None => tabulate::process_threaded(BufReader::new(std::io::stdin()), output, opts)?,
+        }
+    } else {
+        tabulate::process(reader, output, opts)?
+    };
+    if let Some(checksum) = summary.checksum {
+        eprintln!("checksum: {:016x}", checksum);
+    }
+    if !args.quiet && !summary.hidden.is_empty() {
+        eprintln!("tabulate: hiding columns that do not fit: {}", summary.hidden.join(", "));
+    }
+    match summary.overflow {
+        Some(ref overflow) if !args.quiet && overflow.percent() > 0.0 => {
+            let columns: Vec<String> = overflow.columns().iter().map(|c| c.to_string()).collect();
+            eprintln!(
+                "tabulate: warning: {:.0}% of the cells after the estimated rows did not fit \
+                 their column ({} {}), increase -n to measure more rows",
+                overflow.percent(),
+                if columns.len() == 1 { "column" } else { "columns" },
+                columns.join(", ")
+            );
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Counts the allocations made by the system allocator, for --bench. Only
+/// installed with the count-allocations feature, since every allocation pays
+/// for the count.
+#[cfg(feature = "count-allocations")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingAllocator {
+        allocations: AtomicUsize,
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator {
+        allocations: AtomicUsize::new(0),
+    };
+
+    pub fn allocations() -> Option<usize> {
+        Some(ALLOCATOR.allocations.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(not(feature = "count-allocations"))]
+mod counting {
+    pub fn allocations() -> Option<usize> {
+        None
+    }
+}
+
+/// Processes generated input with `opts`, throwing away the output, and prints
+/// how fast that was and how many allocations it took, if they are counted.
+fn bench(shape: &Shape, opts: &Options) -> Result<()> {
+    let input = shape.generate();
+    let before = counting::allocations();
+    let start = Instant::now();
+    tabulate::process(&input[..], std::io::sink(), opts)?;
+    let elapsed = start.elapsed().as_secs_f64();
+    print!(
+        "{} lines of {} bytes in {:.3}s: {:.0} lines/s",
+        shape.rows,
+        input.len(),
+        elapsed,
+        shape.rows as f64 / elapsed,
+    );
+    if let (Some(before), Some(after)) = (before, counting::allocations()) {
+        let allocations = after - before;
+        print!(", {} allocations ({:.1} per line)", allocations, allocations as f64 / shape.rows.max(1) as f64);
+    }
+    println!();
+    Ok(())
+}
+
+fn parse_table_shape(s: &str) -> std::result::Result<Shape, String> {
+    tabulate::bench::parse_table_shape(s).map_err(|e| e.to_string())
+}
+
+fn parse_range_value<T>(s: &str) -> std::result::Result<(Ranges, T), String>
+where
+    T: std::str::FromStr,
+{
+    tabulate::range::parse_range_value(s).map_err(|e| e.to_string())
+}
+
+/// Parses a duration such as `100ms`, `2s` or `1m`.
+fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let err = || format!("invalid duration '{}', expected e.g. 100ms or 2s", s);
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(err)?;
+    let n: u64 = s[..split].parse().map_err(|_| err())?;
+    match &s[split..] {
+        "ms" => Ok(Duration::from_millis(n)),
+        "s" => Ok(Duration::from_secs(n)),
+        "m" => Ok(Duration::from_secs(n.saturating_mul(60))),
+        _ => Err(err()),
+    }
+}
+
+/// Buffers everything written to stdout and flushes it from a background
+/// thread every interval, so lines show up promptly while input is slow
+/// without flushing every line while it is fast. Flushing the writer does
+/// nothing, so that the flush after every row in follow mode leaves the
+/// output to the thread; what is left is flushed when the writer is dropped.
+struct IntervalWriter {
+    inner: Arc<Mutex<BufWriter<std::io::Stdout>>>,
+}
+
+impl IntervalWriter {
+    fn new(stdout: std::io::Stdout, interval: Duration) -> IntervalWriter {
+        let inner = Arc::new(Mutex::new(BufWriter::new(stdout)));
+        let weak = Arc::downgrade(&inner);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            match weak.upgrade() {
+                // Errors show up on the next write from the main thread.
+                Some(out) => {
+                    let _ = out.lock().map(|mut out| out.flush());
+                }
+                None => break,
+            }
+        });
+        IntervalWriter { inner }
+    }
+
+    fn lock(&self) -> std::io::Result<std::sync::MutexGuard<'_, BufWriter<std::io::Stdout>>> {
+        self.inner
+            .lock()
+            .map_err(|_| std::io::Error::other("output lock poisoned"))
+    }
+}
+
+impl Write for IntervalWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.lock()?.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for IntervalWriter {
+    fn drop(&mut self) {
+        let _ = self.lock().map(|mut out| out.flush());
+    }
+}
+
+/// Splits a list of columns, as given to --column-info or --include, into
+/// ranges of columns and, with a header, the names of columns.
+fn parse_columns(list: &[String], header: bool) -> Result<(Option<Ranges>, Vec<Vec<u8>>)> {
+    let mut ranges = Vec::new();
+    let mut names = Vec::new();
+    for item in list {
+        match item.parse::<Range>() {
+            Ok(range) => ranges.push(range),
+            Err(Error::RangeParseError { .. }) if header => names.push(item.as_bytes().to_vec()),
+            Err(e) => return Err(e),
+        }
+    }
+    if ranges.is_empty() && !names.is_empty() {
+        return Ok((None, names));
+    }
+    Ok((Some(Ranges(ranges)), names))
+}
+
+/// Parses a number of bytes with an optional K, M or G suffix (powers of 1024).
+fn parse_size(s: &str) -> std::result::Result<usize, String> {
+    let (digits, shift) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 30),
+        _ => (s, 0),
     };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size '{}'", s))
+}
+
+fn parse_weight(s: &str) -> std::result::Result<(Ranges, f64), String> {
+    match parse_range_value::<f64>(s)? {
+        (_, w) if !(w > 0.0 && w.is_finite()) => Err("weight must be a positive number".to_string()),
+        v => Ok(v),
+    }
+}
+
+/// The width of the terminal, falling back to `$COLUMNS` when it cannot be
+/// detected, e.g. when the output is piped to a pager.
+fn terminal_width() -> Option<usize> {
+    terminal_size::terminal_size()
+        .map(|(w, _)| w.0 as usize)
+        .or_else(|| env::var("COLUMNS").ok()?.trim().parse().ok())
+        .filter(|&w| w > 0)
+}
+
+/// Makes the Windows console interpret the ANSI escape sequences used for
+/// colors and redrawing with --follow. Returns false if that is not possible,
+/// for example on old versions of Windows or when stdout is not a console.
+#[cfg(windows)]
+fn enable_vt_processing() -> bool {
+    type Handle = *mut std::ffi::c_void;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> Handle;
+        fn GetConsoleMode(console: Handle, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: Handle, mode: u32) -> i32;
+    }
+
+    unsafe {
+        let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        if handle.is_null() || GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+#[cfg(not(windows))]
+fn enable_vt_processing() -> bool {
+    true
+}
+
+/// Restores the default disposition of SIGPIPE, which the Rust runtime sets to
+/// ignored, so writing to a closed pipe terminates the process immediately.
+#[cfg(unix)]
+fn reset_sigpipe() {
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
+
+#[cfg(not(unix))]
+fn reset_sigpipe() {}
 
-    let stdin = std::io::stdin();
-    let stdout = std::io::stdout();
-    let stdin = stdin.lock();
-    let stdout = stdout.lock();
+#[cfg(unix)]
+fn os_bytes(s: OsString) -> Vec<u8> {
+    use std::os::unix::ffi::OsStringExt;
+    s.into_vec()
+}
+
+#[cfg(not(unix))]
+fn os_bytes(s: OsString) -> Vec<u8> {
+    s.to_string_lossy().into_owned().into_bytes()
+}
 
-    tabulate::process(stdin, stdout, &opts)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The output for `input` with the options of the command line `args`.
+    fn tabulate(args: &[&str], input: &str) -> String {
+        let args = Args::try_parse_from(std::iter::once("tabulate").chain(args.iter().copied())).unwrap();
+        let opts = options(args).unwrap();
+        let mut output = Vec::new();
+        tabulate::process(input.as_bytes(), &mut output, &opts).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn collapse_repeats_list() {
+        assert_eq!(
+            tabulate(&["--collapse-repeats", "1,2"], "a y\nb z\nb z\n"),
+            "a  y\nb  z\n\"  \"\n"
+        );
+    }
+
+    #[test]
+    fn pin_list() {
+        assert_eq!(
+            tabulate(&["--fit", "--width", "20", "--pin", "1,2"], "aaaaaaaa bbbbbbbb cccccccc\n"),
+            "aaaaaaaa  bbbbbbbb  …\n"
+        );
+    }
+
+    #[test]
+    fn right_edge_list() {
+        assert_eq!(
+            tabulate(&["--fit", "--width", "24", "--right-edge", "2,3"], "aaaaaaaa bbbbbbbb cccccccc\n"),
+            "aaa…  bbbbbbbb  cccccccc\n"
+        );
+    }
+
+    #[test]
+    fn unknown_column_name() {
+        let args = Args::try_parse_from(["tabulate", "--header", "-x", "NOPE"]).unwrap();
+        let opts = options(args).unwrap();
+        let err = tabulate::process(&b"ID NAME\n1 web\n"[..], Vec::new(), &opts).unwrap_err();
+        assert_eq!(err.to_string(), "no column is named 'NOPE'");
+    }
+
+    #[test]
+    fn batch_dir_keeps_subdirectories() {
+        let dir = std::env::temp_dir().join(format!("tabulate-batch-{}", std::process::id()));
+        for (name, text) in &[("in/a/log.txt", "a bb\nccc d\n"), ("in/b/log.txt", "x y\n")] {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        let pattern = format!("{}/in/*/log.txt", dir.display());
+        let out = dir.join("out");
+        let args = ["tabulate", "--batch", &pattern, "--batch-dir", out.to_str().unwrap()];
+        run(Args::try_parse_from(args).unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(out.join("a/log.txt")).unwrap(), "a    bb\nccc  d\n");
+        assert_eq!(std::fs::read_to_string(out.join("b/log.txt")).unwrap(), "x  y\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validation_exit_status() {
+        let args = Args::try_parse_from(["tabulate", "--validate", "2:^[0-9]+$"]).unwrap();
+        let opts = options(args).unwrap();
+        let mut output = Vec::new();
+        let err = tabulate::process(&b"a 1\nb x\n"[..], &mut output, &opts).unwrap_err();
+        assert_eq!(exit_code(&err), 5);
+        assert_eq!(String::from_utf8(output).unwrap(), "a  1\nb  x!\n");
+    }
+
+    #[test]
+    fn page_length_at_least_one() {
+        assert!(Args::try_parse_from(["tabulate", "--page-length", "0"]).is_err());
+        assert!(Args::try_parse_from(["tabulate", "--page-length", "1"]).is_ok());
+    }
+
+    #[test]
+    fn flush_interval_needs_streaming() {
+        assert!(Args::try_parse_from(["tabulate", "--flush-interval", "1s"]).is_err());
+        assert!(Args::try_parse_from(["tabulate", "--flush-interval", "1s", "--online"]).is_ok());
+        assert!(Args::try_parse_from(["tabulate", "--flush-interval", "1s", "--online", "--follow"]).is_ok());
+    }
+
+    #[test]
+    fn margin_not_with_width() {
+        assert!(Args::try_parse_from(["tabulate", "--width", "20", "--margin", "2"]).is_err());
+        let args = Args::try_parse_from(["tabulate", "--width", "20"]).unwrap();
+        assert_eq!(options(args).unwrap().width, Some(20));
+    }
 }
@@ -0,0 +1,116 @@
+//! Recognizing and reformatting numeric cells.
+
+use std::io::Write;
+
+use crate::parser::Row;
+use crate::range::RangeMap;
+
+/// The value of `cell` if it is a decimal number, possibly surrounded by
+/// whitespace and written in scientific notation.
+pub fn parse_number(cell: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(cell).ok()?.trim();
+    // Rust also accepts words like "inf" and "NaN", which are not numbers here.
+    if !s.bytes().any(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok().filter(|n: &f64| n.is_finite())
+}
+
+/// Currency symbols and signs that may come before a number.
+const PREFIXES: &[&str] = &["$", "€", "£", "¥"];
+
+/// Units and other suffixes that may follow a number.
+const SUFFIXES: &[&str] = &[
+    "%", "ns", "us", "µs", "ms", "s", "m", "h", "d", "B", "KB", "MB", "GB", "TB", "PB", "KiB",
+    "MiB", "GiB", "TiB", "PiB", "k", "K", "M", "G", "T", "x",
+];
+
+/// The widths in bytes of the parts of a number that are aligned on the
+/// decimal point: everything before the point, and the point, the decimals and
+/// the suffix after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberWidth {
+    pub integer: usize,
+    pub fraction: usize,
+}
+
+/// Recognizes numbers like `-1,024.5`, `$3.50`, `12%` or `4KiB` and returns
+/// the widths to align them by, or `None` if `cell` is not such a number.
+pub fn number_width(cell: &[u8]) -> Option<NumberWidth> {
+    let s = std::str::from_utf8(cell).ok()?;
+    let mut rest = s.strip_prefix(['-', '+']).unwrap_or(s);
+    if let Some(p) = PREFIXES.iter().find(|p| rest.starts_with(*p)) {
+        rest = rest[p.len()..].strip_prefix('-').unwrap_or(&rest[p.len()..]);
+    }
+    let digits = rest.find(|c: char| !(c.is_ascii_digit() || c == ',')).unwrap_or(rest.len());
+    if !rest[..digits].starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let integer = s.len() - rest.len() + digits;
+    rest = &rest[digits..];
+    if let Some(decimals) = rest.strip_prefix('.') {
+        let n = decimals.find(|c: char| !c.is_ascii_digit()).unwrap_or(decimals.len());
+        rest = &decimals[n..];
+    }
+    if !rest.is_empty() && !SUFFIXES.contains(&rest) {
+        return None;
+    }
+    Some(NumberWidth {
+        integer,
+        fraction: s.len() - integer,
+    })
+}
+
+/// Rounds the numbers in the columns given by `precision` to the number of
+/// decimals given for the column. Other cells are left as they are.
+pub(crate) fn apply_precision(row: &mut Row, precision: &RangeMap<usize>) {
+    if precision.0.is_empty() {
+        return;
+    }
+    let mut line = Vec::with_capacity(row.as_line().len());
+    let mut spans = Vec::with_capacity(row.len());
+    for (i, cell) in row.get_parts().enumerate() {
+        let start = line.len();
+        match (precision.get(i as u32 + 1), parse_number(cell)) {
+            (Some(&decimals), Some(n)) => write!(line, "{:.*}", decimals, n).unwrap(),
+            _ => line.extend_from_slice(cell),
+        }
+        spans.push((start, line.len()));
+    }
+    *row = Row::from_spans(line, spans);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::RowParser;
+    use crate::range::Ranges;
+
+    #[test]
+    fn precision() {
+        let precision = RangeMap(vec![
+            (Ranges(vec!["2".parse().unwrap()]), 2),
+            (Ranges(vec!["3".parse().unwrap()]), 0),
+        ]);
+        let mut row = Row::new();
+        RowParser::new(" ", false).parse_into(&mut row, "1.23456 1.23456 2.5e1 inf");
+        apply_precision(&mut row, &precision);
+        assert_eq!(row.get_parts().collect::<Vec<_>>(), [&b"1.23456"[..], b"1.23", b"25", b"inf"]);
+    }
+
+    #[test]
+    fn number_widths() {
+        let w = |s: &str| number_width(s.as_bytes()).map(|w| (w.integer, w.fraction));
+        assert_eq!(w("12"), Some((2, 0)));
+        assert_eq!(w("-1,024.5"), Some((6, 2)));
+        assert_eq!(w("$3.50"), Some((2, 3)));
+        assert_eq!(w("12.5%"), Some((2, 3)));
+        assert_eq!(w("4KiB"), Some((1, 3)));
+        assert_eq!(w("150ms"), Some((3, 2)));
+        assert_eq!(w("abc"), None);
+        assert_eq!(w("12 apples"), None);
+        assert_eq!(w("$"), None);
+        assert_eq!(w(".5"), None);
+        assert_eq!(w(""), None);
+    }
+}
@@ -1,21 +1,84 @@
+use std::str::FromStr;
+
+use crate::errors::*;
+
 #[derive(Clone, Debug, Default)]
 pub struct Row {
     parts: Vec<(usize, usize)>,
-    line: String,
+    line: Vec<u8>,
 }
 
 impl Row {
     pub fn new() -> Row {
         Row {
             parts: Vec::new(),
-            line: String::new(),
+            line: Vec::new(),
         }
     }
 
-    pub fn get_parts(&self) -> impl Iterator<Item = &str> {
+    /// A row of the cells at the given `(start, end)` byte offsets of `line`.
+    pub fn from_spans<S: Into<Vec<u8>>>(line: S, spans: Vec<(usize, usize)>) -> Row {
+        let line = line.into();
+        assert!(spans.iter().all(|&(i, j)| i <= j && j <= line.len()));
+        Row { parts: spans, line }
+    }
+
+    pub fn get_parts(&self) -> impl Iterator<Item = &[u8]> {
         self.parts.iter().map(move |&(i, j)| &self.line[i..j])
     }
 
+    /// The `(start, end)` byte offsets of each cell in the original line.
+    pub fn spans(&self) -> &[(usize, usize)] {
+        &self.parts
+    }
+
+    /// The original line the row was parsed from, followed by any cells
+    /// added with `push`.
+    pub fn as_line(&self) -> &[u8] {
+        &self.line
+    }
+
+    /// Adds a cell to the end of the row.
+    pub fn push(&mut self, cell: &[u8]) {
+        let start = self.line.len();
+        self.line.extend_from_slice(cell);
+        self.parts.push((start, self.line.len()));
+    }
+
+    /// Merges the cells from the `n`-th on into one cell, which spans them and
+    /// the delimiters between them in the line.
+    pub fn merge_from(&mut self, n: usize) {
+        if n == 0 || self.parts.len() <= n {
+            return;
+        }
+        let end = self.parts[self.parts.len() - 1].1;
+        self.parts.truncate(n);
+        self.parts[n - 1].1 = end;
+    }
+
+    /// Makes the row `n` cells long, by adding empty cells or merging the
+    /// cells from the `n`-th on.
+    pub fn set_len(&mut self, n: usize) {
+        self.merge_from(n);
+        while self.parts.len() < n {
+            self.push(b"");
+        }
+    }
+
+    /// Appends `text` to the last cell, separated by a space, or makes it
+    /// the only cell of an empty row.
+    pub fn append_to_last(&mut self, text: &[u8]) {
+        match self.parts.last_mut() {
+            Some(last) => {
+                self.line.truncate(last.1);
+                self.line.push(b' ');
+                self.line.extend_from_slice(text);
+                last.1 = self.line.len();
+            }
+            None => self.push(text),
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.parts.len()
     }
@@ -26,9 +89,9 @@ impl Row {
 }
 
 impl ::std::ops::Index<usize> for Row {
-    type Output = str;
+    type Output = [u8];
 
-    fn index(&self, index: usize) -> &str {
+    fn index(&self, index: usize) -> &[u8] {
         let (i, j) = self.parts[index];
         &self.line[i..j]
     }
@@ -38,88 +101,294 @@ impl ::std::ops::Index<usize> for Row {
 enum ParseState {
     Whitespace,
     NonWhitespace,
-    EndDelim(char),
+    EndDelim(u8),
 }
 
 pub struct RowParser {
-    delim: String,
+    /// Each delimiter is either the UTF-8 encoding of a single character or a
+    /// single byte that is not valid UTF-8, paired with whether it is strict.
+    delims: Vec<(Vec<u8>, bool)>,
+    /// Lookup table of the bytes that some delimiter starts with.
+    delim_start: [bool; 256],
     strict_delim: bool,
+    /// The delimiters are the default ones, spaces and tabs that collapse, for
+    /// which there is a faster way to split lines.
+    blank_delims: bool,
+}
+
+fn split_delims(delim: &[u8]) -> Vec<Vec<u8>> {
+    let mut delims = Vec::new();
+    for chunk in delim.utf8_chunks() {
+        delims.extend(chunk.valid().chars().map(|ch| ch.to_string().into_bytes()));
+        delims.extend(chunk.invalid().iter().map(|&b| vec![b]));
+    }
+    delims
 }
 
 impl RowParser {
-    pub fn new<S: Into<String>>(delim: S, strict_delim: bool) -> RowParser {
-        RowParser {
-            delim: delim.into(),
+    pub fn new<S: Into<Vec<u8>>>(delim: S, strict_delim: bool) -> RowParser {
+        let mut parser = RowParser {
+            delims: Vec::new(),
+            delim_start: [false; 256],
             strict_delim,
+            blank_delims: false,
+        };
+        parser.add_delims(&delim.into(), strict_delim);
+        parser
+    }
+
+    pub fn builder() -> RowParserBuilder {
+        RowParserBuilder::new()
+    }
+
+    fn add_delims(&mut self, delim: &[u8], strict: bool) {
+        for d in split_delims(delim) {
+            self.delim_start[d[0] as usize] = true;
+            match self.delims.iter_mut().find(|(e, _)| *e == d) {
+                Some(existing) => existing.1 = strict,
+                None => self.delims.push((d, strict)),
+            }
+        }
+        self.blank_delims = !self.strict_delim
+            && self.delims.len() == 2
+            && self.delims.iter().all(|(d, strict)| !strict && (d[..] == *b" " || d[..] == *b"\t"));
+    }
+
+    /// Returns the length of the delimiter starting at byte `i` of `line` and
+    /// whether it is strict, if any.
+    fn delim_at(&self, line: &[u8], i: usize) -> Option<(usize, bool)> {
+        if !self.delim_start[line[i] as usize] {
+            return None;
         }
+        self.delims
+            .iter()
+            .find(|(d, _)| line[i..].starts_with(d))
+            .map(|(d, strict)| (d.len(), *strict))
     }
 
-    pub fn parse_into<S: Into<String>>(&self, row: &mut Row, line: S) {
+    pub fn parse_into<S: Into<Vec<u8>>>(&self, row: &mut Row, line: S) {
         use self::ParseState::*;
 
         row.line = line.into();
         row.parts.clear();
+        if self.blank_delims {
+            return split_blank_delimited(row);
+        }
 
         let mut state = Whitespace;
 
         let mut start = None;
-        let mut chars = row.line.char_indices();
-        let mut current_char = chars.next();
-        while let Some((i, ch)) = current_char.take() {
-            // print!("{} ", ch);
+        // Whether a column has ended since the last strict delimiter.
+        let mut have_column = false;
+        // Whether a strict delimiter was seen after the last column. The
+        // start of the line counts as one when all delimiters are strict.
+        let mut pending_empty = self.strict_delim;
+        let mut i = 0;
+        while i < row.line.len() {
+            let ch = row.line[i];
+            let delim = self.delim_at(&row.line, i);
             match state {
                 Whitespace => {
-                    // println!("whitespace");
-                    if !self.strict_delim && (ch == '(' || ch == '[' || ch == '"') {
+                    if !self.strict_delim && (ch == b'(' || ch == b'[' || ch == b'"') {
                         let end_delim = match ch {
-                            '(' => ')',
-                            '[' => ']',
-                            '"' => '"',
+                            b'(' => b')',
+                            b'[' => b']',
+                            b'"' => b'"',
                             _ => unimplemented!(),
                         };
                         start = Some(i);
                         state = EndDelim(end_delim);
-                    } else if !self.delim.contains(ch) {
+                    } else if let Some((_, strict)) = delim {
+                        if strict {
+                            if !have_column {
+                                row.parts.push((i, i));
+                            }
+                            have_column = false;
+                            pending_empty = true;
+                        }
+                    } else {
                         start = Some(i);
                         state = NonWhitespace;
-                    } else if self.strict_delim {
-                        row.parts.push((i, i));
                     }
                 }
                 NonWhitespace => {
-                    // println!("non-whitespace");
-                    if self.delim.contains(ch) {
+                    if let Some((_, strict)) = delim {
                         if let Some(s) = start {
-                            // println!("output = {:?}", &input[s..i]);
                             row.parts.push((s, i));
                         }
                         start = None;
                         state = Whitespace;
+                        have_column = !strict;
+                        pending_empty = strict;
                     }
                 }
-                EndDelim(delim) => {
-                    // println!("end-delim({})", delim);
-                    if ch == delim {
+                EndDelim(end_delim) => {
+                    if ch == end_delim {
                         if let Some(s) = start {
-                            // println!("output = {:?}", &input[s..i+1]);
                             row.parts.push((s, i + 1));
                         }
                         start = None;
                         state = Whitespace;
+                        have_column = true;
+                        pending_empty = false;
                     }
                 }
             }
-            if current_char.is_none() {
-                current_char = chars.next();
-            }
+            i += match (&state, delim) {
+                (Whitespace, Some((n, _))) => n,
+                _ => 1,
+            };
         }
         if let Some(s) = start {
-            // println!("output = {:?}", &input[s..i]);
             row.parts.push((s, row.line.len()));
-        } else if self.strict_delim && state == Whitespace {
-            row.parts.push((0, 0));
+        } else if pending_empty && state == Whitespace {
+            row.parts.push((row.line.len(), row.line.len()));
+        }
+    }
+}
+
+/// Splits `row.line` at runs of spaces and tabs, like `RowParser::parse_into`
+/// with the default delimiters but without looking up delimiters byte by byte.
+fn split_blank_delimited(row: &mut Row) {
+    let line = &row.line[..];
+    let mut i = 0;
+    while i < line.len() {
+        match line[i] {
+            b' ' | b'\t' => i += 1,
+            open @ (b'(' | b'[' | b'"') => {
+                let close = match open {
+                    b'(' => b')',
+                    b'[' => b']',
+                    _ => b'"',
+                };
+                let end = line[i + 1..]
+                    .iter()
+                    .position(|&b| b == close)
+                    .map_or(line.len(), |n| i + n + 2);
+                row.parts.push((i, end));
+                i = end;
+            }
+            _ => {
+                let end = find_blank(line, i);
+                row.parts.push((i, end));
+                i = end;
+            }
+        }
+    }
+}
+
+/// The index of the first space or tab in `s` from `i` on, or the length of
+/// `s`. Looks at eight bytes at a time.
+fn find_blank(s: &[u8], mut i: usize) -> usize {
+    const ONES: u64 = 0x0101_0101_0101_0101;
+    const HIGH: u64 = 0x8080_8080_8080_8080;
+    // The high bit of the lowest byte of the result is set for the first
+    // byte of `w` equal to `b`. Higher bytes can have false positives.
+    let has_byte = |w: u64, b: u8| {
+        let x = w ^ (ONES * b as u64);
+        x.wrapping_sub(ONES) & !x & HIGH
+    };
+    while let Some(chunk) = s.get(i..i + 8) {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(chunk);
+        let w = u64::from_le_bytes(bytes);
+        let found = has_byte(w, b' ') | has_byte(w, b'\t');
+        if found != 0 {
+            return i + found.trailing_zeros() as usize / 8;
+        }
+        i += 8;
+    }
+    s[i..].iter().position(|&b| b == b' ' || b == b'\t').map_or(s.len(), |n| i + n)
+}
+
+impl RowParser {
+    /// Splits the rest of the line of `row`, from its cell after the first
+    /// `n`, again with this parser.
+    pub fn parse_rest_into(&self, row: &mut Row, n: usize) {
+        if row.parts.len() <= n {
+            return;
+        }
+        let start = row.parts[n].0;
+        let mut rest = Row::new();
+        self.parse_into(&mut rest, &row.line[start..]);
+        row.parts.truncate(n);
+        row.parts.extend(rest.parts.iter().map(|&(s, e)| (start + s, start + e)));
+    }
+}
+
+/// Delimiters to split the rest of a line with after a column, for
+/// `--col-delim`. Without delimiters the rest of the line is one cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDelimiter {
+    pub column: u32,
+    pub delim: Vec<u8>,
+}
+
+/// Parses `COL:DELIM`.
+impl FromStr for ColumnDelimiter {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<ColumnDelimiter> {
+        let err = || Error::ColumnDelimiterParseError { s: s.to_string() };
+        let (column, delim) = s.split_once(':').ok_or_else(err)?;
+        let column: u32 = column.parse().map_err(|_| err())?;
+        if column == 0 {
+            return Err(Error::ColumnsStartAtOne);
+        }
+        Ok(ColumnDelimiter {
+            column,
+            delim: delim.as_bytes().to_vec(),
+        })
+    }
+}
+
+/// Builder for `RowParser`, so new parser options can be added without
+/// changing the signature of `RowParser::new`.
+#[derive(Debug, Clone)]
+pub struct RowParserBuilder {
+    delim: Vec<u8>,
+    strict_delim: bool,
+    strict_delims: Vec<u8>,
+}
+
+impl RowParserBuilder {
+    pub fn new() -> RowParserBuilder {
+        RowParserBuilder {
+            delim: b" \t".to_vec(),
+            strict_delim: false,
+            strict_delims: Vec::new(),
         }
     }
+
+    /// Use the characters of `delim` as column delimiters.
+    pub fn delimiters<S: Into<Vec<u8>>>(mut self, delim: S) -> RowParserBuilder {
+        self.delim = delim.into();
+        self
+    }
+
+    /// Parse columns as strictly being delimited by a single delimiter.
+    pub fn strict(mut self, strict_delim: bool) -> RowParserBuilder {
+        self.strict_delim = strict_delim;
+        self
+    }
+
+    /// Treat the characters of `delim` as strict delimiters, i.e. each of them
+    /// separates two columns, while the remaining delimiters still collapse.
+    pub fn strict_delimiters<S: Into<Vec<u8>>>(mut self, delim: S) -> RowParserBuilder {
+        self.strict_delims = delim.into();
+        self
+    }
+
+    pub fn build(self) -> RowParser {
+        let mut parser = RowParser::new(self.delim, self.strict_delim);
+        parser.add_delims(&self.strict_delims, true);
+        parser
+    }
+}
+
+impl Default for RowParserBuilder {
+    fn default() -> RowParserBuilder {
+        RowParserBuilder::new()
+    }
 }
 
 #[cfg(test)]
@@ -128,7 +397,7 @@ mod tests {
 
     macro_rules! assert_row {
         ($x:ident, [ $( $y:expr ),* ]) => {
-           assert_eq!(&$x.get_parts().collect::<Vec<_>>()[..], &[$( $y ),*]);
+           assert_eq!(&$x.get_parts().collect::<Vec<_>>()[..], &[$( $y.as_bytes() ),*]);
         };
     }
 
@@ -140,6 +409,17 @@ mod tests {
         assert_row!(row, ["a", "b", "c"]);
     }
 
+    #[test]
+    fn test_merge_from() {
+        let parser = RowParser::new(" ", false);
+        let mut row = Row::new();
+        parser.parse_into(&mut row, "a b  c d ");
+        row.merge_from(2);
+        assert_row!(row, ["a", "b  c d"]);
+        row.merge_from(5);
+        assert_row!(row, ["a", "b  c d"]);
+    }
+
     #[test]
     fn test_split_line_collapse() {
         let parser = RowParser::new(" ", false);
@@ -207,4 +487,93 @@ mod tests {
         parser.parse_into(&mut row, "\u{0423}");
         assert_row!(row, ["\u{0423}"]);
     }
+
+    #[test]
+    fn test_split_line_multibyte_delimiter() {
+        let parser = RowParser::new("│", false);
+        let mut row = Row::new();
+        parser.parse_into(&mut row, "a│bø│c");
+        assert_row!(row, ["a", "bø", "c"]);
+    }
+
+    #[test]
+    fn test_split_line_non_utf8() {
+        let parser = RowParser::new(&b"\x1f\xff"[..], true);
+        let mut row = Row::new();
+        parser.parse_into(&mut row, &b"a\x1f\xfe\xffc"[..]);
+        assert_eq!(
+            &row.get_parts().collect::<Vec<_>>()[..],
+            &[&b"a"[..], &b"\xfe"[..], &b"c"[..]]
+        );
+    }
+
+    #[test]
+    fn test_split_line_mixed_strict() {
+        let parser = RowParser::builder()
+            .delimiters(" ")
+            .strict_delimiters(";")
+            .build();
+        let mut row = Row::new();
+        parser.parse_into(&mut row, "a  b;c ; d");
+        assert_row!(row, ["a", "b", "c", "d"]);
+
+        parser.parse_into(&mut row, ";a;;b  c;");
+        assert_row!(row, ["", "a", "", "b", "c", ""]);
+
+        parser.parse_into(&mut row, "  a  ");
+        assert_row!(row, ["a"]);
+    }
+
+    #[test]
+    fn blank_delimiters_split_like_the_general_parser() {
+        let fast = RowParser::new(" \t", false);
+        let mut general = RowParser::new(" \t", false);
+        assert!(fast.blank_delims);
+        general.blank_delims = false;
+
+        let lines = [
+            "",
+            "  \t ",
+            "a b\tc",
+            "  lead and trail\t\t ",
+            "a_very_long_cell_of_more_than_eight_bytes\tnext",
+            "(a b) [c\td] \"e f\" g(h i)j",
+            "unclosed (paren and more",
+            "\"\" () []x",
+            "ø æ\tå 123456789012345 ",
+        ];
+        let (mut a, mut b) = (Row::new(), Row::new());
+        for line in &lines {
+            fast.parse_into(&mut a, *line);
+            general.parse_into(&mut b, *line);
+            assert_eq!(a.spans(), b.spans(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn column_delimiter() {
+        let parser = RowParser::new(" ", false);
+        let rest = RowParser::new(",", false);
+        let mut row = Row::new();
+        parser.parse_into(&mut row, "Jan 1 host a,b c,d");
+        rest.parse_rest_into(&mut row, 3);
+        assert_row!(row, ["Jan", "1", "host", "a", "b c", "d"]);
+        rest.parse_rest_into(&mut row, 10);
+        assert_eq!(row.len(), 6);
+
+        let delim: ColumnDelimiter = "3:,".parse().unwrap();
+        assert_eq!((delim.column, &delim.delim[..]), (3, &b","[..]));
+        assert!("3".parse::<ColumnDelimiter>().is_err());
+        assert!("0:,".parse::<ColumnDelimiter>().is_err());
+    }
+
+    #[test]
+    fn test_spans() {
+        let parser = RowParser::new(" ", false);
+        let mut row = Row::new();
+        parser.parse_into(&mut row, " ab  (c d) e");
+        assert_eq!(row.spans(), &[(1, 3), (5, 10), (11, 12)]);
+        assert_eq!(row.as_line(), b" ab  (c d) e");
+    }
 }
+
@@ -0,0 +1,849 @@
+//! A small backtracking regular expression engine.
+//!
+//! Supports the commonly used subset of the usual syntax: literals, `.`, `^`,
+//! `$`, character classes (`[a-z]`, `[^,]`), the escapes `\d`, `\w`, `\s`,
+//! `\b` (and their negations), capturing and non-capturing (`(?:...)`)
+//! groups, alternation and the greedy and lazy quantifiers `*`, `+`, `?` and
+//! `{n,m}`. Matching works on bytes; input that is not valid UTF-8 matches
+//! byte by byte.
+//!
+//! Patterns are compiled to a list of instructions. The matcher keeps its own
+//! stack of alternatives to backtrack to and never tries an instruction at the
+//! same position twice, so it takes at most time proportional to the size of
+//! the program times the length of the text, however long the text is.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::*;
+use crate::parser::Row;
+
+const PERL_DIGIT: &[(char, char)] = &[('0', '9')];
+const PERL_WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const PERL_SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    /// A predefined set such as `\d`, possibly negated.
+    Set(&'static [(char, char)], bool),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    WordBoundary(bool),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+/// Capture group spans, index 0 being the whole match.
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+#[derive(Clone)]
+pub struct Regex {
+    pattern: String,
+    prog: Vec<Inst>,
+    groups: usize,
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Regex({:?})", self.pattern)
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl FromStr for Regex {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Regex> {
+        Regex::new(s)
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex> {
+        let mut parser = PatternParser {
+            pattern,
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 1,
+        };
+        let node = parser.parse_alternate()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unmatched ')'"));
+        }
+        if node.size() > MAX_PROGRAM {
+            return Err(parser.error("pattern too large"));
+        }
+        let mut prog = Vec::with_capacity(node.size() + 1);
+        compile(&node, &mut prog);
+        prog.push(Inst::Match);
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            prog,
+            groups: parser.groups,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Number of capture groups, including the implicit group of the whole match.
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        self.captures(text).is_some()
+    }
+
+    pub fn find(&self, text: &[u8]) -> Option<(usize, usize)> {
+        self.captures(text).and_then(|caps| caps[0])
+    }
+
+    pub fn captures(&self, text: &[u8]) -> Option<Captures> {
+        // Whether an instruction failed at a position is the same for every
+        // start, so the states tried are shared by all of them.
+        let mut matcher = Matcher {
+            prog: &self.prog,
+            text,
+            visited: vec![0; (self.prog.len() * (text.len() + 1)).div_ceil(64)],
+            stack: Vec::new(),
+            slots: vec![None; 2 * self.groups],
+        };
+        let mut start = 0;
+        loop {
+            if let Some(end) = matcher.run(start) {
+                let mut caps: Captures = matcher.slots.chunks(2).map(|s| s[0].zip(s[1])).collect();
+                caps[0] = Some((start, end));
+                return Some(caps);
+            }
+            match decode(text, start) {
+                Some((_, w)) => start += w,
+                None => return None,
+            }
+        }
+    }
+}
+
+/// The largest number of instructions a pattern may compile to, which keeps
+/// the memory used to match it in bounds.
+const MAX_PROGRAM: usize = 1 << 16;
+
+#[derive(Debug, Clone)]
+enum Inst {
+    /// A node that matches a single character.
+    Char(Node),
+    Start,
+    End,
+    WordBoundary(bool),
+    /// Continue at the first instruction, and at the second if that fails.
+    Split(usize, usize),
+    Jump(usize),
+    /// Record the position in a capture slot, the start or end of a group.
+    Save(usize),
+    Match,
+}
+
+impl Node {
+    /// The number of instructions the node compiles to.
+    fn size(&self) -> usize {
+        match *self {
+            Node::Literal(_) | Node::Any | Node::Class(..) | Node::Start | Node::End | Node::WordBoundary(_) => 1,
+            Node::Group(ref inner, index) => inner.size().saturating_add(if index.is_some() { 2 } else { 0 }),
+            Node::Concat(ref nodes) => nodes.iter().fold(0, |n, node| n.saturating_add(node.size())),
+            Node::Alternate(ref alts) => alts.iter().fold(0, |n, alt| n.saturating_add(alt.size()).saturating_add(2)),
+            Node::Repeat {
+                ref node, min, max, ..
+            } => {
+                let body = node.size();
+                let optional = match max {
+                    Some(max) => (max - min).saturating_mul(body.saturating_add(1)),
+                    None => body.saturating_add(2),
+                };
+                body.saturating_mul(min).saturating_add(optional)
+            }
+        }
+    }
+}
+
+fn compile(node: &Node, prog: &mut Vec<Inst>) {
+    match *node {
+        Node::Literal(_) | Node::Any | Node::Class(..) => prog.push(Inst::Char(node.clone())),
+        Node::Start => prog.push(Inst::Start),
+        Node::End => prog.push(Inst::End),
+        Node::WordBoundary(expected) => prog.push(Inst::WordBoundary(expected)),
+        Node::Group(ref inner, index) => {
+            if let Some(i) = index {
+                prog.push(Inst::Save(2 * i));
+            }
+            compile(inner, prog);
+            if let Some(i) = index {
+                prog.push(Inst::Save(2 * i + 1));
+            }
+        }
+        Node::Concat(ref nodes) => {
+            for node in nodes {
+                compile(node, prog);
+            }
+        }
+        Node::Alternate(ref alts) => {
+            let mut jumps = Vec::new();
+            for (i, alt) in alts.iter().enumerate() {
+                if i + 1 == alts.len() {
+                    compile(alt, prog);
+                    break;
+                }
+                let split = prog.len();
+                prog.push(Inst::Split(split + 1, 0));
+                compile(alt, prog);
+                jumps.push(prog.len());
+                prog.push(Inst::Jump(0));
+                prog[split] = Inst::Split(split + 1, prog.len());
+            }
+            let end = prog.len();
+            for jump in jumps {
+                prog[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat {
+            ref node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..min {
+                compile(node, prog);
+            }
+            let split = |body: usize, end: usize| if greedy { Inst::Split(body, end) } else { Inst::Split(end, body) };
+            match max {
+                None => {
+                    let start = prog.len();
+                    prog.push(Inst::Jump(0));
+                    compile(node, prog);
+                    prog.push(Inst::Jump(start));
+                    prog[start] = split(start + 1, prog.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in min..max {
+                        splits.push(prog.len());
+                        prog.push(Inst::Jump(0));
+                        compile(node, prog);
+                    }
+                    let end = prog.len();
+                    for i in splits {
+                        prog[i] = split(i + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+enum Job {
+    Try(usize, usize),
+    /// Put back the old value of a capture slot when backtracking past the
+    /// instruction that set it.
+    Restore(usize, Option<usize>),
+}
+
+struct Matcher<'a> {
+    prog: &'a [Inst],
+    text: &'a [u8],
+    /// One bit per instruction and position, set once it has been tried there.
+    visited: Vec<u64>,
+    stack: Vec<Job>,
+    slots: Vec<Option<usize>>,
+}
+
+impl<'a> Matcher<'a> {
+    /// Marks the instruction as tried at the position, telling whether it had
+    /// not been yet.
+    fn visit(&mut self, pc: usize, pos: usize) -> bool {
+        let bit = pc * (self.text.len() + 1) + pos;
+        let (word, mask) = (bit / 64, 1 << (bit % 64));
+        let new = self.visited[word] & mask == 0;
+        self.visited[word] |= mask;
+        new
+    }
+
+    /// The end of the first match starting at `start`, with its groups in
+    /// `slots`.
+    fn run(&mut self, start: usize) -> Option<usize> {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.stack.push(Job::Try(0, start));
+        while let Some(job) = self.stack.pop() {
+            let (mut pc, mut pos) = match job {
+                Job::Try(pc, pos) => (pc, pos),
+                Job::Restore(slot, old) => {
+                    self.slots[slot] = old;
+                    continue;
+                }
+            };
+            while self.visit(pc, pos) {
+                match self.prog[pc] {
+                    Inst::Char(ref node) => match match_char(node, self.text, pos) {
+                        Some(w) => pos += w,
+                        None => break,
+                    },
+                    Inst::Start if pos == 0 => {}
+                    Inst::End if pos == self.text.len() => {}
+                    Inst::WordBoundary(expected) => {
+                        let before = pos > 0 && is_word_at(self.text, pos - 1);
+                        if (before != is_word_at(self.text, pos)) != expected {
+                            break;
+                        }
+                    }
+                    Inst::Start | Inst::End => break,
+                    Inst::Split(first, second) => {
+                        self.stack.push(Job::Try(second, pos));
+                        pc = first;
+                        continue;
+                    }
+                    Inst::Jump(to) => {
+                        pc = to;
+                        continue;
+                    }
+                    Inst::Save(slot) => {
+                        self.stack.push(Job::Restore(slot, self.slots[slot]));
+                        self.slots[slot] = Some(pos);
+                    }
+                    Inst::Match => {
+                        self.stack.clear();
+                        return Some(pos);
+                    }
+                }
+                pc += 1;
+            }
+        }
+        None
+    }
+}
+
+/// Decodes the character at byte `i`. Bytes that are not part of a valid
+/// UTF-8 sequence decode to the replacement character.
+fn decode(text: &[u8], i: usize) -> Option<(char, usize)> {
+    let b = *text.get(i)?;
+    let w = match b {
+        0x00..=0x7f => return Some((b as char, 1)),
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Some((char::REPLACEMENT_CHARACTER, 1)),
+    };
+    text.get(i..i + w)
+        .and_then(|s| std::str::from_utf8(s).ok())
+        .and_then(|s| s.chars().next())
+        .map(|c| (c, w))
+        .or(Some((char::REPLACEMENT_CHARACTER, 1)))
+}
+
+fn in_ranges(ranges: &[(char, char)], c: char) -> bool {
+    ranges.iter().any(|&(a, b)| a <= c && c <= b)
+}
+
+fn is_word_at(text: &[u8], i: usize) -> bool {
+    text.get(i)
+        .map(|&b| in_ranges(PERL_WORD, b as char))
+        .unwrap_or(false)
+}
+
+/// Matches a single character against a node that always consumes exactly one
+/// character, returning the width of the match.
+fn match_char(node: &Node, text: &[u8], pos: usize) -> Option<usize> {
+    let (c, w) = decode(text, pos)?;
+    let matched = match *node {
+        Node::Literal(l) => c == l,
+        Node::Any => c != '\n',
+        Node::Class(ref items, negated) => {
+            items.iter().any(|item| match *item {
+                ClassItem::Range(a, b) => a <= c && c <= b,
+                ClassItem::Set(ranges, inverted) => in_ranges(ranges, c) != inverted,
+            }) != negated
+        }
+        _ => unreachable!(),
+    };
+    if matched {
+        Some(w)
+    } else {
+        None
+    }
+}
+
+struct PatternParser<'a> {
+    pattern: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl<'a> PatternParser<'a> {
+    fn error(&self, msg: &str) -> Error {
+        Error::PatternError {
+            pattern: self.pattern.to_string(),
+            msg: msg.to_string(),
+            pos: self.pos,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternate(&mut self) -> Result<Node> {
+        let mut alts = vec![self.parse_concat()?];
+        while self.eat('|') {
+            alts.push(self.parse_concat()?);
+        }
+        if alts.len() == 1 {
+            Ok(alts.pop().unwrap())
+        } else {
+            Ok(Node::Alternate(alts))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn parse_atom(&mut self) -> Result<Node> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        Ok(match c {
+            '(' => {
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups - 1)
+                };
+                let inner = self.parse_alternate()?;
+                if !self.eat(')') {
+                    return Err(self.error("unclosed group"));
+                }
+                Node::Group(Box::new(inner), index)
+            }
+            '[' => self.parse_class()?,
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '*' | '+' | '?' => return Err(self.error("repetition operator without operand")),
+            '\\' => match self.parse_escape()? {
+                Escape::Char(c) => Node::Literal(c),
+                Escape::Set(ranges, negated) => Node::Class(vec![ClassItem::Set(ranges, negated)], false),
+                Escape::WordBoundary(b) => Node::WordBoundary(b),
+            },
+            c => Node::Literal(c),
+        })
+    }
+
+    fn parse_escape(&mut self) -> Result<Escape> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("trailing backslash"))?;
+        self.pos += 1;
+        Ok(match c {
+            'd' => Escape::Set(PERL_DIGIT, false),
+            'w' => Escape::Set(PERL_WORD, false),
+            's' => Escape::Set(PERL_SPACE, false),
+            'D' => Escape::Set(PERL_DIGIT, true),
+            'W' => Escape::Set(PERL_WORD, true),
+            'S' => Escape::Set(PERL_SPACE, true),
+            'b' => Escape::WordBoundary(true),
+            'B' => Escape::WordBoundary(false),
+            'n' => Escape::Char('\n'),
+            't' => Escape::Char('\t'),
+            'r' => Escape::Char('\r'),
+            c if c.is_ascii_alphanumeric() => return Err(self.error("unknown escape sequence")),
+            c => Escape::Char(c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unclosed character class"))?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                match self.parse_escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Set(ranges, negated) => {
+                        items.push(ClassItem::Set(ranges, negated));
+                        continue;
+                    }
+                    Escape::WordBoundary(_) => {
+                        return Err(self.error("word boundary in character class"))
+                    }
+                }
+            } else {
+                c
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let mut hi = self.peek().unwrap();
+                self.pos += 1;
+                if hi == '\\' {
+                    match self.parse_escape()? {
+                        Escape::Char(c) => hi = c,
+                        _ => return Err(self.error("invalid character class range")),
+                    }
+                }
+                if hi < lo {
+                    return Err(self.error("invalid character class range"));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Node::Class(items, negated))
+    }
+
+    fn parse_number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let start = self.pos;
+                self.pos += 1;
+                let bounds = self.parse_number().and_then(|min| {
+                    if self.eat('}') {
+                        Some((min, Some(min)))
+                    } else if self.eat(',') {
+                        let max = self.parse_number();
+                        if self.eat('}') {
+                            Some((min, max))
+                        } else {
+                            None
+                        }
+                    } else {
+                        None
+                    }
+                });
+                match bounds {
+                    Some((min, Some(max))) if max < min => {
+                        return Err(self.error("invalid repetition range"))
+                    }
+                    Some(bounds) => {
+                        self.pos -= 1;
+                        bounds
+                    }
+                    None => {
+                        // Not a repetition, treat the brace literally.
+                        self.pos = start;
+                        return Ok(atom);
+                    }
+                }
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary(_)) {
+            return Err(self.error("repetition of an assertion"));
+        }
+        let greedy = !self.eat('?');
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+}
+
+enum Escape {
+    Char(char),
+    Set(&'static [(char, char)], bool),
+    WordBoundary(bool),
+}
+
+/// A regular expression that should be matched against a single column.
+#[derive(Debug, Clone)]
+pub struct ColumnPattern {
+    pub column: u32,
+    pub regex: Regex,
+}
+
+impl ColumnPattern {
+    pub fn is_match(&self, row: &Row) -> bool {
+        let i = self.column as usize - 1;
+        i < row.len() && self.regex.is_match(&row[i])
+    }
+}
+
+/// Parses patterns of the form `COL REGEX`.
+impl FromStr for ColumnPattern {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<ColumnPattern> {
+        let (col, regex) = s
+            .split_once(' ')
+            .ok_or_else(|| Error::ColumnPatternParseError { s: s.to_string() })?;
+        let column: u32 = col
+            .parse()
+            .map_err(|_| Error::ColumnPatternParseError { s: s.to_string() })?;
+        if column == 0 {
+            return Err(Error::ColumnsStartAtOne);
+        }
+        Ok(ColumnPattern {
+            column,
+            regex: regex.parse()?,
+        })
+    }
+}
+
+/// A pattern that every value of a column must match, for `--validate`.
+/// Rows without the column are validated as if the value was empty.
+#[derive(Debug, Clone)]
+pub struct Validation {
+    pub column: u32,
+    pub regex: Regex,
+}
+
+impl Validation {
+    pub fn is_valid(&self, row: &Row) -> bool {
+        let i = self.column as usize - 1;
+        self.regex.is_match(if i < row.len() { &row[i] } else { b"" })
+    }
+}
+
+/// Parses `COL:REGEX`.
+impl FromStr for Validation {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Validation> {
+        let err = || Error::ValidationParseError { s: s.to_string() };
+        let (column, regex) = s.split_once(':').ok_or_else(err)?;
+        let column: u32 = column.parse().map_err(|_| err())?;
+        if column == 0 {
+            return Err(Error::ColumnsStartAtOne);
+        }
+        Ok(Validation {
+            column,
+            regex: regex.parse()?,
+        })
+    }
+}
+
+/// A column whose value is taken from the line by a pattern, for
+/// `--extract`: the first group of the first match, or the whole match if the
+/// pattern has no groups.
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    name: String,
+    regex: Regex,
+}
+
+impl Extraction {
+    /// The header of the extracted column.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value extracted from `line`, which is empty if the pattern does
+    /// not match.
+    pub fn value<'a>(&self, line: &'a [u8]) -> &'a [u8] {
+        let group = if self.regex.groups() > 1 { 1 } else { 0 };
+        match self.regex.captures(line).and_then(|caps| caps[group]) {
+            Some((start, end)) => &line[start..end],
+            None => &[],
+        }
+    }
+}
+
+/// Appends the values extracted from the line of `row` as new cells after the
+/// first `fields` cells, so they are in the same columns in every row. Shorter
+/// rows are padded with empty cells, and the cells of longer rows from the
+/// `fields`-th on are merged. The values are all extracted before any is
+/// appended, so they come from the original line.
+pub fn apply_extractions(row: &mut Row, extractions: &[Extraction], fields: usize) {
+    if extractions.is_empty() {
+        return;
+    }
+    let line = row.as_line().to_vec();
+    row.set_len(fields);
+    for extraction in extractions {
+        row.push(extraction.value(&line));
+    }
+}
+
+/// Parses `NAME=REGEX`.
+impl FromStr for Extraction {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Extraction> {
+        let (name, regex) = s
+            .split_once('=')
+            .filter(|(name, _)| !name.is_empty())
+            .ok_or_else(|| Error::ExtractionParseError { s: s.to_string() })?;
+        Ok(Extraction {
+            name: name.to_string(),
+            regex: regex.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::new(pattern).unwrap().find(text.as_bytes())
+    }
+
+    #[test]
+    fn literals_and_anchors() {
+        assert_eq!(find("bc", "abcd"), Some((1, 3)));
+        assert_eq!(find("^bc", "abcd"), None);
+        assert_eq!(find("^ab", "abcd"), Some((0, 2)));
+        assert_eq!(find("cd$", "abcd"), Some((2, 4)));
+        assert_eq!(find("a|cd", "xcd"), Some((1, 3)));
+        assert_eq!(find("", "abc"), Some((0, 0)));
+    }
+
+    #[test]
+    fn repetition() {
+        assert_eq!(find("a+", "baaac"), Some((1, 4)));
+        assert_eq!(find("a+?", "baaac"), Some((1, 2)));
+        assert_eq!(find("ba*c", "bc"), Some((0, 2)));
+        assert_eq!(find("x.*y", "x1y2y"), Some((0, 5)));
+        assert_eq!(find("x.*?y", "x1y2y"), Some((0, 3)));
+        assert_eq!(find("a{2,3}", "aaaa"), Some((0, 3)));
+        assert_eq!(find("a{2}", "a"), None);
+        assert_eq!(find("(ab)+", "ababx"), Some((0, 4)));
+        assert_eq!(find("(a*)*b", "aab"), Some((0, 3)));
+        assert_eq!(find("a{x", "a{x"), Some((0, 3)));
+    }
+
+    #[test]
+    fn long_text() {
+        let text = "ab".repeat(100_000);
+        assert_eq!(find("^(?:ab)+$", &text), Some((0, text.len())));
+        assert_eq!(find("(a|a)*b", &"a".repeat(100_000)), None);
+        let caps = Regex::new("^(ab)*$").unwrap().captures(text.as_bytes()).unwrap();
+        assert_eq!(caps[1], Some((text.len() - 2, text.len())));
+        assert!(Regex::new("(?:a{1000}){1000}").is_err());
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(find(r"\d+", "abc 123 def"), Some((4, 7)));
+        assert_eq!(find(r"[a-c]+", "xxbcay"), Some((2, 5)));
+        assert_eq!(find(r"[^a-c ]+", "ab xy"), Some((3, 5)));
+        assert_eq!(find(r"\w+\s\w+", "-- foo bar"), Some((3, 10)));
+        assert_eq!(find(r"[\d.]+", "v1.25"), Some((1, 5)));
+        assert_eq!(find(r"\bfoo\b", "foobar foo"), Some((7, 10)));
+        assert_eq!(find(r"[]x]", "a]"), Some((1, 2)));
+        assert_eq!(find("ø.", "aøbc"), Some((1, 4)));
+    }
+
+    #[test]
+    fn captures() {
+        let re = Regex::new(r"status=(\d+)(?: (\w+))?").unwrap();
+        assert_eq!(re.groups(), 3);
+        let caps = re.captures(b"x status=404 gone").unwrap();
+        assert_eq!(caps, vec![Some((2, 17)), Some((9, 12)), Some((13, 17))]);
+        let caps = re.captures(b"status=5").unwrap();
+        assert_eq!(caps, vec![Some((0, 8)), Some((7, 8)), None]);
+    }
+
+    #[test]
+    fn extraction() {
+        let status: Extraction = r"status=status=(\d+)".parse().unwrap();
+        let word: Extraction = r"user=\bu\w+".parse().unwrap();
+        assert_eq!(status.name(), "status");
+        let mut row = Row::from_spans("GET / status=404 user1", vec![(0, 3), (4, 5), (6, 16), (17, 22)]);
+        apply_extractions(&mut row, &[status.clone(), word, status.clone()], 4);
+        let cells: Vec<&[u8]> = row.get_parts().collect();
+        assert_eq!(&cells[4..], [&b"404"[..], b"user1", b"404"]);
+
+        let mut short = Row::from_spans("a msg status=404", vec![(0, 1), (2, 5), (6, 16)]);
+        let mut long = Row::from_spans("b msg status=200 x y", vec![(0, 1), (2, 5), (6, 16), (17, 18), (19, 20)]);
+        apply_extractions(&mut short, std::slice::from_ref(&status), 4);
+        apply_extractions(&mut long, std::slice::from_ref(&status), 4);
+        let cells: Vec<&[u8]> = short.get_parts().collect();
+        assert_eq!(cells, [&b"a"[..], b"msg", b"status=404", b"", b"404"]);
+        let cells: Vec<&[u8]> = long.get_parts().collect();
+        assert_eq!(cells, [&b"b"[..], b"msg", b"status=200", b"x y", b"200"]);
+        assert_eq!(status.value(b"GET /"), b"");
+        assert!("=x".parse::<Extraction>().is_err());
+        assert!("x".parse::<Extraction>().is_err());
+        assert!("x=(".parse::<Extraction>().is_err());
+    }
+
+    #[test]
+    fn validation() {
+        let rule: Validation = r"2:^\d+$".parse().unwrap();
+        assert_eq!(rule.column, 2);
+        assert!(rule.is_valid(&Row::from_spans("a 12", vec![(0, 1), (2, 4)])));
+        assert!(!rule.is_valid(&Row::from_spans("a 1x", vec![(0, 1), (2, 4)])));
+        assert!(!rule.is_valid(&Row::from_spans("a", vec![(0, 1)])));
+        assert!("2".parse::<Validation>().is_err());
+        assert!("0:x".parse::<Validation>().is_err());
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(Regex::new("(ab").is_err());
+        assert!(Regex::new("ab)").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("[ab").is_err());
+        assert!(Regex::new(r"\q").is_err());
+        assert!(Regex::new("a{3,2}").is_err());
+    }
+}
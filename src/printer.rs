@@ -0,0 +1,552 @@
+//! Printing of rows according to the computed column layout.
+
+use std::io::{self, Write};
+
+use crate::color::{Color, Style};
+use crate::column::Column;
+use crate::parser::Row;
+use crate::{utils, Options};
+
+type VisibleCell<'r> = (usize, (&'r [u8], &'r Column));
+
+/// The cells of `row` with their index and column, skipping excluded columns.
+fn visible_cells<'r>(
+    columns: &'r [Column],
+    row: &'r Row,
+) -> impl Iterator<Item = (VisibleCell<'r>, bool, bool)> {
+    padded_cells(columns, row, false)
+}
+
+/// Like `visible_cells`, but with `pad` there is an empty cell for every
+/// column that `row` is too short for.
+fn padded_cells<'r>(
+    columns: &'r [Column],
+    row: &'r Row,
+    pad: bool,
+) -> impl Iterator<Item = (VisibleCell<'r>, bool, bool)> {
+    let missing = if pad { columns.len().saturating_sub(row.len()) } else { 0 };
+    utils::first_last_iter(
+        row.get_parts()
+            .chain(std::iter::repeat_n(&[][..], missing))
+            .zip(columns)
+            .enumerate()
+            .filter(|&(_, (_, col))| !col.is_excluded()),
+    )
+}
+
+/// The sizes of the columns that are not excluded.
+fn visible_sizes(columns: &[Column]) -> impl Iterator<Item = usize> + '_ {
+    columns.iter().filter(|c| !c.is_excluded()).map(|c| c.size())
+}
+
+pub(crate) struct RowPrinter<'a> {
+    opts: &'a Options,
+    highlight: Style,
+    pub matched_rows: usize,
+    prev_row: Option<Row>,
+    prev_group: Option<Vec<u8>>,
+    /// The line being printed, and the offsets where a column delimiter starts.
+    line: Vec<u8>,
+    breaks: Vec<usize>,
+    /// The size of every column and whether it is excluded, as last sent in a
+    /// layout event.
+    sent_layout: Vec<(usize, bool)>,
+    /// The sizes of the visible columns the header was last underlined with,
+    /// and the header, which the rule is at least as wide as.
+    rule_sizes: Option<Vec<usize>>,
+    underlined: Option<Row>,
+    /// The hash of the visible cells printed so far, for `Options::checksum`.
+    checksum: Option<utils::Fnv1a>,
+    /// The number of rows printed and of the cells in them that were cut
+    /// off, for `Options::footer_info` and the summary.
+    rows_printed: usize,
+    truncated_cells: usize,
+    /// The header to start every page with, the number of the current page
+    /// and the number of rows printed on it, for `Options::page_length`.
+    header: Option<Row>,
+    page: usize,
+    page_rows: usize,
+    /// Whether the top of the frame has been drawn but not the bottom, for
+    /// `Options::border`.
+    framed: bool,
+    /// When only some lines are tabulated, the header waits for the first row
+    /// of the table, so that the lines before the table are printed before it.
+    pending_header: Option<Row>,
+    /// Where to print the rows that fail validation instead of the output, and
+    /// how many rows failed.
+    pub invalid: Option<&'a mut dyn Write>,
+    pub invalid_rows: usize,
+}
+
+impl<'a> RowPrinter<'a> {
+    pub fn new(opts: &'a Options) -> RowPrinter<'a> {
+        RowPrinter {
+            opts,
+            highlight: Style::fg(Color::Red).bold(),
+            matched_rows: 0,
+            prev_row: None,
+            prev_group: None,
+            line: Vec::new(),
+            breaks: Vec::new(),
+            sent_layout: Vec::new(),
+            rule_sizes: None,
+            underlined: None,
+            checksum: opts.checksum.then(utils::Fnv1a::new),
+            rows_printed: 0,
+            truncated_cells: 0,
+            header: None,
+            page: 0,
+            page_rows: 0,
+            framed: false,
+            pending_header: None,
+            invalid: None,
+            invalid_rows: 0,
+        }
+    }
+
+    /// The hash of the values of the visible cells of the printed rows, before
+    /// they were padded or truncated, if `Options::checksum` is set.
+    pub fn checksum(&self) -> Option<u64> {
+        self.checksum.map(|hash| hash.finish())
+    }
+
+    /// Adds the visible cells of `row` to the checksum. Cells end with a unit
+    /// separator and rows with a record separator, so moving a value to
+    /// another cell or row changes the checksum.
+    fn add_to_checksum(&mut self, columns: &[Column], row: &Row) {
+        if let Some(ref mut hash) = self.checksum {
+            for ((_, (cell, _)), _, _) in visible_cells(columns, row) {
+                hash.write(cell);
+                hash.write(b"\x1f");
+            }
+            hash.write(b"\x1e");
+        }
+    }
+
+    pub fn print_row<W: Write>(&mut self, out: &mut W, columns: &[Column], row: &Row) -> io::Result<()> {
+        let mut highlight = None;
+        if let Some(ref find) = self.opts.find {
+            if find.is_match(row) {
+                self.matched_rows += 1;
+                if self.opts.color {
+                    highlight = Some(find.column as usize - 1);
+                }
+            }
+        }
+        if self.opts.only_status() {
+            return Ok(());
+        }
+        if self.opts.passes_through(row) {
+            if !self.opts.events {
+                // The lines after a table embedded in other lines go below its frame.
+                if self.opts.tabulates_some_lines() {
+                    self.close_frame(out, columns)?;
+                }
+                out.write_all(row.as_line())?;
+                writeln!(out)?;
+            }
+            return Ok(());
+        }
+        if self.opts.events {
+            return self.print_event(out, "row", columns, row);
+        }
+        if let Some(header) = self.pending_header.take() {
+            self.show_header(out, columns, &header)?;
+        }
+        // The columns with values that fail validation.
+        let invalid = self.opts.invalid_columns(row);
+        if !invalid.is_empty() {
+            self.invalid_rows += 1;
+            if let Some(mut out) = self.invalid.take() {
+                self.format_row(columns, row, highlight, &[])?;
+                let written = self.write_line(&mut out);
+                self.invalid = Some(out);
+                return written;
+            }
+        }
+        if let Some(n) = self.opts.page_length {
+            if self.page == 0 || self.page_rows == n {
+                self.start_page(out, columns)?;
+            }
+            self.page_rows += 1;
+        }
+        if let (Some(c), Some(sizes)) = (self.opts.underline_header, self.rule_sizes.as_ref()) {
+            if !visible_sizes(columns).eq(sizes.iter().copied()) {
+                // Between rows a rule alone would look like a separator, so
+                // the header is printed again above it.
+                match self.underlined.clone() {
+                    Some(ref header) if self.rows_printed > 0 => self.write_header(out, columns, header)?,
+                    _ => self.print_rule(out, columns, c)?,
+                }
+            }
+        }
+        self.open_frame(out, columns)?;
+        self.print_group_separator(out, columns, row)?;
+        self.add_to_checksum(columns, row);
+        self.truncated_cells += self.format_row(columns, row, highlight, &invalid)?;
+        self.rows_printed += 1;
+        self.write_line(out)?;
+
+        if self.opts.collapse_repeats.is_some() {
+            self.prev_row = Some(row.clone());
+        }
+        Ok(())
+    }
+
+    /// Formats the cells of `row` into the current line. The cell in column
+    /// `highlight` and the `invalid` ones are highlighted. Returns how many
+    /// cells were cut off.
+    fn format_row(
+        &mut self,
+        columns: &[Column],
+        row: &Row,
+        highlight: Option<usize>,
+        invalid: &[usize],
+    ) -> io::Result<usize> {
+        let mut line = std::mem::take(&mut self.line);
+        line.clear();
+        self.breaks.clear();
+        let mut overflow: usize = 0;
+        let mut truncated = 0;
+        let border = self.opts.border;
+        if border {
+            line.extend_from_slice("│ ".as_bytes());
+        }
+        for ((i, (cell, col)), first, last) in padded_cells(columns, row, border) {
+            // The last column is padded too, so the right edge of the frame lines up.
+            let last = last && !border;
+            if !first {
+                self.breaks.push(line.len());
+                line.extend_from_slice(self.opts.column_delim());
+            }
+            let marked;
+            let cell = if !self.opts.color && invalid.contains(&i) {
+                marked = [cell, &self.opts.invalid_mark[..]].concat();
+                &marked[..]
+            } else {
+                cell
+            };
+            let style = if highlight == Some(i) || (self.opts.color && invalid.contains(&i)) {
+                Some(&self.highlight)
+            } else if self.opts.color {
+                self.opts
+                    .thresholds
+                    .iter()
+                    .filter(|t| t.column as usize == i + 1)
+                    .find_map(|t| t.style(cell))
+            } else {
+                None
+            };
+            let cell = match self.opts.empty_value {
+                _ if self.is_repeated(row, i) => &self.opts.ditto_mark[..],
+                Some(ref empty) if cell.is_empty() => &empty[..],
+                _ => cell,
+            };
+            if col.truncates(cell, overflow, last) {
+                truncated += 1;
+            }
+            overflow = col.print_cell(&mut line, cell, overflow, last, style)?;
+        }
+        if border {
+            line.extend_from_slice(" │".as_bytes());
+        }
+        self.line = line;
+        Ok(truncated)
+    }
+
+    /// The number of cells that were cut off in the rows printed so far.
+    pub fn truncated_cells(&self) -> usize {
+        self.truncated_cells
+    }
+
+    /// Prints a comment line with the number of rows and visible columns that
+    /// were printed, and how many cells were cut off.
+    pub fn print_footer<W: Write>(&mut self, out: &mut W, columns: &[Column]) -> io::Result<()> {
+        if self.opts.only_status() {
+            return Ok(());
+        }
+        writeln!(
+            out,
+            "# {} rows, {} columns, {} truncated",
+            self.rows_printed,
+            visible_sizes(columns).count(),
+            self.truncated_cells
+        )
+    }
+
+    /// Prints the header row, which is never truncated. With
+    /// `Options::page_length` it is printed again at the top of every page.
+    pub fn print_header<W: Write>(&mut self, out: &mut W, columns: &[Column], header: &Row) -> io::Result<()> {
+        if self.opts.only_status() {
+            return Ok(());
+        }
+        if self.opts.events {
+            return self.print_event(out, "header", columns, header);
+        }
+        self.add_to_checksum(columns, header);
+        if self.opts.tabulates_some_lines() {
+            self.pending_header = Some(header.clone());
+            return Ok(());
+        }
+        self.show_header(out, columns, header)
+    }
+
+    fn show_header<W: Write>(&mut self, out: &mut W, columns: &[Column], header: &Row) -> io::Result<()> {
+        if self.opts.page_length.is_some() {
+            self.header = Some(header.clone());
+            return self.start_page(out, columns);
+        }
+        self.write_header(out, columns, header)
+    }
+
+    /// Starts a new page with a form feed, unless it is the first, the page
+    /// number and the header.
+    fn start_page<W: Write>(&mut self, out: &mut W, columns: &[Column]) -> io::Result<()> {
+        if self.page > 0 {
+            self.close_frame(out, columns)?;
+            out.write_all(b"\x0c")?;
+        }
+        self.page += 1;
+        self.page_rows = 0;
+        if self.opts.page_numbers {
+            writeln!(out, "Page {}", self.page)?;
+        }
+        if let Some(header) = self.header.take() {
+            self.write_header(out, columns, &header)?;
+            self.header = Some(header);
+        }
+        Ok(())
+    }
+
+    fn write_header<W: Write>(&mut self, out: &mut W, columns: &[Column], header: &Row) -> io::Result<()> {
+        if self.opts.hide_header {
+            return Ok(());
+        }
+        let columns: Vec<Column> = columns
+            .iter()
+            .map(|c| {
+                let mut col = c.untruncated();
+                if let Some(alignment) = self.opts.header_align {
+                    col.set_alignment(alignment);
+                }
+                col
+            })
+            .collect();
+        self.open_frame(out, &columns)?;
+        self.line.clear();
+        self.breaks.clear();
+        let border = self.opts.border;
+        if border {
+            self.line.extend_from_slice("│ ".as_bytes());
+        }
+        let mut overflow: usize = 0;
+        for ((_, (cell, col)), first, last) in padded_cells(&columns, header, border) {
+            if !first {
+                self.breaks.push(self.line.len());
+                self.line.extend_from_slice(self.opts.column_delim());
+            }
+            let style = self.opts.header_style.as_ref().filter(|_| self.opts.color);
+            overflow = col.print_cell(&mut self.line, cell, overflow, last && !border, style)?;
+        }
+        if border {
+            self.line.extend_from_slice(" │".as_bytes());
+        }
+        self.write_line(out)?;
+        if border {
+            print_frame_rule(out, &columns, ['├', '┼', '┤'])?;
+        }
+        match self.opts.underline_header {
+            // In online mode nothing has been measured yet, so the rule is
+            // printed before the first row instead.
+            Some(_) if visible_sizes(&columns).all(|size| size == 0) => {
+                self.rule_sizes = Some(Vec::new());
+                self.underlined = Some(header.clone());
+            }
+            Some(c) => {
+                self.underlined = Some(header.clone());
+                self.print_rule(out, &columns, c)?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Draws the top of the frame, if there is a border and it is not drawn yet.
+    fn open_frame<W: Write>(&mut self, out: &mut W, columns: &[Column]) -> io::Result<()> {
+        if self.opts.border && !self.framed {
+            print_frame_rule(out, columns, ['┌', '┬', '┐'])?;
+            self.framed = true;
+        }
+        Ok(())
+    }
+
+    /// Prints the header if no row of the table came after it, and draws the
+    /// bottom of the frame. Called after the last row.
+    pub fn finish<W: Write>(&mut self, out: &mut W, columns: &[Column]) -> io::Result<()> {
+        if let Some(header) = self.pending_header.take() {
+            self.show_header(out, columns, &header)?;
+        }
+        self.close_frame(out, columns)
+    }
+
+    /// Draws the bottom of the frame, if its top has been drawn.
+    fn close_frame<W: Write>(&mut self, out: &mut W, columns: &[Column]) -> io::Result<()> {
+        if self.framed {
+            print_frame_rule(out, columns, ['└', '┴', '┘'])?;
+            self.framed = false;
+        }
+        Ok(())
+    }
+
+    /// Prints a rule of `c` under every visible column, as wide as the column
+    /// or as its header, whichever is wider.
+    fn print_rule<W: Write>(&mut self, out: &mut W, columns: &[Column], c: char) -> io::Result<()> {
+        let mut buf = [0; 4];
+        let c = c.encode_utf8(&mut buf).as_bytes();
+        let visible = columns.iter().enumerate().filter(|(_, col)| !col.is_excluded());
+        for (n, (i, col)) in visible.enumerate() {
+            if n > 0 {
+                out.write_all(&self.opts.output_delim)?;
+            }
+            let width = match self.underlined {
+                Some(ref header) if i < header.len() => self.opts.widths().width(&header[i]),
+                _ => 0,
+            };
+            out.write_all(&c.repeat(col.size().max(width)))?;
+        }
+        writeln!(out)?;
+        self.rule_sizes = Some(visible_sizes(columns).collect());
+        Ok(())
+    }
+
+    /// Writes the visible cells of `row` as a JSON event of the given type,
+    /// after a layout event if the columns changed since the last one.
+    fn print_event<W: Write>(&mut self, out: &mut W, kind: &str, columns: &[Column], row: &Row) -> io::Result<()> {
+        let layout: Vec<(usize, bool)> = columns.iter().map(|c| (c.size(), c.is_excluded())).collect();
+        if layout != self.sent_layout {
+            write!(out, "{{\"type\":\"layout\",\"columns\":[")?;
+            for (i, &(size, excluded)) in layout.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                write!(out, "{{\"size\":{},\"excluded\":{}}}", size, excluded)?;
+            }
+            writeln!(out, "]}}")?;
+            self.sent_layout = layout;
+        }
+        write!(out, "{{\"type\":\"{}\",\"cells\":[", kind)?;
+        for ((_, (cell, _)), first, _) in visible_cells(columns, row) {
+            if !first {
+                write!(out, ",")?;
+            }
+            utils::write_json_string(out, cell)?;
+        }
+        writeln!(out, "]}}")
+    }
+
+    /// Writes the current line. With --soft-wrap, columns that would make the
+    /// line wider than the output are moved to continuation lines. With
+    /// --no-trailing-space, spaces at the end of every line are left out.
+    fn write_line<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let trim = self.opts.no_trailing_space;
+        let (marker, width) = match (&self.opts.soft_wrap, self.opts.width) {
+            (Some(marker), Some(width)) if !self.opts.fit => (marker, width),
+            _ => {
+                out.write_all(if trim { utils::trim_end(&self.line) } else { &self.line })?;
+                return writeln!(out);
+            }
+        };
+
+        let delim_len = self.opts.output_delim.len();
+        let widths = self.opts.widths();
+        let measure = |s: &[u8]| widths.width(s);
+        let mut line_width = 0;
+        let mut start = 0;
+        // The trailing spaces of the last segment, written only if another
+        // segment follows on the same line.
+        let mut spaces: &[u8] = &[];
+        for end in self.breaks.iter().copied().chain(Some(self.line.len())) {
+            let mut segment = &self.line[start..end];
+            let visible_width = measure(utils::trim_end(segment));
+            if start > 0 && line_width + visible_width > width {
+                if !trim {
+                    out.write_all(spaces)?;
+                }
+                out.write_all(b"\n")?;
+                out.write_all(marker)?;
+                segment = &segment[delim_len..];
+                line_width = measure(marker);
+            } else {
+                out.write_all(spaces)?;
+            }
+            let visible = utils::trim_end(segment);
+            out.write_all(visible)?;
+            spaces = &segment[visible.len()..];
+            line_width += measure(segment);
+            start = end;
+        }
+        if !trim {
+            out.write_all(spaces)?;
+        }
+        writeln!(out)
+    }
+
+    /// Prints a blank line or a rule if the value of the --group-sep column
+    /// differs from the one in the previous row.
+    fn print_group_separator<W: Write>(&mut self, out: &mut W, columns: &[Column], row: &Row) -> io::Result<()> {
+        let col = match self.opts.group_sep {
+            // Columns are counted from 1, so 0 is no column.
+            Some(col) if col > 0 => col as usize - 1,
+            _ => return Ok(()),
+        };
+        let value = if col < row.len() { &row[col] } else { &[][..] };
+        match self.prev_group {
+            Some(ref prev) if prev[..] != *value && self.opts.border => {
+                print_frame_rule(out, columns, ['├', '┼', '┤'])?;
+            }
+            Some(ref prev) if prev[..] != *value => {
+                if let Some(rule) = self.opts.group_rule {
+                    let visible: Vec<&Column> = columns.iter().filter(|c| !c.is_excluded()).collect();
+                    let width = visible.iter().map(|c| c.size()).sum::<usize>()
+                        + self.opts.delim_width() * visible.len().saturating_sub(1);
+                    write!(out, "{}", rule.to_string().repeat(width))?;
+                }
+                writeln!(out)?;
+            }
+            _ => {}
+        }
+        self.prev_group = Some(value.to_vec());
+        Ok(())
+    }
+
+    /// Whether cell `i` should be replaced by a ditto mark because it equals
+    /// the cell above it.
+    fn is_repeated(&self, row: &Row, i: usize) -> bool {
+        let collapsed = self
+            .opts
+            .collapse_repeats
+            .as_ref()
+            .map(|rs| rs.any_contains((i + 1) as u32))
+            .unwrap_or(false);
+        match self.prev_row {
+            Some(ref prev) if collapsed => i < prev.len() && i < row.len() && prev[i] == row[i],
+            _ => false,
+        }
+    }
+}
+
+/// Draws a horizontal line of the frame along the visible columns. `corners`
+/// are drawn at the left edge, between the columns and at the right edge.
+fn print_frame_rule<W: Write>(out: &mut W, columns: &[Column], corners: [char; 3]) -> io::Result<()> {
+    let mut rule = String::new();
+    rule.push(corners[0]);
+    for (i, size) in visible_sizes(columns).enumerate() {
+        if i > 0 {
+            rule.push(corners[1]);
+        }
+        // The column and the spaces on either side of it.
+        rule.extend(std::iter::repeat_n('─', size + 2));
+    }
+    rule.push(corners[2]);
+    writeln!(out, "{}", rule)
+}
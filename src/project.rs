@@ -0,0 +1,289 @@
+//! Building output columns from input columns with `--project`.
+//!
+//! A projection is a comma-separated list of expressions, one for each output
+//! column. An expression is a column number (starting at 1), a string literal
+//! in double quotes or a function applied to other expressions, e.g.
+//! `1,3,"literal",upper(4)`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::*;
+use crate::parser::Row;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Upper,
+    Lower,
+    Trim,
+    Len,
+    Concat,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "upper" => Some(Function::Upper),
+            "lower" => Some(Function::Lower),
+            "trim" => Some(Function::Trim),
+            "len" => Some(Function::Len),
+            "concat" => Some(Function::Concat),
+            _ => None,
+        }
+    }
+
+    fn arity(self) -> Option<usize> {
+        match self {
+            Function::Concat => None,
+            _ => Some(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Column(usize),
+    Literal(Vec<u8>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, row: &Row, out: &mut Vec<u8>) {
+        match *self {
+            Expr::Column(i) => {
+                if i < row.len() {
+                    out.extend_from_slice(&row[i]);
+                }
+            }
+            Expr::Literal(ref s) => out.extend_from_slice(s),
+            Expr::Call(f, ref args) => {
+                let mut value = Vec::new();
+                for arg in args {
+                    arg.eval(row, &mut value);
+                }
+                match f {
+                    Function::Upper => out.extend(map_text(&value, str::to_uppercase, <[u8]>::to_ascii_uppercase)),
+                    Function::Lower => out.extend(map_text(&value, str::to_lowercase, <[u8]>::to_ascii_lowercase)),
+                    Function::Trim => out.extend_from_slice(value.trim_ascii()),
+                    Function::Len => {
+                        let len = std::str::from_utf8(&value)
+                            .map(|s| s.chars().count())
+                            .unwrap_or(value.len());
+                        out.extend_from_slice(len.to_string().as_bytes());
+                    }
+                    Function::Concat => out.extend_from_slice(&value),
+                }
+            }
+        }
+    }
+}
+
+/// Applies `text` to valid UTF-8 and `bytes` to anything else.
+fn map_text(value: &[u8], text: fn(&str) -> String, bytes: fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+    match std::str::from_utf8(value) {
+        Ok(s) => text(s).into_bytes(),
+        Err(_) => bytes(value),
+    }
+}
+
+#[derive(Clone)]
+pub struct Projection {
+    source: String,
+    exprs: Vec<Expr>,
+}
+
+impl Projection {
+    pub fn new(s: &str) -> Result<Projection> {
+        let mut parser = ProjectionParser {
+            source: s,
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let exprs = parser.parse_list()?;
+        Ok(Projection {
+            source: s.to_string(),
+            exprs,
+        })
+    }
+
+    /// Replaces `row` with the output columns of the projection.
+    pub fn apply(&self, row: &mut Row) {
+        let mut line = Vec::new();
+        let mut spans = Vec::with_capacity(self.exprs.len());
+        for expr in &self.exprs {
+            let start = line.len();
+            expr.eval(row, &mut line);
+            spans.push((start, line.len()));
+        }
+        *row = Row::from_spans(line, spans);
+    }
+}
+
+impl FromStr for Projection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Projection> {
+        Projection::new(s)
+    }
+}
+
+impl fmt::Debug for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Projection({:?})", self.source)
+    }
+}
+
+struct ProjectionParser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> ProjectionParser<'a> {
+    fn error(&self, msg: &str) -> Error {
+        Error::ProjectionParseError {
+            s: self.source.to_string(),
+            msg: msg.to_string(),
+            pos: self.pos,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<Expr>> {
+        let mut exprs = vec![self.parse_expr()?];
+        while self.eat(',') {
+            exprs.push(self.parse_expr()?);
+        }
+        self.skip_whitespace();
+        if self.peek().is_some() {
+            return Err(self.error("expected ','"));
+        }
+        Ok(exprs)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') => {
+                self.pos += 1;
+                self.parse_literal()
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let n = self.take_while(|c| c.is_ascii_digit());
+                match n.parse::<usize>() {
+                    Ok(0) => Err(Error::ColumnsStartAtOne),
+                    Ok(n) => Ok(Expr::Column(n - 1)),
+                    Err(_) => Err(self.error("column number is too large")),
+                }
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                let f = Function::from_name(&name)
+                    .ok_or_else(|| self.error(&format!("unknown function '{}'", name)))?;
+                if !self.eat('(') {
+                    return Err(self.error("expected '(' after function name"));
+                }
+                let mut args = Vec::new();
+                if !self.eat(')') {
+                    args.push(self.parse_expr()?);
+                    while self.eat(',') {
+                        args.push(self.parse_expr()?);
+                    }
+                    if !self.eat(')') {
+                        return Err(self.error("expected ')'"));
+                    }
+                }
+                if f.arity().is_some_and(|n| n != args.len()) {
+                    return Err(self.error(&format!("wrong number of arguments to '{}'", name)));
+                }
+                Ok(Expr::Call(f, args))
+            }
+            Some(_) => Err(self.error("expected a column, a string or a function")),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Expr> {
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(Expr::Literal(s.into_bytes()));
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c) => s.push(c),
+                        None => return Err(self.error("unterminated string")),
+                    }
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::RowParser;
+
+    fn project(projection: &str, line: &str) -> Vec<String> {
+        let mut row = Row::new();
+        RowParser::new(" ", false).parse_into(&mut row, line);
+        projection.parse::<Projection>().unwrap().apply(&mut row);
+        row.get_parts()
+            .map(|c| String::from_utf8(c.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn projections() {
+        assert_eq!(project("1,3", "a b c"), ["a", "c"]);
+        assert_eq!(project(r#"1, "x \"y\"", upper(2)"#, "a bø"), ["a", "x \"y\"", "BØ"]);
+        assert_eq!(project("concat(2, \"-\", 1), len(2), 5", "ab cde"), ["cde-ab", "3", ""]);
+    }
+
+    #[test]
+    fn invalid_projections() {
+        assert!("".parse::<Projection>().is_err());
+        assert!("0".parse::<Projection>().is_err());
+        assert!("1 2".parse::<Projection>().is_err());
+        assert!("foo(1)".parse::<Projection>().is_err());
+        assert!("upper(1, 2)".parse::<Projection>().is_err());
+        assert!("\"abc".parse::<Projection>().is_err());
+    }
+}
@@ -28,7 +28,7 @@ impl FromStr for Range {
     type Err = Error;
     fn from_str(s: &str) -> Result<Range> {
         use crate::Range::*;
-        let num = || many1(digit()).map(|string: String| string.parse::<u32>().unwrap());
+        let num = || many1(digit()).map(|string: String| string.parse::<u32>().unwrap_or(u32::MAX));
 
         let mut range = num()
             .and(optional(token('-').with(optional(num()))))
@@ -72,3 +72,46 @@ impl FromIterator<Range> for Ranges {
         Ranges(Vec::from_iter(iter))
     }
 }
+
+/// Values assigned to ranges of columns. When ranges overlap, the value given
+/// last takes precedence.
+#[derive(Debug, Clone)]
+pub struct RangeMap<T>(pub Vec<(Ranges, T)>);
+
+impl<T> RangeMap<T> {
+    pub fn new() -> RangeMap<T> {
+        RangeMap(Vec::new())
+    }
+
+    pub fn get(&self, n: u32) -> Option<&T> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(rs, _)| rs.any_contains(n))
+            .map(|(_, v)| v)
+    }
+}
+
+impl<T> Default for RangeMap<T> {
+    fn default() -> RangeMap<T> {
+        RangeMap::new()
+    }
+}
+
+impl<T> FromIterator<(Ranges, T)> for RangeMap<T> {
+    fn from_iter<I: IntoIterator<Item = (Ranges, T)>>(iter: I) -> Self {
+        RangeMap(Vec::from_iter(iter))
+    }
+}
+
+/// Parses a value assigned to a list of ranges, in the form `LIST=VALUE`.
+pub fn parse_range_value<T: FromStr>(s: &str) -> Result<(Ranges, T)> {
+    let (list, value) = s
+        .split_once('=')
+        .ok_or_else(|| Error::RangeValueParseError { s: s.to_string() })?;
+    let ranges = list.split(',').map(|r| r.parse()).collect::<Result<Ranges>>()?;
+    let value = value
+        .parse()
+        .map_err(|_| Error::RangeValueParseError { s: s.to_string() })?;
+    Ok((ranges, value))
+}
@@ -0,0 +1,81 @@
+//! Renaming the columns of the header, for `--rename`.
+
+use std::str::FromStr;
+
+use crate::errors::*;
+use crate::parser::Row;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Label {
+    Column(u32),
+    Name(Vec<u8>),
+}
+
+/// A new name for the header of a column, given by its number or its
+/// current name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    label: Label,
+    name: Vec<u8>,
+}
+
+/// Parses `OLD=NEW`, where OLD is the current name of a column or its number.
+impl FromStr for Rename {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Rename> {
+        let (old, new) = s
+            .split_once('=')
+            .filter(|(old, _)| !old.is_empty())
+            .ok_or_else(|| Error::RenameParseError { s: s.to_string() })?;
+        let label = match old.parse::<u32>() {
+            Ok(0) => return Err(Error::ColumnsStartAtOne),
+            Ok(n) => Label::Column(n),
+            Err(_) => Label::Name(old.as_bytes().to_vec()),
+        };
+        Ok(Rename {
+            label,
+            name: new.as_bytes().to_vec(),
+        })
+    }
+}
+
+/// Replaces the names in `header` by the first rename that matches the
+/// column, by number or by its original name.
+pub(crate) fn rename_header(header: &mut Row, renames: &[Rename]) {
+    if renames.is_empty() {
+        return;
+    }
+    let mut renamed = Row::new();
+    for (i, cell) in header.get_parts().enumerate() {
+        let new = renames.iter().find(|r| match r.label {
+            Label::Column(n) => n as usize == i + 1,
+            Label::Name(ref name) => name[..] == *cell,
+        });
+        renamed.push(new.map(|r| &r.name[..]).unwrap_or(cell));
+    }
+    *header = renamed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::RowParser;
+
+    #[test]
+    fn rename_by_name_and_number() {
+        let renames: Vec<Rename> = ["req_count=Requests", "3=Bytes", "missing=x"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let mut header = Row::new();
+        RowParser::new(" ", false).parse_into(&mut header, "host req_count bytes_total");
+        rename_header(&mut header, &renames);
+        let cells: Vec<&[u8]> = header.get_parts().collect();
+        assert_eq!(cells, [&b"host"[..], b"Requests", b"Bytes"]);
+
+        assert!("0=x".parse::<Rename>().is_err());
+        assert!("=x".parse::<Rename>().is_err());
+        assert!("x".parse::<Rename>().is_err());
+    }
+}
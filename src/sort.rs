@@ -0,0 +1,235 @@
+//! Sorting rows by a column, for `--sort`.
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use crate::errors::*;
+use crate::numeric;
+use crate::parser::Row;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Byte-wise comparison.
+    Text,
+    /// Version numbers, like `sort -V`: `1.9 < 1.10 < 1.10a`.
+    Version,
+    /// Numbers by value. Values that are not numbers sort last.
+    Numeric,
+    /// Numbers in the text compare by value and letters ignore case:
+    /// `file2 < File10`.
+    Natural,
+}
+
+/// A column to sort by and how to compare its values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: u32,
+    pub order: SortOrder,
+}
+
+impl SortKey {
+    pub fn compare(&self, a: &Row, b: &Row) -> Ordering {
+        let (a, b) = (self.cell(a), self.cell(b));
+        match self.order {
+            SortOrder::Text => a.cmp(b),
+            SortOrder::Version => compare_versions(a, b),
+            SortOrder::Numeric => compare_numbers(a, b),
+            SortOrder::Natural => compare_natural(a, b),
+        }
+    }
+
+    fn cell<'r>(&self, row: &'r Row) -> &'r [u8] {
+        let i = self.column as usize - 1;
+        if i < row.len() {
+            &row[i]
+        } else {
+            &[]
+        }
+    }
+}
+
+/// Parses `COL` or `COL:ORDER`, where ORDER is `text`, `numeric`, `version`
+/// or `natural`.
+impl FromStr for SortKey {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<SortKey> {
+        let err = || Error::SortKeyParseError { s: s.to_string() };
+        let (column, order) = match s.split_once(':') {
+            Some((c, o)) => (c, o),
+            None => (s, "text"),
+        };
+        let column: u32 = column.parse().map_err(|_| err())?;
+        if column == 0 {
+            return Err(Error::ColumnsStartAtOne);
+        }
+        let order = match order {
+            "text" => SortOrder::Text,
+            "numeric" => SortOrder::Numeric,
+            "version" => SortOrder::Version,
+            "natural" => SortOrder::Natural,
+            _ => return Err(err()),
+        };
+        Ok(SortKey { column, order })
+    }
+}
+
+/// Sorts `rows` by the first of `keys`, then by the second for rows that are
+/// equal by the first, and so on. Rows that are equal by all keys keep their
+/// order.
+pub fn sort_rows(rows: &mut [Row], keys: &[SortKey]) {
+    rows.sort_by(|a, b| compare_rows(a, b, keys));
+}
+
+/// Compares rows by the first of `keys` that tells them apart.
+pub fn compare_rows(a: &Row, b: &Row, keys: &[SortKey]) -> Ordering {
+    keys.iter()
+        .map(|key| key.compare(a, b))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+fn compare_numbers(a: &[u8], b: &[u8]) -> Ordering {
+    match (numeric::parse_number(a), numeric::parse_number(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+/// Splits `s` into runs of digits and runs of other bytes.
+fn chunks(s: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = *rest.first()?;
+        let n = rest
+            .iter()
+            .position(|b| b.is_ascii_digit() != first.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(n);
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+/// Compares runs of digits by their numeric value, without overflowing.
+fn compare_digits(a: &[u8], b: &[u8]) -> Ordering {
+    fn trim(s: &[u8]) -> &[u8] {
+        let zeros = s.iter().take_while(|&&b| b == b'0').count();
+        &s[zeros..]
+    }
+    let (a, b) = (trim(a), trim(b));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// Compares non-digit runs of version strings. Letters sort before other
+/// characters and `~` sorts before everything, even the end of the string.
+fn compare_version_text(a: &[u8], b: &[u8]) -> Ordering {
+    let weight = |c: Option<&u8>| match c {
+        Some(b'~') => -1,
+        None => 0,
+        Some(c) if c.is_ascii_alphabetic() => *c as i32,
+        Some(c) => *c as i32 + 256,
+    };
+    let n = a.len().max(b.len());
+    (0..n)
+        .map(|i| weight(a.get(i)).cmp(&weight(b.get(i))))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+fn compare_versions(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut a, mut b) = (chunks(a), chunks(b));
+    loop {
+        let order = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(x), Some(y)) if x[0].is_ascii_digit() && y[0].is_ascii_digit() => compare_digits(x, y),
+            (Some(x), Some(y)) if x[0].is_ascii_digit() => compare_version_text(b"", y),
+            (Some(x), Some(y)) if y[0].is_ascii_digit() => compare_version_text(x, b""),
+            (Some(x), Some(y)) => compare_version_text(x, y),
+            (Some(x), None) if x[0].is_ascii_digit() => Ordering::Greater,
+            (Some(x), None) => compare_version_text(x, b""),
+            (None, Some(y)) if y[0].is_ascii_digit() => Ordering::Less,
+            (None, Some(y)) => compare_version_text(b"", y),
+        };
+        if order.is_ne() {
+            return order;
+        }
+    }
+}
+
+fn compare_natural(a: &[u8], b: &[u8]) -> Ordering {
+    let mut chunks_a = chunks(a);
+    let mut chunks_b = chunks(b);
+    loop {
+        let order = match (chunks_a.next(), chunks_b.next()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x[0].is_ascii_digit() && y[0].is_ascii_digit() => compare_digits(x, y),
+            (Some(x), Some(y)) => {
+                let lower = |c: &u8| c.to_ascii_lowercase();
+                x.iter().map(lower).cmp(y.iter().map(lower))
+            }
+        };
+        if order.is_ne() {
+            return order;
+        }
+    }
+    // Equal apart from case and leading zeros; fall back to the bytes so the
+    // order is still total.
+    a.cmp(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_order() {
+        let mut versions = vec![
+            "1.10", "1.9", "1.10a", "1.2.3", "1.10~rc1", "v2", "1.010.1", "1.0", "1",
+        ];
+        versions.sort_by(|a, b| compare_versions(a.as_bytes(), b.as_bytes()));
+        assert_eq!(
+            versions,
+            ["1", "1.0", "1.2.3", "1.9", "1.10~rc1", "1.10", "1.10a", "1.010.1", "v2"]
+        );
+    }
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["file10.txt", "File2.txt", "file1.txt", "file02.txt", "a", "file"];
+        names.sort_by(|a, b| compare_natural(a.as_bytes(), b.as_bytes()));
+        assert_eq!(names, ["a", "file", "file1.txt", "File2.txt", "file02.txt", "file10.txt"]);
+    }
+
+    #[test]
+    fn multiple_keys() {
+        use crate::parser::RowParser;
+
+        let parser = RowParser::new(" ", false);
+        let mut rows: Vec<Row> = ["b 10", "a 9", "b 9", "a x", "a 10"]
+            .iter()
+            .map(|line| {
+                let mut row = Row::new();
+                parser.parse_into(&mut row, *line);
+                row
+            })
+            .collect();
+        let keys: Vec<SortKey> = vec!["1".parse().unwrap(), "2:numeric".parse().unwrap()];
+        sort_rows(&mut rows, &keys);
+        let lines: Vec<&[u8]> = rows.iter().map(|r| r.as_line()).collect();
+        assert_eq!(lines, [&b"a 9"[..], b"a 10", b"a x", b"b 9", b"b 10"]);
+    }
+
+    #[test]
+    fn parse_sort_key() {
+        let key: SortKey = "2:version".parse().unwrap();
+        assert_eq!(key.column, 2);
+        assert_eq!(key.order, SortOrder::Version);
+        assert_eq!("3".parse::<SortKey>().unwrap().order, SortOrder::Text);
+        assert!("0".parse::<SortKey>().is_err());
+        assert!("2:foo".parse::<SortKey>().is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Sparklines of the recent values of a numeric column, for `--spark`.
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use crate::errors::*;
+use crate::parser::Row;
+
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The column to draw a sparkline of and how many values to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spark {
+    column: u32,
+    window: usize,
+}
+
+impl Spark {
+    pub fn new(column: u32, window: usize) -> Result<Spark> {
+        if column == 0 {
+            return Err(Error::ColumnsStartAtOne);
+        }
+        Ok(Spark { column, window })
+    }
+}
+
+impl FromStr for Spark {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Spark> {
+        let err = || Error::SparkParseError { s: s.to_string() };
+        let (column, window) = match s.split_once(':') {
+            Some((c, w)) => (c, w.parse().map_err(|_| err())?),
+            None => (s, 8),
+        };
+        if window == 0 {
+            return Err(err());
+        }
+        Spark::new(column.parse().map_err(|_| err())?, window)
+    }
+}
+
+/// The values seen so far in the column of a `Spark`.
+#[derive(Debug, Clone)]
+pub(crate) struct SparkHistory {
+    spark: Spark,
+    values: VecDeque<f64>,
+}
+
+impl SparkHistory {
+    pub fn new(spark: Spark) -> SparkHistory {
+        SparkHistory {
+            spark,
+            values: VecDeque::with_capacity(spark.window),
+        }
+    }
+
+    /// Records the value of the column in `row`, if it is a number, and
+    /// appends the sparkline of the recent values to the row.
+    pub fn apply(&mut self, row: &mut Row) {
+        let i = self.spark.column as usize - 1;
+        let value = (i < row.len())
+            .then(|| std::str::from_utf8(&row[i]).ok()?.trim().parse::<f64>().ok())
+            .flatten()
+            .filter(|v| v.is_finite());
+        if let Some(v) = value {
+            if self.values.len() == self.spark.window {
+                self.values.pop_front();
+            }
+            self.values.push_back(v);
+        }
+        row.push(self.sparkline().as_bytes());
+    }
+
+    fn sparkline(&self) -> String {
+        let min = self.values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = self.values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        self.values
+            .iter()
+            .map(|&v| {
+                let level = if max > min {
+                    ((v - min) / (max - min) * (BLOCKS.len() - 1) as f64).round() as usize
+                } else {
+                    0
+                };
+                BLOCKS[level.min(BLOCKS.len() - 1)]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::RowParser;
+
+    #[test]
+    fn sparkline() {
+        let parser = RowParser::new(" ", false);
+        let mut history = SparkHistory::new("2:3".parse().unwrap());
+        let mut lines = Vec::new();
+        for line in &["a 1", "b 8", "c x", "d 4.5", "e 15"] {
+            let mut row = Row::new();
+            parser.parse_into(&mut row, *line);
+            history.apply(&mut row);
+            lines.push(String::from_utf8(row[2].to_vec()).unwrap());
+        }
+        assert_eq!(lines, ["▁", "▁█", "▁█", "▁█▅", "▃▁█"]);
+    }
+
+    #[test]
+    fn parse_spark() {
+        assert_eq!("3".parse::<Spark>().unwrap(), Spark::new(3, 8).unwrap());
+        assert_eq!("3:20".parse::<Spark>().unwrap(), Spark::new(3, 20).unwrap());
+        assert!("0".parse::<Spark>().is_err());
+        assert!("3:0".parse::<Spark>().is_err());
+        assert!("x".parse::<Spark>().is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::io::{self, BufRead, Write};
+
 pub struct FirstLastIter<I, T> {
     inner: I,
     next: Option<T>,
@@ -31,3 +33,86 @@ where
         }
     }
 }
+
+pub struct ByteLines<R> {
+    inner: R,
+}
+
+/// Like `BufRead::lines` but yields the raw bytes of each line, so input does
+/// not have to be valid UTF-8. Both `\n` and `\r\n` line endings are stripped.
+pub fn byte_lines<R: BufRead>(inner: R) -> ByteLines<R> {
+    ByteLines { inner }
+}
+
+impl<R: BufRead> Iterator for ByteLines<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = Vec::new();
+        match self.inner.read_until(b'\n', &mut buf) {
+            Ok(0) => None,
+            Ok(_) => {
+                if buf.last() == Some(&b'\n') {
+                    buf.pop();
+                    if buf.last() == Some(&b'\r') {
+                        buf.pop();
+                    }
+                }
+                Some(Ok(buf))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// The longest prefix of `s` that is at most `n` bytes and does not end in
+/// the middle of a UTF-8 encoded character.
+pub fn truncate_utf8(s: &[u8], n: usize) -> &[u8] {
+    if n >= s.len() {
+        return s;
+    }
+    let end = (0..=n).rev().find(|&i| s[i] & 0xc0 != 0x80).unwrap_or(0);
+    &s[..end]
+}
+
+/// Writes `s` as a JSON string. Invalid UTF-8 is replaced with U+FFFD.
+pub fn write_json_string<W: Write + ?Sized>(out: &mut W, s: &[u8]) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in String::from_utf8_lossy(s).chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\t' => out.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+/// `s` without trailing spaces.
+pub fn trim_end(s: &[u8]) -> &[u8] {
+    let end = s.iter().rposition(|&b| b != b' ').map(|i| i + 1).unwrap_or(0);
+    &s[..end]
+}
+
+/// The 64-bit FNV-1a hash of the bytes written to it.
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
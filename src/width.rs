@@ -0,0 +1,315 @@
+//! The width that text takes up on a terminal.
+
+use crate::utils::truncate_utf8;
+
+/// Characters that take up no space of their own, such as combining marks,
+/// zero width spaces and variation selectors.
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036f),
+    (0x0483, 0x0489),
+    (0x0591, 0x05bd),
+    (0x05bf, 0x05bf),
+    (0x05c1, 0x05c2),
+    (0x05c4, 0x05c5),
+    (0x05c7, 0x05c7),
+    (0x0610, 0x061a),
+    (0x064b, 0x065f),
+    (0x0670, 0x0670),
+    (0x06d6, 0x06dc),
+    (0x06df, 0x06e4),
+    (0x06e7, 0x06e8),
+    (0x06ea, 0x06ed),
+    (0x0711, 0x0711),
+    (0x0730, 0x074a),
+    (0x07a6, 0x07b0),
+    (0x0900, 0x0902),
+    (0x093a, 0x093a),
+    (0x093c, 0x093c),
+    (0x0941, 0x0948),
+    (0x094d, 0x094d),
+    (0x0951, 0x0957),
+    (0x0e31, 0x0e31),
+    (0x0e34, 0x0e3a),
+    (0x0e47, 0x0e4e),
+    (0x1160, 0x11ff),
+    (0x1ab0, 0x1aff),
+    (0x1dc0, 0x1dff),
+    (0x200b, 0x200f),
+    (0x202a, 0x202e),
+    (0x2060, 0x2064),
+    (0x20d0, 0x20ff),
+    (0xfe00, 0xfe0f),
+    (0xfe20, 0xfe2f),
+    (0xfeff, 0xfeff),
+    (0xe0020, 0xe007f),
+    (0xe0100, 0xe01ef),
+];
+
+/// Characters that take up two columns: East Asian wide and fullwidth
+/// characters, and emoji that are displayed as such by default.
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115f),
+    (0x231a, 0x231b),
+    (0x2329, 0x232a),
+    (0x23e9, 0x23ec),
+    (0x23f0, 0x23f0),
+    (0x23f3, 0x23f3),
+    (0x25fd, 0x25fe),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267f, 0x267f),
+    (0x2693, 0x2693),
+    (0x26a1, 0x26a1),
+    (0x26aa, 0x26ab),
+    (0x26bd, 0x26be),
+    (0x26c4, 0x26c5),
+    (0x26ce, 0x26ce),
+    (0x26d4, 0x26d4),
+    (0x26ea, 0x26ea),
+    (0x26f2, 0x26f3),
+    (0x26f5, 0x26f5),
+    (0x26fa, 0x26fa),
+    (0x26fd, 0x26fd),
+    (0x2705, 0x2705),
+    (0x270a, 0x270b),
+    (0x2728, 0x2728),
+    (0x274c, 0x274c),
+    (0x274e, 0x274e),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27b0, 0x27b0),
+    (0x27bf, 0x27bf),
+    (0x2b1b, 0x2b1c),
+    (0x2b50, 0x2b50),
+    (0x2b55, 0x2b55),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xa960, 0xa97f),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe10, 0xfe19),
+    (0xfe30, 0xfe6f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x16fe0, 0x16fe4),
+    (0x17000, 0x18cff),
+    (0x1b000, 0x1b2ff),
+    (0x1f004, 0x1f004),
+    (0x1f0cf, 0x1f0cf),
+    (0x1f18e, 0x1f18e),
+    (0x1f191, 0x1f19a),
+    (0x1f200, 0x1f202),
+    (0x1f210, 0x1f23b),
+    (0x1f240, 0x1f248),
+    (0x1f250, 0x1f251),
+    (0x1f260, 0x1f265),
+    (0x1f300, 0x1f320),
+    (0x1f32d, 0x1f335),
+    (0x1f337, 0x1f37c),
+    (0x1f37e, 0x1f393),
+    (0x1f3a0, 0x1f3ca),
+    (0x1f3cf, 0x1f3d3),
+    (0x1f3e0, 0x1f3f0),
+    (0x1f3f4, 0x1f3f4),
+    (0x1f3f8, 0x1f43e),
+    (0x1f440, 0x1f440),
+    (0x1f442, 0x1f4fc),
+    (0x1f4ff, 0x1f53d),
+    (0x1f54b, 0x1f54e),
+    (0x1f550, 0x1f567),
+    (0x1f57a, 0x1f57a),
+    (0x1f595, 0x1f596),
+    (0x1f5a4, 0x1f5a4),
+    (0x1f5fb, 0x1f64f),
+    (0x1f680, 0x1f6c5),
+    (0x1f6cc, 0x1f6cc),
+    (0x1f6d0, 0x1f6d2),
+    (0x1f6d5, 0x1f6d7),
+    (0x1f6eb, 0x1f6ec),
+    (0x1f6f4, 0x1f6fc),
+    (0x1f7e0, 0x1f7eb),
+    (0x1f90c, 0x1f93a),
+    (0x1f93c, 0x1f945),
+    (0x1f947, 0x1f9ff),
+    (0x1fa70, 0x1faff),
+    (0x20000, 0x2fffd),
+    (0x30000, 0x3fffd),
+];
+
+fn in_table(c: u32, table: &[(u32, u32)]) -> bool {
+    table
+        .binary_search_by(|&(start, end)| {
+            if end < c {
+                std::cmp::Ordering::Less
+            } else if start > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+fn char_width(c: char) -> usize {
+    let c = c as u32;
+    if c < 0x300 {
+        1
+    } else if in_table(c, ZERO_WIDTH) {
+        0
+    } else if in_table(c, WIDE) {
+        2
+    } else {
+        1
+    }
+}
+
+/// The number of columns `s` takes up on a terminal. Bytes that are not valid
+/// UTF-8 take up one column each.
+pub fn display_width(s: &[u8]) -> usize {
+    if s.is_ascii() {
+        return s.len();
+    }
+    s.utf8_chunks()
+        .map(|chunk| chunk.valid().chars().map(char_width).sum::<usize>() + chunk.invalid().len())
+        .sum()
+}
+
+/// The length of the ANSI control sequence, such as `\x1b[1;31m`, that `s`
+/// starts with, if it starts with one.
+fn escape_len(s: &[u8]) -> Option<usize> {
+    let rest = s.strip_prefix(b"\x1b[")?;
+    let params = rest.iter().take_while(|&&b| (0x20..0x40).contains(&b)).count();
+    match rest.get(params) {
+        Some(0x40..=0x7e) => Some(2 + params + 1),
+        _ => None,
+    }
+}
+
+/// Splits `s` into runs of text and the ANSI control sequences between them,
+/// with whether each part is a control sequence.
+fn split_escapes(s: &[u8]) -> impl Iterator<Item = (&[u8], bool)> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let (part, is_escape) = match escape_len(rest) {
+            Some(n) => (&rest[..n], true),
+            None => {
+                let n = rest[1..].iter().position(|&b| b == 0x1b).map_or(rest.len(), |i| i + 1);
+                (&rest[..n], false)
+            }
+        };
+        rest = &rest[part.len()..];
+        Some((part, is_escape))
+    })
+}
+
+/// Whether `s` has nothing but whitespace and ANSI control sequences.
+pub fn is_blank(s: &[u8]) -> bool {
+    split_escapes(s).all(|(part, is_escape)| is_escape || part.iter().all(u8::is_ascii_whitespace))
+}
+
+/// How values are measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Widths {
+    /// Measure by length in bytes instead of display width.
+    pub bytes: bool,
+    /// ANSI control sequences, such as colors, take up no space.
+    pub ansi: bool,
+}
+
+impl Widths {
+    pub fn width(&self, s: &[u8]) -> usize {
+        let measure = |s: &[u8]| if self.bytes { s.len() } else { display_width(s) };
+        if self.ansi && s.contains(&0x1b) {
+            split_escapes(s).filter(|p| !p.1).map(|p| measure(p.0)).sum()
+        } else {
+            measure(s)
+        }
+    }
+
+    /// The longest prefix of `s` that is at most `max` wide and does not end
+    /// in the middle of a character, with its width. Control sequences are
+    /// kept up to where the text is cut.
+    pub fn truncate<'s>(&self, s: &'s [u8], max: usize) -> (&'s [u8], usize) {
+        if !self.ansi || !s.contains(&0x1b) {
+            return truncate(s, max, self.bytes);
+        }
+        let (mut end, mut width) = (0, 0);
+        for (part, is_escape) in split_escapes(s) {
+            if !is_escape {
+                let (prefix, w) = truncate(part, max - width, self.bytes);
+                width += w;
+                if prefix.len() < part.len() {
+                    return (&s[..end + prefix.len()], width);
+                }
+            }
+            end += part.len();
+        }
+        (s, width)
+    }
+}
+
+/// `Widths::truncate` for text without control sequences.
+fn truncate(s: &[u8], max: usize, bytes: bool) -> (&[u8], usize) {
+    if bytes || s.is_ascii() {
+        let prefix = truncate_utf8(s, max);
+        return (prefix, prefix.len());
+    }
+    let (mut end, mut width) = (0, 0);
+    for chunk in s.utf8_chunks() {
+        let widths = chunk.valid().chars().map(|c| (c.len_utf8(), char_width(c)));
+        for (len, w) in widths.chain(chunk.invalid().iter().map(|_| (1, 1))) {
+            if width + w > max {
+                return (&s[..end], width);
+            }
+            end += len;
+            width += w;
+        }
+    }
+    (s, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(display_width(b"abc"), 3);
+        assert_eq!(display_width("blåbær".as_bytes()), 6);
+        assert_eq!(display_width("e\u{301}".as_bytes()), 1);
+        assert_eq!(display_width("日本語".as_bytes()), 6);
+        assert_eq!(display_width("ok 👍".as_bytes()), 5);
+        assert_eq!(display_width("▁▂▃".as_bytes()), 3);
+        assert_eq!(display_width(b"a\xffb"), 3);
+        assert_eq!(Widths { bytes: true, ansi: false }.width("日本".as_bytes()), 6);
+    }
+
+    #[test]
+    fn ansi_escapes() {
+        let ansi = Widths { bytes: false, ansi: true };
+        let red = b"\x1b[01;31mred\x1b[0m";
+        assert_eq!(ansi.width(red), 3);
+        assert_eq!(Widths::default().width(red), 15);
+        assert_eq!(ansi.width(b"\x1b[Kx\x1bz"), 3);
+        assert_eq!(ansi.truncate(red, 2), (&b"\x1b[01;31mre"[..], 2));
+        assert_eq!(ansi.truncate(red, 3), (&red[..], 3));
+
+        assert!(is_blank(b"\x1b[2K\x1b[1G \r"));
+        assert!(!is_blank(b"\x1b[2K|"));
+    }
+
+    #[test]
+    fn truncate_to_width() {
+        assert_eq!(truncate("日本語".as_bytes(), 5, false), ("日本".as_bytes(), 4));
+        assert_eq!(truncate("e\u{301}x".as_bytes(), 1, false), ("e\u{301}".as_bytes(), 1));
+        assert_eq!(truncate(b"abc", 2, false), (&b"ab"[..], 2));
+        assert_eq!(truncate("日本".as_bytes(), 4, true), ("日".as_bytes(), 3));
+    }
+}